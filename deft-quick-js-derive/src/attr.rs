@@ -0,0 +1,343 @@
+use syn::ext::IdentExt;
+use syn::{Attribute, Field, Ident, LitStr, Path, Result, Variant};
+
+/// Case conversion for `#[js(rename_all = "...")]`.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy)]
+pub enum RenameRule {
+    LowerCase,
+    UpperCase,
+    PascalCase,
+    CamelCase,
+    SnakeCase,
+    ScreamingSnakeCase,
+    KebabCase,
+    ScreamingKebabCase,
+}
+
+impl RenameRule {
+    fn parse(lit: &LitStr) -> Result<Self> {
+        Ok(match lit.value().as_str() {
+            "lowercase" => RenameRule::LowerCase,
+            "UPPERCASE" => RenameRule::UpperCase,
+            "PascalCase" => RenameRule::PascalCase,
+            "camelCase" => RenameRule::CamelCase,
+            "snake_case" => RenameRule::SnakeCase,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnakeCase,
+            "kebab-case" => RenameRule::KebabCase,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebabCase,
+            other => {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!("unknown rename rule `{}`", other),
+                ))
+            }
+        })
+    }
+
+    /// Apply to a `PascalCase` variant name.
+    pub fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::PascalCase => variant.to_owned(),
+            RenameRule::LowerCase => variant.to_ascii_lowercase(),
+            RenameRule::UpperCase => variant.to_ascii_uppercase(),
+            RenameRule::CamelCase => variant[..1].to_ascii_lowercase() + &variant[1..],
+            RenameRule::SnakeCase => {
+                let mut snake = String::new();
+                for (i, ch) in variant.char_indices() {
+                    if i > 0 && ch.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(ch.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnakeCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::KebabCase => RenameRule::SnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebabCase => RenameRule::ScreamingSnakeCase
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    /// Apply to a `snake_case` field name.
+    pub fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::LowerCase | RenameRule::SnakeCase => field.to_owned(),
+            RenameRule::UpperCase | RenameRule::ScreamingSnakeCase => field.to_ascii_uppercase(),
+            RenameRule::PascalCase => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for ch in field.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(ch);
+                    }
+                }
+                pascal
+            }
+            RenameRule::CamelCase => {
+                let pascal = RenameRule::PascalCase.apply_to_field(field);
+                pascal[..1].to_ascii_lowercase() + &pascal[1..]
+            }
+            RenameRule::KebabCase => field.replace('_', "-"),
+            RenameRule::ScreamingKebabCase => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+/// `#[js(...)]` attributes of a struct or enum.
+#[derive(Default)]
+pub struct ContainerAttrs {
+    pub rename_all: Option<RenameRule>,
+    pub tag: Option<String>,
+    pub untagged: bool,
+}
+
+impl ContainerAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename_all") {
+                    out.rename_all = Some(RenameRule::parse(&meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("tag") {
+                    out.tag = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("untagged") {
+                    out.untagged = true;
+                } else {
+                    return Err(meta.error("unknown js container attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// `#[js(...)]` attributes of an enum variant.
+#[derive(Default)]
+pub struct VariantAttrs {
+    pub rename: Option<String>,
+    pub rename_all: Option<RenameRule>,
+}
+
+impl VariantAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    out.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("rename_all") {
+                    out.rename_all = Some(RenameRule::parse(&meta.value()?.parse()?)?);
+                } else {
+                    return Err(meta.error("unknown js variant attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(out)
+    }
+
+    /// The Javascript name of the variant.
+    pub fn name(&self, variant: &Variant, rule: Option<RenameRule>) -> String {
+        if let Some(name) = &self.rename {
+            return name.clone();
+        }
+        let name = variant.ident.unraw().to_string();
+        match rule {
+            Some(rule) => rule.apply_to_variant(&name),
+            None => name,
+        }
+    }
+}
+
+/// How a missing field is filled in.
+pub enum FieldDefault {
+    Trait,
+    Path(Path),
+}
+
+/// `#[js(...)]` attributes of a field.
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub rename: Option<String>,
+    pub default: Option<FieldDefault>,
+    pub skip: bool,
+}
+
+impl FieldAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    out.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("default") {
+                    out.default = if meta.input.peek(syn::Token![=]) {
+                        let path = meta.value()?.parse::<LitStr>()?.parse()?;
+                        Some(FieldDefault::Path(path))
+                    } else {
+                        Some(FieldDefault::Trait)
+                    };
+                } else if meta.path.is_ident("skip") {
+                    out.skip = true;
+                } else {
+                    return Err(meta.error("unknown js field attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// A named field with its parsed attributes.
+pub struct NamedField<'a> {
+    pub ident: &'a Ident,
+    pub key: String,
+    pub attrs: FieldAttrs,
+    pub is_option: bool,
+}
+
+impl<'a> NamedField<'a> {
+    pub fn parse(field: &'a Field, rule: Option<RenameRule>) -> Result<Self> {
+        let ident = field.ident.as_ref().expect("named field");
+        let attrs = FieldAttrs::parse(&field.attrs)?;
+        let key = match &attrs.rename {
+            Some(name) => name.clone(),
+            None => {
+                let name = ident.unraw().to_string();
+                match rule {
+                    Some(rule) => rule.apply_to_field(&name),
+                    None => name,
+                }
+            }
+        };
+        Ok(Self {
+            ident,
+            key,
+            attrs,
+            is_option: is_option(&field.ty),
+        })
+    }
+}
+
+/// Whether the type is spelled as `Option<_>`.
+fn is_option(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(p) if p.qself.is_none() => p
+            .path
+            .segments
+            .last()
+            .map(|s| s.ident == "Option" && !s.arguments.is_empty())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// The kind of member a `#[js_methods]` method defines.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MethodKind {
+    Method,
+    Constructor,
+    Getter,
+    Setter,
+}
+
+/// `#[js(...)]` attributes of a method in `#[js_methods]`.
+pub struct MethodAttrs {
+    pub kind: MethodKind,
+    pub rename: Option<String>,
+    pub skip: bool,
+}
+
+impl MethodAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut out = Self {
+            kind: MethodKind::Method,
+            rename: None,
+            skip: false,
+        };
+        for attr in attrs.iter().filter(|a| a.path().is_ident("js")) {
+            attr.parse_nested_meta(|meta| {
+                let kind = if meta.path.is_ident("constructor") {
+                    MethodKind::Constructor
+                } else if meta.path.is_ident("get") {
+                    MethodKind::Getter
+                } else if meta.path.is_ident("set") {
+                    MethodKind::Setter
+                } else if meta.path.is_ident("rename") {
+                    out.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                    return Ok(());
+                } else if meta.path.is_ident("skip") {
+                    out.skip = true;
+                    return Ok(());
+                } else {
+                    return Err(meta.error("unknown js method attribute"));
+                };
+                if out.kind != MethodKind::Method {
+                    return Err(meta.error("a method can only be one of constructor, get or set"));
+                }
+                out.kind = kind;
+                Ok(())
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// The arguments of `#[js_function(...)]`, `#[js_class(...)]`,
+/// `#[js_methods(...)]` and `#[js_module(...)]`.
+#[derive(Default)]
+pub struct MacroArgs {
+    pub name: Option<String>,
+    pub rename_all: Option<RenameRule>,
+}
+
+impl MacroArgs {
+    /// Parse the arguments, `allowed` lists the accepted keys.
+    pub fn parse(args: proc_macro2::TokenStream, allowed: &[&str]) -> Result<Self> {
+        let mut out = Self::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("name") && allowed.contains(&"name") {
+                out.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("rename_all") && allowed.contains(&"rename_all") {
+                out.rename_all = Some(RenameRule::parse(&meta.value()?.parse()?)?);
+            } else {
+                return Err(meta.error(format!("expected one of: {}", allowed.join(", "))));
+            }
+            Ok(())
+        });
+        syn::parse::Parser::parse2(parser, args)?;
+        Ok(out)
+    }
+}
+
+/// The text of the doc comments.
+pub fn doc_string(attrs: &[Attribute]) -> String {
+    let lines = attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(nv) => match &nv.value {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s), ..
+                }) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_owned).unwrap_or(line))
+        .collect::<Vec<_>>();
+    lines.join("\n").trim().to_owned()
+}
@@ -0,0 +1,175 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::ext::IdentExt;
+use syn::{FnArg, ImplItem, Item, ItemImpl, Result, Visibility};
+
+use crate::attr::{doc_string, MacroArgs, MethodAttrs, MethodKind};
+use crate::function::{callback, check_signature, declaration, return_ts_type, ts_type, Output};
+
+pub fn expand_class(args: TokenStream, item: Item) -> Result<TokenStream> {
+    let args = MacroArgs::parse(args, &["name"])?;
+    let (ident, generics, attrs) = match &item {
+        Item::Struct(item) => (&item.ident, &item.generics, &item.attrs),
+        Item::Enum(item) => (&item.ident, &item.generics, &item.attrs),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                item,
+                "#[js_class] can only be used on structs and enums",
+            ))
+        }
+    };
+    if !generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(generics, "generic classes are not supported"));
+    }
+    let js_name = args.name.unwrap_or_else(|| ident.unraw().to_string());
+    let doc = doc_string(attrs);
+
+    Ok(quote! {
+        #item
+
+        impl ::deft_quick_js::host::HostClass for #ident {
+            const NAME: &'static str = #js_name;
+            const DOC: &'static str = #doc;
+
+            fn define(
+                class: &mut ::deft_quick_js::host::ClassBuilder<Self>,
+            ) -> ::core::result::Result<(), ::deft_quick_js::ExecutionError> {
+                <Self as ::deft_quick_js::derive::ClassMethods>::define_methods(class)
+            }
+
+            fn declaration() -> ::deft_quick_js::typescript::TsClass {
+                let mut class = ::deft_quick_js::typescript::TsClass::new().doc(Self::DOC);
+                <Self as ::deft_quick_js::derive::ClassMethods>::declare_methods(&mut class);
+                class
+            }
+        }
+
+        impl ::deft_quick_js::typescript::TsType for #ident {
+            fn ts_type() -> ::std::string::String {
+                ::std::string::String::from(#js_name)
+            }
+        }
+
+        impl ::core::convert::From<#ident> for ::deft_quick_js::JsValue {
+            fn from(value: #ident) -> Self {
+                ::deft_quick_js::JsValue::new_resource(value)
+            }
+        }
+    })
+}
+
+pub fn expand_methods(args: TokenStream, mut item: ItemImpl) -> Result<TokenStream> {
+    let args = MacroArgs::parse(args, &["rename_all"])?;
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new_spanned(path, "#[js_methods] can not be used on trait impls"));
+    }
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&item.generics, "generic classes are not supported"));
+    }
+    let self_ty = &item.self_ty;
+    let class_name = self_ty.to_token_stream().to_string();
+
+    let mut members = Vec::new();
+    let mut declarations = Vec::new();
+    let mut has_constructor = false;
+    for impl_item in &mut item.items {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        let attrs = MethodAttrs::parse(&method.attrs)?;
+        // `js` is not a registered attribute of an attribute macro.
+        method.attrs.retain(|a| !a.path().is_ident("js"));
+        if attrs.skip || !matches!(method.vis, Visibility::Public(_)) {
+            continue;
+        }
+        check_signature(&method.sig)?;
+        let doc = doc_string(&method.attrs);
+
+        let ident = &method.sig.ident;
+        let has_receiver = matches!(method.sig.inputs.first(), Some(FnArg::Receiver(_)));
+        let name = match &attrs.rename {
+            Some(name) => name.clone(),
+            None => {
+                let name = ident.unraw().to_string();
+                let name = match attrs.kind {
+                    MethodKind::Setter => name.strip_prefix("set_").map(str::to_owned).unwrap_or(name),
+                    _ => name,
+                };
+                match args.rename_all {
+                    Some(rule) => rule.apply_to_field(&name),
+                    None => name,
+                }
+            }
+        };
+
+        let member = match attrs.kind {
+            MethodKind::Constructor => {
+                if has_receiver {
+                    return Err(syn::Error::new_spanned(&method.sig, "a constructor can not take `self`"));
+                }
+                if has_constructor {
+                    return Err(syn::Error::new_spanned(&method.sig, "a class can only have one constructor"));
+                }
+                has_constructor = true;
+                let declaration = declaration(&method.sig, &doc);
+                declarations.push(quote!(class.constructor = ::core::option::Option::Some(#declaration);));
+                let callback = callback(&method.sig, quote!(Self::#ident), &class_name, Output::Instance)?;
+                quote! {
+                    let __callback = #callback;
+                    class.constructor(__callback.0, __callback.1)?;
+                }
+            }
+            kind => {
+                let function = format!("{}.{}", class_name, name);
+                let callback = callback(&method.sig, quote!(Self::#ident), &function, Output::Value)?;
+                let define = match kind {
+                    MethodKind::Getter => quote!(getter),
+                    MethodKind::Setter => quote!(setter),
+                    _ if has_receiver => quote!(method),
+                    _ => quote!(static_method),
+                };
+                if kind != MethodKind::Method && !has_receiver {
+                    return Err(syn::Error::new_spanned(&method.sig, "accessors must take `&self` or `&mut self`"));
+                }
+                declarations.push(match kind {
+                    MethodKind::Getter => {
+                        let ty = return_ts_type(&method.sig.output);
+                        quote!(class.getter(#name, &#ty, #doc);)
+                    }
+                    MethodKind::Setter => {
+                        let ty = match method.sig.inputs.iter().nth(1) {
+                            Some(FnArg::Typed(arg)) => ts_type(&arg.ty),
+                            _ => quote!(::std::string::String::from("any")),
+                        };
+                        quote!(class.setter(#name, &#ty, #doc);)
+                    }
+                    _ => {
+                        let declaration = declaration(&method.sig, &doc);
+                        quote!(class.method(#name, #declaration, !#has_receiver);)
+                    }
+                });
+                quote!(class.#define(#name, #callback)?;)
+            }
+        };
+        members.push(member);
+    }
+
+    Ok(quote! {
+        #item
+
+        impl ::deft_quick_js::derive::ClassMethods for #self_ty {
+            fn define_methods(
+                class: &mut ::deft_quick_js::host::ClassBuilder<Self>,
+            ) -> ::core::result::Result<(), ::deft_quick_js::ExecutionError> {
+                #( #members )*
+                ::core::result::Result::Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn declare_methods(class: &mut ::deft_quick_js::typescript::TsClass) {
+                #( #declarations )*
+            }
+        }
+    })
+}
@@ -0,0 +1,222 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Fields, FieldsNamed, FieldsUnnamed, Result};
+
+use crate::attr::{ContainerAttrs, FieldDefault, NamedField, RenameRule, VariantAttrs};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let attrs = ContainerAttrs::parse(&input.attrs)?;
+    let name = &input.ident;
+    let type_name = name.to_string();
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let construct = named(quote!(#name), fields, attrs.rename_all)?;
+                quote! {
+                    let mut __map = ::deft_quick_js::derive::into_object(value)?;
+                    #construct
+                }
+            }
+            Fields::Unnamed(fields) => unnamed(quote!(#name), fields, &type_name),
+            Fields::Unit => quote!(::core::result::Result::Ok(#name)),
+        },
+        Data::Enum(data) => {
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let variant_attrs = VariantAttrs::parse(&variant.attrs)?;
+                let js_name = variant_attrs.name(variant, attrs.rename_all);
+                let ident = &variant.ident;
+                let path = quote!(#name::#ident);
+                // Converts `value` into the variant.
+                let construct = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let construct = named(path, fields, variant_attrs.rename_all)?;
+                        quote! {
+                            let mut __map = ::deft_quick_js::derive::into_object(value)?;
+                            #construct
+                        }
+                    }
+                    Fields::Unnamed(fields) => unnamed(path, fields, &js_name),
+                    Fields::Unit if attrs.untagged => quote! {
+                        match value {
+                            ::deft_quick_js::JsValue::Null | ::deft_quick_js::JsValue::Undefined => {
+                                ::core::result::Result::Ok(#path)
+                            }
+                            _ => ::core::result::Result::Err(::deft_quick_js::ValueError::UnexpectedType),
+                        }
+                    },
+                    Fields::Unit => quote!(::core::result::Result::Ok(#path)),
+                };
+                let is_unit = matches!(variant.fields, Fields::Unit);
+                if attrs.tag.is_some() {
+                    if let Fields::Unnamed(fields) = &variant.fields {
+                        if fields.unnamed.len() != 1 {
+                            return Err(syn::Error::new_spanned(
+                                variant,
+                                "tuple variants are not supported in internally tagged enums",
+                            ));
+                        }
+                    }
+                }
+                variants.push((js_name, is_unit, construct));
+            }
+
+            if attrs.untagged {
+                let attempts = variants.iter().map(|(_, _, construct)| {
+                    quote! {
+                        let __attempt = (|value: ::deft_quick_js::JsValue|
+                            -> ::core::result::Result<Self, ::deft_quick_js::ValueError> {
+                            #construct
+                        })(value.clone());
+                        if let ::core::result::Result::Ok(v) = __attempt {
+                            return ::core::result::Result::Ok(v);
+                        }
+                    }
+                });
+                quote! {
+                    #( #attempts )*
+                    ::core::result::Result::Err(::deft_quick_js::derive::no_variant_matched(#type_name))
+                }
+            } else if let Some(tag) = &attrs.tag {
+                let arms = variants.iter().map(|(js_name, _, construct)| {
+                    quote!(#js_name => { #construct })
+                });
+                quote! {
+                    let mut __map = ::deft_quick_js::derive::into_object(value)?;
+                    let __tag = ::deft_quick_js::derive::tag(&mut __map, #tag)?;
+                    let value = ::deft_quick_js::JsValue::Object(__map);
+                    match __tag.as_str() {
+                        #( #arms )*
+                        other => ::core::result::Result::Err(
+                            ::deft_quick_js::derive::unknown_variant(other, #type_name)
+                        ),
+                    }
+                }
+            } else {
+                let unit_arms = variants
+                    .iter()
+                    .filter(|(_, is_unit, _)| *is_unit)
+                    .map(|(js_name, _, construct)| quote!(#js_name => { #construct }));
+                let arms = variants
+                    .iter()
+                    .filter(|(_, is_unit, _)| !*is_unit)
+                    .map(|(js_name, _, construct)| quote!(#js_name => { #construct }));
+                quote! {
+                    match value {
+                        ::deft_quick_js::JsValue::String(__name) => match __name.as_str() {
+                            #( #unit_arms )*
+                            other => ::core::result::Result::Err(
+                                ::deft_quick_js::derive::unknown_variant(other, #type_name)
+                            ),
+                        },
+                        value => {
+                            let __map = ::deft_quick_js::derive::into_object(value)?;
+                            if __map.len() != 1 {
+                                return ::core::result::Result::Err(::deft_quick_js::ValueError::Internal(
+                                    format!("expected an object with a single variant of `{}`", #type_name)
+                                ));
+                            }
+                            let (__name, value) = __map.into_iter().next().unwrap();
+                            match __name.as_str() {
+                                #( #arms )*
+                                other => ::core::result::Result::Err(
+                                    ::deft_quick_js::derive::unknown_variant(other, #type_name)
+                                ),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "FromJs can not be derived for unions",
+            ))
+        }
+    };
+
+    let mut generics = input.generics.clone();
+    for param in input.generics.type_params() {
+        let ident = &param.ident;
+        let where_clause = generics.make_where_clause();
+        where_clause
+            .predicates
+            .push(parse_quote!(#ident: ::core::convert::TryFrom<::deft_quick_js::JsValue>));
+        where_clause.predicates.push(parse_quote!(
+            ::deft_quick_js::ValueError: ::core::convert::From<<#ident as ::core::convert::TryFrom<::deft_quick_js::JsValue>>::Error>
+        ));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::core::convert::TryFrom<::deft_quick_js::JsValue> for #name #ty_generics
+        #where_clause
+        {
+            type Error = ::deft_quick_js::ValueError;
+
+            #[allow(unused_mut, unreachable_code)]
+            fn try_from(value: ::deft_quick_js::JsValue) -> ::core::result::Result<Self, Self::Error> {
+                #body
+            }
+        }
+    })
+}
+
+/// Construct `path { .. }` from the properties in `__map`.
+fn named(path: TokenStream, fields: &FieldsNamed, rule: Option<RenameRule>) -> Result<TokenStream> {
+    let fields = fields
+        .named
+        .iter()
+        .map(|f| NamedField::parse(f, rule))
+        .collect::<Result<Vec<_>>>()?;
+    let inits = fields.iter().map(|field| {
+        let ident = field.ident;
+        let key = &field.key;
+        let value = if field.attrs.skip {
+            quote!(::core::default::Default::default())
+        } else {
+            match &field.attrs.default {
+                Some(FieldDefault::Trait) => quote! {
+                    ::deft_quick_js::derive::field(&mut __map, #key)?
+                        .unwrap_or_default()
+                },
+                Some(FieldDefault::Path(default)) => quote! {
+                    ::deft_quick_js::derive::field(&mut __map, #key)?
+                        .unwrap_or_else(#default)
+                },
+                None if field.is_option => quote! {
+                    ::deft_quick_js::derive::optional_field(&mut __map, #key)?
+                },
+                None => quote! {
+                    ::deft_quick_js::derive::field(&mut __map, #key)?
+                        .ok_or_else(|| ::deft_quick_js::derive::missing_field(#key))?
+                },
+            }
+        };
+        quote!(#ident: #value)
+    });
+    Ok(quote! {
+        ::core::result::Result::Ok(#path { #( #inits, )* })
+    })
+}
+
+/// Construct `path(..)` from `value`: the value itself for a single field,
+/// an array otherwise.
+fn unnamed(path: TokenStream, fields: &FieldsUnnamed, name: &str) -> TokenStream {
+    let count = fields.unnamed.len();
+    if count == 1 {
+        return quote! {
+            ::core::result::Result::Ok(#path(::deft_quick_js::derive::convert(value, #name)?))
+        };
+    }
+    let items = (0..count).map(|i| {
+        let item_name = format!("{}.{}", name, i);
+        quote!(::deft_quick_js::derive::convert(__items.next().unwrap(), #item_name)?)
+    });
+    quote! {
+        let mut __items = ::deft_quick_js::derive::into_array(value, #count)?.into_iter();
+        ::core::result::Result::Ok(#path( #( #items, )* ))
+    }
+}
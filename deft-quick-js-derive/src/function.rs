@@ -0,0 +1,275 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{FnArg, ItemFn, Pat, Result, ReturnType, Signature, Type};
+
+use crate::attr::{doc_string, MacroArgs};
+
+pub fn expand(args: TokenStream, item: ItemFn) -> Result<TokenStream> {
+    let args = MacroArgs::parse(args, &["name"])?;
+    check_signature(&item.sig)?;
+
+    let ident = &item.sig.ident;
+    let js_name = args.name.unwrap_or_else(|| ident.unraw().to_string());
+    let doc = doc_string(&item.attrs);
+    let vis = &item.vis;
+    let binding = format_ident!("js_{}", ident.unraw());
+    let binding_doc = format!("Javascript binding of [`{}`].", ident.unraw());
+    let callback = callback(&item.sig, quote!(#ident), &js_name, Output::Value)?;
+    let declaration = declaration(&item.sig, &doc);
+
+    Ok(quote! {
+        #item
+
+        #[doc = #binding_doc]
+        #[allow(non_camel_case_types)]
+        #vis struct #binding;
+
+        impl ::deft_quick_js::host::HostFunction for #binding {
+            const NAME: &'static str = #js_name;
+            const DOC: &'static str = #doc;
+
+            fn create(
+                context: &::deft_quick_js::Context,
+            ) -> ::core::result::Result<::deft_quick_js::JsValue, ::deft_quick_js::ExecutionError> {
+                context.create_callback(Self::NAME, #callback)
+            }
+
+            fn declaration() -> ::deft_quick_js::typescript::TsFunction {
+                #declaration
+            }
+        }
+    })
+}
+
+/// Reject signatures that can not be called from Javascript.
+pub fn check_signature(sig: &Signature) -> Result<()> {
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new_spanned(asyncness, "async functions are not supported"));
+    }
+    if sig.generics.type_params().next().is_some() || sig.generics.const_params().next().is_some() {
+        return Err(syn::Error::new_spanned(&sig.generics, "generic functions are not supported"));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(syn::Error::new_spanned(variadic, "variadic functions are not supported"));
+    }
+    Ok(())
+}
+
+/// How the return value of the Rust function is passed on.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Output {
+    /// Converted to a `JsValue`.
+    Value,
+    /// Returned as the value of a new class instance.
+    Instance,
+}
+
+/// Generate a callback that converts the arguments and calls `path`.
+///
+/// The callback is a `Fn(&CallContext)` closure wrapped in
+/// `ArgumentCount`, so the function gets the right `length`. `function` is
+/// the name used in error messages.
+pub fn callback(sig: &Signature, path: TokenStream, function: &str, output: Output) -> Result<TokenStream> {
+    let mut receiver = None;
+    let mut params = Vec::new();
+    let mut conversions = Vec::new();
+    let mut call_args = Vec::new();
+
+    for (index, input) in sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Receiver(r) => {
+                if r.reference.is_none() {
+                    return Err(syn::Error::new_spanned(r, "taking `self` by value is not supported"));
+                }
+                receiver = Some(r.mutability.is_some());
+            }
+            FnArg::Typed(arg) => {
+                if is_call_context(&arg.ty) {
+                    call_args.push(quote!(__call));
+                    continue;
+                }
+                let name = match &*arg.pat {
+                    Pat::Ident(pat) => pat.ident.unraw().to_string(),
+                    _ => format!("arg{}", index),
+                };
+                let binding = format_ident!("__arg{}", index);
+                let ty = &arg.ty;
+                conversions.push(quote! {
+                    let #binding: #ty = ::deft_quick_js::derive::arg(&mut __args, #function, #name)?;
+                });
+                params.push(quote!(::deft_quick_js::derive::param::<#ty, _>()));
+                call_args.push(quote!(#binding));
+            }
+        }
+    }
+
+    let (this, this_arg) = match receiver {
+        None => (quote!(), quote!()),
+        Some(mutable) => {
+            let borrow = if mutable { quote!(borrow_mut) } else { quote!(borrow) };
+            let reference = if mutable { quote!(&mut *__this) } else { quote!(&*__this) };
+            (
+                quote! {
+                    let __this = ::deft_quick_js::derive::this::<Self>(__call, #function)?;
+                    #[allow(unused_mut)]
+                    let mut __this = ::deft_quick_js::derive::#borrow(&__this, #function)?;
+                },
+                quote!(#reference,),
+            )
+        }
+    };
+    let call = quote!(#path(#this_arg #( #call_args ),*));
+    let (result_ty, result) = match output {
+        Output::Value => (quote!(::deft_quick_js::JsValue), value_result(&sig.output, call)),
+        Output::Instance => (quote!(Self), instance_result(&sig.output, call)),
+    };
+
+    Ok(quote! {
+        ::deft_quick_js::derive::ArgumentCount(
+            ::deft_quick_js::derive::length(&[ #( #params ),* ]),
+            move |__call: &::deft_quick_js::CallContext|
+                -> ::core::result::Result<#result_ty, ::std::string::String>
+            {
+                #[allow(unused_mut, unused_variables)]
+                let mut __args = ::deft_quick_js::derive::args(__call, #function, &[ #( #params ),* ])?;
+                #( #conversions )*
+                #this
+                #result
+            },
+        )
+    })
+}
+
+/// Convert the result of `call` with `IntoCallbackResult`.
+fn value_result(output: &ReturnType, call: TokenStream) -> TokenStream {
+    match output {
+        ReturnType::Default => quote! {
+            #call;
+            ::core::result::Result::Ok(::deft_quick_js::JsValue::Undefined)
+        },
+        ReturnType::Type(_, ty) if result_ok_type(ty).map(is_unit).unwrap_or(false) => quote! {
+            #call
+                .map(|()| ::deft_quick_js::JsValue::Undefined)
+                .map_err(|e| ::std::string::ToString::to_string(&e))
+        },
+        ReturnType::Type(_, ty) if is_unit(ty) => quote! {
+            #call;
+            ::core::result::Result::Ok(::deft_quick_js::JsValue::Undefined)
+        },
+        ReturnType::Type(..) => quote! {
+            ::deft_quick_js::derive::IntoCallbackResult::into_callback_res(#call)
+        },
+    }
+}
+
+/// Pass on the value of a constructor, which returns `Self` or a `Result`.
+fn instance_result(output: &ReturnType, call: TokenStream) -> TokenStream {
+    match output {
+        ReturnType::Type(_, ty) if result_ok_type(ty).is_some() => quote! {
+            #call.map_err(|e| ::std::string::ToString::to_string(&e))
+        },
+        _ => quote!(::core::result::Result::Ok(#call)),
+    }
+}
+
+/// Generate the `TsFunction` of a signature.
+///
+/// `Option<T>` parameters are optional and a `Rest<T>` parameter is a rest
+/// parameter, other types are looked up with `TsType`.
+pub fn declaration(sig: &Signature, doc: &str) -> TokenStream {
+    let mut params = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        let arg = match input {
+            FnArg::Typed(arg) if !is_call_context(&arg.ty) => arg,
+            _ => continue,
+        };
+        let name = match &*arg.pat {
+            Pat::Ident(pat) => pat.ident.unraw().to_string(),
+            _ => format!("arg{}", index),
+        };
+        params.push(if let Some(ty) = generic_argument(&arg.ty, "Option") {
+            let ty = ts_type(ty);
+            quote!(.optional(#name, &#ty))
+        } else if let Some(ty) = generic_argument(&arg.ty, "Rest") {
+            let ty = ts_type(ty);
+            quote!(.rest(#name, &#ty))
+        } else {
+            let ty = ts_type(&arg.ty);
+            quote!(.param(#name, &#ty))
+        });
+    }
+    let returns = return_ts_type(&sig.output);
+    quote! {
+        ::deft_quick_js::typescript::TsFunction::new()
+            #( #params )*
+            .returns(&#returns)
+            .doc(#doc)
+    }
+}
+
+/// Generate the TypeScript type of the value a function returns.
+pub fn return_ts_type(output: &ReturnType) -> TokenStream {
+    match output {
+        ReturnType::Default => quote!(::std::string::String::from("void")),
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(ty) if is_unit(ty) => quote!(::std::string::String::from("void")),
+            Some(ty) => ts_type(ty),
+            None if is_unit(ty) => quote!(::std::string::String::from("void")),
+            None => ts_type(ty),
+        },
+    }
+}
+
+/// Generate the TypeScript type of `ty`, which is `any` if `ty` does not
+/// implement `TsType`.
+pub fn ts_type(ty: &Type) -> TokenStream {
+    quote! {{
+        #[allow(unused_imports)]
+        use ::deft_quick_js::derive::{KnownTsType as _, UnknownTsType as _};
+        (&::deft_quick_js::derive::TsTypeOf::<#ty>::new()).ts_type()
+    }}
+}
+
+/// Whether the type is spelled as `&CallContext`.
+fn is_call_context(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(r) => match &*r.elem {
+            Type::Path(p) => p
+                .path
+                .segments
+                .last()
+                .map(|s| s.ident == "CallContext")
+                .unwrap_or(false),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(t) if t.elems.is_empty())
+}
+
+/// The `T` of a type spelled as `Result<T, ..>`.
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    generic_argument(ty, "Result")
+}
+
+/// The first type argument of a type spelled as `name<T, ..>`.
+fn generic_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let segment = match ty {
+        Type::Path(p) if p.qself.is_none() => p.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != name {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
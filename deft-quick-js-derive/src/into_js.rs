@@ -0,0 +1,202 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{parse_quote, Data, DeriveInput, Fields, Ident, Result};
+
+use crate::attr::{ContainerAttrs, NamedField, VariantAttrs};
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let attrs = ContainerAttrs::parse(&input.attrs)?;
+    let name = &input.ident;
+
+    let body = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => {
+                let fields = fields
+                    .named
+                    .iter()
+                    .map(|f| NamedField::parse(f, attrs.rename_all))
+                    .collect::<Result<Vec<_>>>()?;
+                let bindings = fields.iter().map(|f| f.ident.clone()).collect::<Vec<_>>();
+                let object = object(&fields, &bindings, None);
+                quote! {
+                    let #name { #( #bindings, )* .. } = value;
+                    #object
+                }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote! {
+                ::core::convert::Into::into(value.0)
+            },
+            Fields::Unnamed(fields) => {
+                let indices = (0..fields.unnamed.len()).map(syn::Index::from);
+                quote! {
+                    ::deft_quick_js::JsValue::Array(vec![
+                        #( ::core::convert::Into::into(value.#indices), )*
+                    ])
+                }
+            }
+            Fields::Unit => quote!(::deft_quick_js::JsValue::Null),
+        },
+        Data::Enum(data) => {
+            let arms = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_attrs = VariantAttrs::parse(&variant.attrs)?;
+                    let js_name = variant_attrs.name(variant, attrs.rename_all);
+                    let ident = &variant.ident;
+                    match &variant.fields {
+                        Fields::Named(fields) => {
+                            let fields = fields
+                                .named
+                                .iter()
+                                .map(|f| NamedField::parse(f, variant_attrs.rename_all))
+                                .collect::<Result<Vec<_>>>()?;
+                            let idents = fields.iter().map(|f| f.ident);
+                            let bindings = binding_idents(fields.len());
+                            let value = if let Some(tag) = &attrs.tag {
+                                object(&fields, &bindings, Some((tag, &js_name)))
+                            } else {
+                                let object = object(&fields, &bindings, None);
+                                tagged(&attrs, &js_name, object)
+                            };
+                            Ok(quote! {
+                                #name::#ident { #( #idents: #bindings, )* } => { #value }
+                            })
+                        }
+                        Fields::Unnamed(fields) => {
+                            let bindings = binding_idents(fields.unnamed.len());
+                            let value = if bindings.len() == 1 {
+                                quote!(::core::convert::Into::into(__f0))
+                            } else {
+                                quote! {
+                                    ::deft_quick_js::JsValue::Array(vec![
+                                        #( ::core::convert::Into::into(#bindings), )*
+                                    ])
+                                }
+                            };
+                            let value = match &attrs.tag {
+                                Some(tag) if bindings.len() == 1 => quote! {
+                                    let mut __map = ::deft_quick_js::derive::into_object(#value)
+                                        .unwrap_or_default();
+                                    __map.insert(
+                                        #tag.to_string(),
+                                        ::deft_quick_js::JsValue::String(#js_name.to_string()),
+                                    );
+                                    ::deft_quick_js::JsValue::Object(__map)
+                                },
+                                Some(_) => {
+                                    return Err(syn::Error::new_spanned(
+                                        variant,
+                                        "tuple variants are not supported in internally tagged enums",
+                                    ))
+                                }
+                                None => tagged(&attrs, &js_name, value),
+                            };
+                            Ok(quote! {
+                                #name::#ident( #( #bindings, )* ) => { #value }
+                            })
+                        }
+                        Fields::Unit => {
+                            let value = match &attrs.tag {
+                                Some(tag) => quote! {
+                                    let mut __map = ::deft_quick_js::derive::Map::new();
+                                    __map.insert(
+                                        #tag.to_string(),
+                                        ::deft_quick_js::JsValue::String(#js_name.to_string()),
+                                    );
+                                    ::deft_quick_js::JsValue::Object(__map)
+                                },
+                                None if attrs.untagged => quote!(::deft_quick_js::JsValue::Null),
+                                None => quote! {
+                                    ::deft_quick_js::JsValue::String(#js_name.to_string())
+                                },
+                            };
+                            Ok(quote!(#name::#ident => { #value }))
+                        }
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote! {
+                match value {
+                    #( #arms )*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "IntoJs can not be derived for unions",
+            ))
+        }
+    };
+
+    let mut generics = input.generics.clone();
+    for param in input.generics.type_params() {
+        let ident = &param.ident;
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#ident: ::core::convert::Into<::deft_quick_js::JsValue>));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::core::convert::From<#name #ty_generics> for ::deft_quick_js::JsValue
+        #where_clause
+        {
+            #[allow(unused_variables)]
+            fn from(value: #name #ty_generics) -> Self {
+                #body
+            }
+        }
+    })
+}
+
+fn binding_idents(count: usize) -> Vec<Ident> {
+    (0..count).map(|i| format_ident!("__f{}", i)).collect()
+}
+
+/// Build an object from the named fields, whose values are bound to
+/// `bindings`, optionally adding a `tag` property.
+fn object(fields: &[NamedField], bindings: &[Ident], tag: Option<(&String, &String)>) -> TokenStream {
+    let inserts = fields.iter().zip(bindings).map(|(field, binding)| {
+        let key = &field.key;
+        if field.attrs.skip {
+            quote!()
+        } else if field.is_option {
+            quote! {
+                if let ::core::option::Option::Some(v) = #binding {
+                    __map.insert(#key.to_string(), ::core::convert::Into::into(v));
+                }
+            }
+        } else {
+            quote! {
+                __map.insert(#key.to_string(), ::core::convert::Into::into(#binding));
+            }
+        }
+    });
+    let tag = tag.map(|(tag, name)| {
+        quote! {
+            __map.insert(#tag.to_string(), ::deft_quick_js::JsValue::String(#name.to_string()));
+        }
+    });
+    quote! {
+        let mut __map = ::deft_quick_js::derive::Map::new();
+        #tag
+        #( #inserts )*
+        ::deft_quick_js::JsValue::Object(__map)
+    }
+}
+
+/// Wrap the value of a variant according to the enum representation.
+fn tagged(attrs: &ContainerAttrs, js_name: &str, value: TokenStream) -> TokenStream {
+    if attrs.untagged {
+        return value;
+    }
+    quote! {
+        let __value: ::deft_quick_js::JsValue = { #value };
+        let mut __outer = ::deft_quick_js::derive::Map::new();
+        __outer.insert(#js_name.to_string(), __value);
+        ::deft_quick_js::JsValue::Object(__outer)
+    }
+}
@@ -0,0 +1,161 @@
+//! Derive and attribute macros for [deft-quick-js](https://docs.rs/deft-quick-js).
+//!
+//! Use them through the `derive` feature of `deft-quick-js`:
+//!
+//! ```ignore
+//! use deft_quick_js::{FromJs, IntoJs};
+//!
+//! #[derive(IntoJs, FromJs)]
+//! #[js(rename_all = "camelCase")]
+//! struct User {
+//!     user_name: String,
+//!     #[js(default)]
+//!     age: i32,
+//!     email: Option<String>,
+//! }
+//! ```
+//!
+//! `IntoJs` implements `From<T> for JsValue` and `FromJs` implements
+//! `TryFrom<JsValue> for T`, so the types can be used as callback arguments
+//! and return values and with `Context::eval_as`.
+//!
+//! Supported attributes:
+//!
+//! * container: `#[js(rename_all = "...")]`, `#[js(tag = "...")]` and
+//!   `#[js(untagged)]`
+//! * variant: `#[js(rename = "...")]` and `#[js(rename_all = "...")]`
+//! * field: `#[js(rename = "...")]`, `#[js(default)]`,
+//!   `#[js(default = "path")]` and `#[js(skip)]`
+//!
+//! Enums are externally tagged by default: unit variants become strings,
+//! other variants an object with the variant name as the only key.
+//!
+//! The attribute macros expose Rust functions and types to Javascript:
+//!
+//! ```ignore
+//! use deft_quick_js::{js_class, js_function, js_methods, js_module};
+//!
+//! #[js_module]
+//! mod geometry {
+//!     use super::*;
+//!
+//!     /// Add two numbers.
+//!     #[js_function]
+//!     pub fn add(a: f64, b: f64) -> f64 {
+//!         a + b
+//!     }
+//!
+//!     #[js_class]
+//!     pub struct Point {
+//!         x: f64,
+//!         y: f64,
+//!     }
+//!
+//!     #[js_methods(rename_all = "camelCase")]
+//!     impl Point {
+//!         #[js(constructor)]
+//!         pub fn new(x: f64, y: f64) -> Self {
+//!             Point { x, y }
+//!         }
+//!
+//!         #[js(get)]
+//!         pub fn x(&self) -> f64 {
+//!             self.x
+//!         }
+//!
+//!         pub fn move_by(&mut self, dx: f64, dy: f64) {
+//!             self.x += dx;
+//!             self.y += dy;
+//!         }
+//!     }
+//! }
+//!
+//! geometry::register(&context)?;
+//! ```
+
+extern crate proc_macro;
+
+mod attr;
+mod class;
+mod from_js;
+mod function;
+mod into_js;
+mod module;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, Item, ItemFn, ItemImpl, ItemMod};
+
+#[proc_macro_derive(IntoJs, attributes(js))]
+pub fn derive_into_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    into_js::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(FromJs, attributes(js))]
+pub fn derive_from_js(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_js::expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Expose a function to Javascript.
+///
+/// Generates a `js_<name>` type that implements `HostFunction`. The
+/// arguments are converted like the arguments of `Context::add_callback`,
+/// a `&CallContext` parameter gets the context of the call.
+///
+/// `#[js_function(name = "...")]` sets the Javascript name.
+#[proc_macro_attribute]
+pub fn js_function(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemFn);
+    function::expand(args.into(), input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Expose a struct or enum to Javascript as a class.
+///
+/// Implements `HostClass` and `From<T> for JsValue`, the members are
+/// defined by `#[js_methods]`. `#[js_class(name = "...")]` sets the
+/// Javascript name.
+#[proc_macro_attribute]
+pub fn js_class(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as Item);
+    class::expand_class(args.into(), input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Define the members of a `#[js_class]`.
+///
+/// Every `pub` method is exposed: methods taking `self` by reference on the
+/// prototype, the others as static methods. Supported attributes:
+///
+/// * `#[js(constructor)]`, `#[js(get)]` and `#[js(set)]`, setters drop a
+///   `set_` prefix from their name
+/// * `#[js(rename = "...")]` and `#[js(skip)]`
+///
+/// `#[js_methods(rename_all = "...")]` renames all members.
+#[proc_macro_attribute]
+pub fn js_methods(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemImpl);
+    class::expand_methods(args.into(), input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Collect the `#[js_function]`s and `#[js_class]`es of an inline module.
+///
+/// Adds `module()`, `register(&Context)`, which installs everything as
+/// globals, and `register_module(&Context)`, which defines a native ES
+/// module. `#[js_module(name = "...")]` sets the module name.
+#[proc_macro_attribute]
+pub fn js_module(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemMod);
+    module::expand(args.into(), input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
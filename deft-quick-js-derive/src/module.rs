@@ -0,0 +1,75 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Attribute, Item, ItemMod, Result};
+
+use crate::attr::MacroArgs;
+
+pub fn expand(args: TokenStream, mut item: ItemMod) -> Result<TokenStream> {
+    let args = MacroArgs::parse(args, &["name"])?;
+    let module_name = args.name.unwrap_or_else(|| item.ident.unraw().to_string());
+    let content = match &mut item.content {
+        Some((_, content)) => content,
+        None => {
+            return Err(syn::Error::new_spanned(
+                &item,
+                "#[js_module] can only be used on inline modules",
+            ))
+        }
+    };
+
+    let mut registrations = Vec::new();
+    for item in content.iter() {
+        match item {
+            Item::Fn(f) if has_attr(&f.attrs, "js_function") => {
+                let binding = format_ident!("js_{}", f.sig.ident.unraw());
+                registrations.push(quote!(.function::<#binding>()));
+            }
+            Item::Struct(s) if has_attr(&s.attrs, "js_class") => {
+                let ident = &s.ident;
+                registrations.push(quote!(.class::<#ident>()));
+            }
+            Item::Enum(e) if has_attr(&e.attrs, "js_class") => {
+                let ident = &e.ident;
+                registrations.push(quote!(.class::<#ident>()));
+            }
+            _ => {}
+        }
+    }
+
+    content.push(syn::parse_quote! {
+        /// The Javascript name of this module.
+        pub const MODULE_NAME: &str = #module_name;
+    });
+    content.push(syn::parse_quote! {
+        /// The functions and classes of this module.
+        pub fn module() -> ::deft_quick_js::host::HostModule {
+            ::deft_quick_js::host::HostModule::new() #( #registrations )*
+        }
+    });
+    content.push(syn::parse_quote! {
+        /// Install the functions and classes of this module as globals.
+        pub fn register(
+            context: &::deft_quick_js::Context,
+        ) -> ::core::result::Result<(), ::deft_quick_js::ExecutionError> {
+            module().register(context)
+        }
+    });
+    content.push(syn::parse_quote! {
+        /// Define this module as a native ES module named [MODULE_NAME].
+        pub fn register_module(
+            context: &::deft_quick_js::Context,
+        ) -> ::core::result::Result<(), ::deft_quick_js::ExecutionError> {
+            module().register_module(context, MODULE_NAME)
+        }
+    });
+
+    Ok(quote!(#item))
+}
+
+/// Whether one of the attributes is `#[name]` or `#[path::name]`.
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .any(|a| a.path().segments.last().map(|s| s.ident == name).unwrap_or(false))
+}
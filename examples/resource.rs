@@ -1,7 +1,5 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use quick_js::{Context, JsValue, ResourceValue};
-use quick_js::console::{ConsoleBackend, Level};
+use deft_quick_js::{Context, JsValue};
+use deft_quick_js::console::{ConsoleBackend, Level};
 
 pub struct Console {}
 
@@ -32,9 +30,7 @@ pub fn main() {
     let resource = MyResource {
         text: "test".to_string(),
     };
-    let js_value = JsValue::Resource(ResourceValue {
-        resource: Rc::new(RefCell::new(resource)),
-    });
+    let js_value = JsValue::new_resource(resource);
     context.add_callback("print", |msg: JsValue| {
         println!("{:?}", msg);
         if let Some(txt) = msg.as_resource(|r: &mut MyResource| r.text.to_string()) {
@@ -45,5 +41,5 @@ pub fn main() {
         0
     }).unwrap();
     context.set_global("rs", js_value).unwrap();
-    context.eval(r"print(rs)").unwrap();
+    context.eval(r"print(rs)", "resource.js").unwrap();
 }
\ No newline at end of file
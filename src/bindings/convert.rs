@@ -1,12 +1,13 @@
-use std::{collections::HashMap, os::raw::c_char};
+use std::os::raw::c_char;
 use std::os::raw::{c_int, c_void};
-use std::ptr::null_mut;
 
 use libquickjs_sys as q;
 
-use crate::{JsValue, RawJSValue, ResourceValue, ValueError};
+use crate::value::symbol::WELL_KNOWN_SYMBOLS;
+use crate::value::ResourceWrapper;
+use crate::{JsObject, JsSymbol, JsValue, RawJSValue, ResourceValue, SymbolKind, TypedArray, ValueError, ValueLimit, ValueLimits};
 
-use super::{droppable_value::DroppableValue, JsClass, make_cstring, Resource, ResourceObject};
+use super::{discard_exception, droppable_value::DroppableValue, JsClass, make_cstring, Resource, ResourceObject, RuntimeState};
 
 use super::{
     TAG_BOOL, TAG_EXCEPTION, TAG_FLOAT64, TAG_INT, TAG_NULL, TAG_OBJECT, TAG_STRING, TAG_UNDEFINED,
@@ -17,7 +18,7 @@ use {
     super::TAG_BIG_INT,
     crate::value::bigint::{BigInt, BigIntOrI64},
 };
-use libquickjs_sys::{JS_GetClassID, JS_GetOpaque, JS_GetOpaque2, JS_NewClass, JS_NewClassID, JS_NewObjectClass, JS_SetOpaque, JSClassDef, JSRuntime, JSValue, JS_VALUE_GET_TAG};
+use libquickjs_sys::{JS_GetClassID, JS_GetOpaque2, JS_NewObjectClass, JS_SetOpaque, JSValue, JS_VALUE_GET_TAG};
 
 #[cfg(feature = "chrono")]
 fn js_date_constructor(context: *mut q::JSContext) -> q::JSValue {
@@ -57,6 +58,282 @@ fn js_create_bigint_function(context: *mut q::JSContext) -> q::JSValue {
     bigint_function
 }
 
+/// The `Map`, `Set` and `Symbol` functions of a context, captured when it
+/// is created so conversions do not call functions that scripts replaced.
+#[derive(Clone, Copy)]
+pub(super) struct Intrinsics {
+    map: q::JSValue,
+    set: q::JSValue,
+    map_set: q::JSValue,
+    set_add: q::JSValue,
+    map_entries: q::JSValue,
+    set_values: q::JSValue,
+    map_iterator_next: q::JSValue,
+    set_iterator_next: q::JSValue,
+    symbol_key_for: q::JSValue,
+    symbol_description: q::JSValue,
+}
+
+/// The class id of `Set` objects, `JS_CLASS_SET` in quickjs. It follows
+/// `JS_CLASS_MAP`, which [q::JS_IsMap] checks.
+const JS_CLASS_SET: q::JSClassID = 36;
+
+impl Intrinsics {
+    /// Must be called before any script runs in the context.
+    pub(super) fn capture(context: *mut q::JSContext) -> Result<Self, ValueError> {
+        const SCRIPT: &str = "(() => { \
+            const map = new Map(), set = new Set(); \
+            return [Map, Set, Map.prototype.set, Set.prototype.add, Map.prototype.entries, Set.prototype.values, \
+                Object.getPrototypeOf(map.entries()).next, Object.getPrototypeOf(set.values()).next, \
+                Symbol.keyFor, Object.getOwnPropertyDescriptor(Symbol.prototype, 'description').get, set]; \
+        })()";
+        let script = make_cstring(SCRIPT)?;
+        let name = make_cstring("<intrinsics>")?;
+        let values = unsafe {
+            q::JS_Eval(context, script.as_ptr(), SCRIPT.len() as _, name.as_ptr(), q::JS_EVAL_TYPE_GLOBAL as i32)
+        };
+        if q::JS_IsException(values) {
+            unsafe { discard_exception(context) };
+            return Err(ValueError::Internal("Could not capture the intrinsics".into()));
+        }
+        let values = DroppableValue::new(values, |&mut values| unsafe {
+            q::JS_FreeValue(context, values);
+        });
+        let get = |index| unsafe { q::JS_GetPropertyUint32(context, *values, index) };
+        let set_instance = get(10);
+        debug_assert_eq!(unsafe { q::JS_GetClassID(set_instance) }, JS_CLASS_SET);
+        unsafe { q::JS_FreeValue(context, set_instance) };
+        Ok(Intrinsics {
+            map: get(0),
+            set: get(1),
+            map_set: get(2),
+            set_add: get(3),
+            map_entries: get(4),
+            set_values: get(5),
+            map_iterator_next: get(6),
+            set_iterator_next: get(7),
+            symbol_key_for: get(8),
+            symbol_description: get(9),
+        })
+    }
+
+    /// Must be called before the context is freed.
+    pub(super) unsafe fn free(self, context: *mut q::JSContext) {
+        let values = [
+            self.map,
+            self.set,
+            self.map_set,
+            self.set_add,
+            self.map_entries,
+            self.set_values,
+            self.map_iterator_next,
+            self.set_iterator_next,
+            self.symbol_key_for,
+            self.symbol_description,
+        ];
+        for value in values {
+            q::JS_FreeValue(context, value);
+        }
+    }
+
+    fn get(context: *mut q::JSContext) -> Result<Self, ValueError> {
+        let state = unsafe { RuntimeState::get(q::JS_GetRuntime(context)) };
+        state
+            .intrinsics
+            .get()
+            .ok_or_else(|| ValueError::Internal("The intrinsics are not available".into()))
+    }
+}
+
+/// Call `function` on `this` with `args`, turning an exception into an
+/// error.
+fn call_intrinsic(
+    context: *mut q::JSContext,
+    function: q::JSValue,
+    this: q::JSValue,
+    args: &mut [q::JSValue],
+) -> Result<q::JSValue, ValueError> {
+    let value = unsafe { q::JS_Call(context, function, this, args.len() as c_int, args.as_mut_ptr()) };
+    if q::JS_IsException(value) {
+        unsafe { discard_exception(context) };
+        return Err(ValueError::Internal("Could not call intrinsic".into()));
+    }
+    Ok(value)
+}
+
+/// Construct a `Map` from its entries or a `Set` from its values.
+fn create_collection(
+    context: *mut q::JSContext,
+    is_map: bool,
+    items: Vec<Vec<JsValue>>,
+) -> Result<q::JSValue, ValueError> {
+    let intrinsics = Intrinsics::get(context)?;
+    let (constructor, add) = if is_map {
+        (intrinsics.map, intrinsics.map_set)
+    } else {
+        (intrinsics.set, intrinsics.set_add)
+    };
+    let collection = unsafe { q::JS_CallConstructor(context, constructor, 0, std::ptr::null_mut()) };
+    if q::JS_IsException(collection) {
+        unsafe { discard_exception(context) };
+        return Err(ValueError::Internal("Could not construct collection".into()));
+    }
+    let collection = DroppableValue::new(collection, |&mut collection| unsafe {
+        q::JS_FreeValue(context, collection);
+    });
+    for item in items {
+        let mut args = Vec::with_capacity(item.len());
+        let mut result = Ok(());
+        for value in item {
+            match serialize_value(context, value) {
+                Ok(value) => args.push(value),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        let result = result.and_then(|()| call_intrinsic(context, add, *collection, &mut args));
+        for arg in args {
+            unsafe { q::JS_FreeValue(context, arg) };
+        }
+        unsafe { q::JS_FreeValue(context, result?) };
+    }
+    Ok(unsafe { q::JS_DupValue(context, *collection) })
+}
+
+/// Copy the entries of a `Map` or the values of a `Set`.
+///
+/// Returns `Ok(None)` for other values.
+fn deserialize_collection(
+    context: *mut q::JSContext,
+    value: q::JSValue,
+    graph: &mut Graph,
+) -> Result<Option<JsValue>, ValueError> {
+    let is_map = unsafe { q::JS_IsMap(value) };
+    let is_set = !is_map && unsafe { q::JS_GetClassID(value) } == JS_CLASS_SET;
+    if !is_map && !is_set {
+        return Ok(None);
+    }
+    let intrinsics = Intrinsics::get(context)?;
+
+    let (iterate, next) = if is_map {
+        (intrinsics.map_entries, intrinsics.map_iterator_next)
+    } else {
+        (intrinsics.set_values, intrinsics.set_iterator_next)
+    };
+    let iterator = call_intrinsic(context, iterate, value, &mut [])?;
+    let iterator = DroppableValue::new(iterator, |&mut iterator| unsafe {
+        q::JS_FreeValue(context, iterator);
+    });
+
+    graph.enter(value, |graph| {
+        let mut values = Vec::new();
+        let mut pairs = Vec::new();
+        for index in 0.. {
+            // The results are fresh objects, reading them runs no scripts.
+            let result = call_intrinsic(context, next, *iterator, &mut [])?;
+            let result = DroppableValue::new(result, |&mut result| unsafe {
+                q::JS_FreeValue(context, result);
+            });
+            let done = unsafe { q::JS_GetProperty(context, *result, q::JS_ATOM_done as q::JSAtom) };
+            let is_done = unsafe { q::JS_ToBool(context, done) } != 0;
+            unsafe { q::JS_FreeValue(context, done) };
+            if is_done {
+                break;
+            }
+            let item = unsafe { q::JS_GetProperty(context, *result, q::JS_ATOM_value as q::JSAtom) };
+            let item = DroppableValue::new(item, |&mut item| unsafe {
+                q::JS_FreeValue(context, item);
+            });
+            if is_set {
+                values.push(graph.child(PathSegment::Index(index), |graph| deserialize_nested(context, &item, graph))?);
+            } else {
+                let key = graph.child(PathSegment::MapKey(index), |graph| element(context, &item, 0, graph))?;
+                let value = graph.child(PathSegment::MapValue(index), |graph| element(context, &item, 1, graph))?;
+                pairs.push((key, value));
+            }
+        }
+        Ok(Some(if is_map { JsValue::Map(pairs) } else { JsValue::Set(values) }))
+    })
+}
+
+/// The atom of a well-known symbol, e.g. `iterator` for `Symbol.iterator`.
+fn well_known_atom(name: &str) -> Option<q::JSAtom> {
+    let atom = match name {
+        "asyncIterator" => q::JS_ATOM_Symbol_asyncIterator,
+        "hasInstance" => q::JS_ATOM_Symbol_hasInstance,
+        "isConcatSpreadable" => q::JS_ATOM_Symbol_isConcatSpreadable,
+        "iterator" => q::JS_ATOM_Symbol_iterator,
+        "match" => q::JS_ATOM_Symbol_match,
+        "matchAll" => q::JS_ATOM_Symbol_matchAll,
+        "replace" => q::JS_ATOM_Symbol_replace,
+        "search" => q::JS_ATOM_Symbol_search,
+        "species" => q::JS_ATOM_Symbol_species,
+        "split" => q::JS_ATOM_Symbol_split,
+        "toPrimitive" => q::JS_ATOM_Symbol_toPrimitive,
+        "toStringTag" => q::JS_ATOM_Symbol_toStringTag,
+        "unscopables" => q::JS_ATOM_Symbol_unscopables,
+        _ => return None,
+    };
+    Some(atom as q::JSAtom)
+}
+
+fn serialize_symbol(context: *mut q::JSContext, symbol: &JsSymbol) -> Result<JSValue, ValueError> {
+    if let Some(raw) = symbol.raw() {
+        if raw.belongs_to(context) {
+            return Ok(raw.create_js_value());
+        }
+        // Registered and well-known symbols are recreated below, a unique
+        // symbol only exists in its own context.
+        if symbol.kind() == SymbolKind::Unique {
+            return Err(ValueError::ForeignValue);
+        }
+    }
+    let value = match (symbol.key(), symbol.well_known_name().and_then(well_known_atom)) {
+        (Some(key), _) => {
+            let ckey = make_cstring(key)?;
+            unsafe { q::JS_NewSymbol(context, ckey.as_ptr(), true) }
+        }
+        (_, Some(atom)) => unsafe { q::JS_AtomToValue(context, atom) },
+        _ => return Err(ValueError::Internal("Could not create symbol".into())),
+    };
+    if !q::JS_IsSymbol(value) {
+        unsafe { discard_exception(context) };
+        unsafe { q::JS_FreeValue(context, value) };
+        return Err(ValueError::Internal("Could not create symbol".into()));
+    }
+    Ok(value)
+}
+
+fn deserialize_symbol(context: *mut q::JSContext, value: JSValue) -> Result<JsValue, ValueError> {
+    let intrinsics = Intrinsics::get(context)?;
+    // Both return a string, or undefined when there is none.
+    let string_or_none = |result: q::JSValue| -> Result<Option<String>, ValueError> {
+        let converted = deserialize_value(context, &result);
+        unsafe { q::JS_FreeValue(context, result) };
+        match converted? {
+            JsValue::String(s) => Ok(Some(s)),
+            _ => Ok(None),
+        }
+    };
+    let description = string_or_none(call_intrinsic(context, intrinsics.symbol_description, value, &mut [])?)?;
+    let key = string_or_none(call_intrinsic(context, intrinsics.symbol_key_for, q::JS_UNDEFINED, &mut [value])?)?;
+
+    let atom = unsafe { q::JS_ValueToAtom(context, value) };
+    let is_well_known = WELL_KNOWN_SYMBOLS.iter().any(|name| well_known_atom(name) == Some(atom));
+    unsafe { q::JS_FreeAtom(context, atom) };
+
+    let kind = if key.is_some() {
+        SymbolKind::Registered
+    } else if is_well_known {
+        SymbolKind::WellKnown
+    } else {
+        SymbolKind::Unique
+    };
+    Ok(JsValue::Symbol(JsSymbol::from_raw(kind, description, RawJSValue::new(context, &value))))
+}
+
 /// Serialize a Rust value into a quickjs runtime value.
 //TODO pub(super)?
 pub fn serialize_value(
@@ -167,19 +444,29 @@ pub fn serialize_value(
 
             obj
         }
-        JsValue::Raw(raw) => {
-            unsafe {
-                raw.create_js_value()
-            }
-        }
-        JsValue::Exception(raw) => {
-            unsafe {
-                raw.create_js_value()
+        JsValue::Raw(raw) | JsValue::Exception(raw) => {
+            if !raw.belongs_to(context) {
+                return Err(ValueError::ForeignValue);
             }
+            raw.create_js_value()
         }
         JsValue::Resource(raw) => {
             create_resource(context, raw)
         }
+        JsValue::ArrayBuffer(bytes) => {
+            let buffer = unsafe { q::JS_NewArrayBufferCopy(context, bytes.as_ptr(), bytes.len() as _) };
+            if q::JS_IsException(buffer) {
+                return Err(ValueError::Internal("Could not create ArrayBuffer".into()));
+            }
+            buffer
+        }
+        JsValue::TypedArray(array) => create_typed_array(context, &array)?,
+        JsValue::Symbol(symbol) => serialize_symbol(context, &symbol)?,
+        JsValue::Map(entries) => {
+            create_collection(context, true, entries.into_iter().map(|(k, v)| vec![k, v]).collect())?
+        }
+        JsValue::Set(values) => create_collection(context, false, values.into_iter().map(|v| vec![v]).collect())?,
+        JsValue::Iterator(iterator) => iterator.create(context)?,
         #[cfg(feature = "chrono")]
         JsValue::Date(datetime) => {
             let date_constructor = js_date_constructor(context);
@@ -264,35 +551,132 @@ pub fn serialize_value(
     Ok(v)
 }
 
+fn create_typed_array(context: *mut q::JSContext, array: &TypedArray) -> Result<JSValue, ValueError> {
+    let bytes = array.as_bytes();
+    let buffer = unsafe { q::JS_NewArrayBufferCopy(context, bytes.as_ptr(), bytes.len() as _) };
+    if q::JS_IsException(buffer) {
+        return Err(ValueError::Internal("Could not create ArrayBuffer".into()));
+    }
+    // The constructor reads all of (buffer, byteOffset, length).
+    let mut args = [buffer, q::JS_MKVAL(q::JS_TAG_INT, 0), q::JS_UNDEFINED];
+    let value = unsafe { q::JS_NewTypedArray(context, args.len() as c_int, args.as_mut_ptr(), array.array_type()) };
+    unsafe { q::JS_FreeValue(context, buffer) };
+    if q::JS_IsException(value) {
+        return Err(ValueError::Internal(format!("Could not create {}", array.name())));
+    }
+    Ok(value)
+}
+
+/// Create an ArrayBuffer that uses the memory of `owner`, which is dropped
+/// when the buffer is garbage collected or detached.
+pub(crate) fn create_external_array_buffer<B>(context: *mut q::JSContext, owner: B) -> Result<JSValue, ValueError>
+where
+    B: AsMut<[u8]> + 'static,
+{
+    unsafe extern "C" fn free_owner<B>(_runtime: *mut q::JSRuntime, opaque: *mut c_void, _ptr: *mut c_void) {
+        drop(Box::from_raw(opaque as *mut B));
+    }
+
+    // The owner is boxed first, so moving it does not move the bytes.
+    let owner = Box::into_raw(Box::new(owner));
+    let bytes = unsafe { (*owner).as_mut() };
+    let buffer = unsafe {
+        q::JS_NewArrayBuffer(
+            context,
+            bytes.as_mut_ptr(),
+            bytes.len() as _,
+            Some(free_owner::<B>),
+            owner as *mut c_void,
+            false,
+        )
+    };
+    if q::JS_IsException(buffer) {
+        unsafe {
+            discard_exception(context);
+            drop(Box::from_raw(owner));
+        }
+        return Err(ValueError::Internal("Could not create ArrayBuffer".into()));
+    }
+    Ok(buffer)
+}
+
+/// Copy the contents of an ArrayBuffer or typed array.
+///
+/// Returns `Ok(None)` for other values.
+fn deserialize_buffer(context: *mut q::JSContext, value: JSValue) -> Result<Option<JsValue>, ValueError> {
+    let array_type = unsafe { q::JS_GetTypedArrayType(value) };
+    let bytes = match unsafe { borrow_buffer(context, value)? } {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    if array_type < 0 {
+        Ok(Some(JsValue::ArrayBuffer(bytes.to_vec())))
+    } else {
+        Ok(TypedArray::from_bytes(array_type as _, bytes).map(JsValue::TypedArray))
+    }
+}
+
+/// Get the bytes of an ArrayBuffer, or the bytes a typed array refers to.
+///
+/// Returns `Ok(None)` for other values.
+///
+/// Safety: no Javascript code may run while the slice is in use, it could
+/// detach or resize the buffer. The slice must not alias another borrow of
+/// the same buffer.
+pub(crate) unsafe fn borrow_buffer<'a>(
+    context: *mut q::JSContext,
+    value: JSValue,
+) -> Result<Option<&'a mut [u8]>, ValueError> {
+    if q::JS_IsArrayBuffer(value) {
+        return array_buffer_bytes(context, value).map(Some);
+    }
+    if q::JS_GetTypedArrayType(value) < 0 {
+        return Ok(None);
+    }
+    let (mut offset, mut length) = (0, 0);
+    let buffer = q::JS_GetTypedArrayBuffer(context, value, &mut offset, &mut length, std::ptr::null_mut());
+    if q::JS_IsException(buffer) {
+        discard_exception(context);
+        return Err(ValueError::Internal("Could not read typed array: out of bounds".into()));
+    }
+    // The view keeps the buffer alive.
+    let bytes = array_buffer_bytes(context, buffer);
+    q::JS_FreeValue(context, buffer);
+    Ok(Some(&mut bytes?[offset as usize..(offset + length) as usize]))
+}
+
+/// Get the bytes of an ArrayBuffer.
+///
+/// Safety: see [borrow_buffer].
+unsafe fn array_buffer_bytes<'a>(context: *mut q::JSContext, buffer: JSValue) -> Result<&'a mut [u8], ValueError> {
+    let mut size = 0;
+    let ptr = q::JS_GetArrayBuffer(context, &mut size, buffer);
+    if ptr.is_null() {
+        discard_exception(context);
+        return Err(ValueError::Internal("Could not read ArrayBuffer: it is detached".into()));
+    }
+    Ok(std::slice::from_raw_parts_mut(ptr, size as usize))
+}
+
 pub fn create_resource(context: *mut q::JSContext, resource: ResourceValue) -> JSValue {
     unsafe  {
-        let class_id = Resource::class_id();
-        if class_id.id.get() == 0 {
-            let runtime = q::JS_GetRuntime(context);
-            let mut cls_id = 0;
-            JS_NewClassID(runtime, &mut cls_id);
-            class_id.id.set(cls_id);
-            extern fn finalizer(rt: *mut JSRuntime, val: JSValue) {
-                //println!("finalizer calling");
-                unsafe {
-                    let cls_id = JS_GetClassID(val);
-                    let opaque = JS_GetOpaque(val, cls_id) as *mut ResourceObject;
-                    let _ = Box::from_raw(opaque);
-                }
-                //println!("finalizer called");
-            }
-            let cls_def = JSClassDef {
-                class_name: Resource::NAME.as_ptr() as *const std::ffi::c_char,
-                finalizer: Some(finalizer),
-                gc_mark: None,
-                call: None,
-                exotic: null_mut(),
-            };
-            JS_NewClass(runtime, cls_id, &cls_def);
+        let runtime = q::JS_GetRuntime(context);
+        // A resource has a single object per runtime: a traced resource
+        // must only be marked once per collection.
+        if let Some(wrapper) = resource.wrapper.get().filter(|w| w.runtime == runtime) {
+            return q::JS_DupValue(context, q::JS_MKPTR(q::JS_TAG_OBJECT, wrapper.object));
         }
-
-        let class_id = class_id.id.get();
+        let class_id = Resource::register(runtime);
         let res = JS_NewObjectClass(context, class_id as c_int);
+        // Instances of host classes get the prototype of their class.
+        let type_id = resource.resource.try_borrow().ok().map(|r| (*r).type_id());
+        let state = RuntimeState::get(q::JS_GetRuntime(context));
+        if let Some(prototype) = type_id.and_then(|id| state.class_prototype(id)) {
+            q::JS_SetPrototype(context, res, prototype);
+        }
+        if resource.wrapper.get().is_none() {
+            resource.wrapper.set(Some(ResourceWrapper { runtime, object: q::JS_VALUE_GET_PTR(res) }));
+        }
         let opaque = Box::into_raw(Box::new(ResourceObject {
             data: resource,
         }));
@@ -302,50 +686,242 @@ pub fn create_resource(context: *mut q::JSContext, resource: ResourceValue) -> J
 
 }
 
+/// A step from a container to a value in it.
+enum PathSegment {
+    Index(u32),
+    Property(String),
+    MapKey(u32),
+    MapValue(u32),
+}
+
+/// Tracks the conversion of a nested value, to stop at cycles and at the
+/// [ValueLimits] of the runtime.
+struct Graph {
+    limits: ValueLimits,
+    /// The containers being converted, from the root to the current one.
+    ancestors: Vec<*mut c_void>,
+    path: Vec<PathSegment>,
+    elements: usize,
+    /// Whether plain objects are converted too, instead of kept as
+    /// [JsValue::Raw].
+    deep: bool,
+}
+
+impl Graph {
+    fn new(context: *mut q::JSContext) -> Self {
+        let state = unsafe { RuntimeState::get(q::JS_GetRuntime(context)) };
+        Graph {
+            limits: state.value_limits.get(),
+            ancestors: Vec::new(),
+            path: Vec::new(),
+            elements: 0,
+            deep: false,
+        }
+    }
+
+    /// A graph that also converts plain objects.
+    fn deep(context: *mut q::JSContext) -> Self {
+        Graph { deep: true, ..Graph::new(context) }
+    }
+
+    /// The path to the current value, e.g. `$[0].items`.
+    fn path(&self) -> String {
+        let mut path = "$".to_string();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+                PathSegment::Property(name) => path.push_str(&format!(".{}", name)),
+                PathSegment::MapKey(index) => path.push_str(&format!("<key {}>", index)),
+                PathSegment::MapValue(index) => path.push_str(&format!("<value {}>", index)),
+            }
+        }
+        path
+    }
+
+    /// Convert the contents of a container.
+    fn enter<T>(
+        &mut self,
+        container: q::JSValue,
+        f: impl FnOnce(&mut Self) -> Result<T, ValueError>,
+    ) -> Result<T, ValueError> {
+        let ptr = unsafe { container.u.ptr };
+        if self.ancestors.contains(&ptr) {
+            return Err(ValueError::Cycle { path: self.path() });
+        }
+        if self.ancestors.len() >= self.limits.max_depth {
+            let limit = ValueLimit::Depth(self.limits.max_depth);
+            return Err(ValueError::LimitExceeded { limit, path: self.path() });
+        }
+        self.ancestors.push(ptr);
+        let result = f(self);
+        self.ancestors.pop();
+        result
+    }
+
+    /// Fail early if the `len` values of an array would exceed the element
+    /// limit, before reading them.
+    fn reserve(&mut self, len: u32) -> Result<(), ValueError> {
+        let remaining = self.limits.max_elements.saturating_sub(self.elements);
+        if len as usize <= remaining {
+            return Ok(());
+        }
+        self.path.push(PathSegment::Index(remaining as u32));
+        let path = self.path();
+        self.path.pop();
+        let limit = ValueLimit::Elements(self.limits.max_elements);
+        Err(ValueError::LimitExceeded { limit, path })
+    }
+
+    /// Convert a value of the current container.
+    fn child<T>(
+        &mut self,
+        segment: PathSegment,
+        f: impl FnOnce(&mut Self) -> Result<T, ValueError>,
+    ) -> Result<T, ValueError> {
+        self.path.push(segment);
+        self.elements += 1;
+        let result = if self.elements > self.limits.max_elements {
+            let limit = ValueLimit::Elements(self.limits.max_elements);
+            Err(ValueError::LimitExceeded { limit, path: self.path() })
+        } else {
+            f(self)
+        };
+        self.path.pop();
+        result
+    }
+}
+
+fn array_length(context: *mut q::JSContext, array: &q::JSValue) -> Result<u32, ValueError> {
+    let length_name = make_cstring("length")?;
+
+    let len_raw = unsafe { q::JS_GetPropertyStr(context, *array, length_name.as_ptr()) };
+
+    let len_res = deserialize_value(context, &len_raw);
+    unsafe { q::JS_FreeValue(context, len_raw) };
+    match len_res? {
+        JsValue::Int(x) => Ok(x as u32),
+        _ => Err(ValueError::Internal(
+            "Could not determine array length".into(),
+        )),
+    }
+}
+
+/// Convert the element of an array at `index`.
+fn element(
+    context: *mut q::JSContext,
+    array: &q::JSValue,
+    index: u32,
+    graph: &mut Graph,
+) -> Result<JsValue, ValueError> {
+    let value_raw = unsafe { q::JS_GetPropertyUint32(context, *array, index) };
+    if q::JS_IsException(value_raw) {
+        return Err(ValueError::Internal("Could not build array".into()));
+    }
+    let value_res = deserialize_nested(context, &value_raw, graph);
+    unsafe { q::JS_FreeValue(context, value_raw) };
+    value_res
+}
+
 fn deserialize_array(
     context: *mut q::JSContext,
     raw_value: &q::JSValue,
+    graph: &mut Graph,
 ) -> Result<JsValue, ValueError> {
     assert!(q::JS_IsObject(*raw_value));
 
-    let length_name = make_cstring("length")?;
+    graph.enter(*raw_value, |graph| {
+        let len = array_length(context, raw_value)?;
+        graph.reserve(len)?;
+        let mut values = Vec::with_capacity(len as usize);
+        for index in 0..len {
+            let value = graph.child(PathSegment::Index(index), |graph| element(context, raw_value, index, graph))?;
+            values.push(value);
+        }
+        Ok(JsValue::Array(values))
+    })
+}
 
-    let len_raw = unsafe { q::JS_GetPropertyStr(context, *raw_value, length_name.as_ptr()) };
+pub fn deserialize_object(context: *mut q::JSContext, obj: &q::JSValue) -> Result<JsObject, ValueError> {
+    object_properties(context, obj, &mut Graph::new(context))
+}
 
-    let len_res = deserialize_value(context, &len_raw);
-    unsafe { q::JS_FreeValue(context, len_raw) };
-    let len = match len_res? {
-        JsValue::Int(x) => x,
-        _ => {
-            return Err(ValueError::Internal(
-                "Could not determine array length".into(),
-            ));
-        }
-    };
+fn object_properties(
+    context: *mut q::JSContext,
+    obj: &q::JSValue,
+    graph: &mut Graph,
+) -> Result<JsObject, ValueError> {
+    let mut map = JsObject::new();
+    for_each_property(context, obj, q::JS_GPN_STRING_MASK, graph, |key, value| {
+        map.push(atom_to_string(context, key)?, value);
+        Ok(())
+    })?;
+    Ok(map)
+}
 
-    let mut values = Vec::new();
-    for index in 0..(len as usize) {
-        let value_raw = unsafe { q::JS_GetPropertyUint32(context, *raw_value, index as u32) };
-        if q::JS_IsException(value_raw) {
-            return Err(ValueError::Internal("Could not build array".into()));
-        }
-        let value_res = deserialize_value(context, &value_raw);
-        unsafe { q::JS_FreeValue(context, value_raw) };
+/// Get the keys of the enumerable string-keyed own properties of an object,
+/// without converting their values.
+pub(crate) fn property_keys(context: *mut q::JSContext, obj: &q::JSValue) -> Result<Vec<String>, ValueError> {
+    let mut keys = Vec::new();
+    for_each_atom(context, obj, q::JS_GPN_STRING_MASK, |atom| {
+        keys.push(atom_to_string(context, atom)?);
+        Ok(())
+    })?;
+    Ok(keys)
+}
+
+fn atom_to_string(context: *mut q::JSContext, atom: q::JSAtom) -> Result<String, ValueError> {
+    let key_value = unsafe { q::JS_AtomToString(context, atom) };
+    if q::JS_IsException(key_value) {
+        unsafe { discard_exception(context) };
+        return Err(ValueError::Internal(
+            "Could not get object property name".into(),
+        ));
+    }
 
-        let value = value_res?;
-        values.push(value);
+    let key_res = deserialize_value(context, &key_value);
+    unsafe {
+        q::JS_FreeValue(context, key_value);
     }
+    match key_res? {
+        JsValue::String(s) => Ok(s),
+        _ => Err(ValueError::Internal("Could not get property name".into())),
+    }
+}
 
-    Ok(JsValue::Array(values))
+/// Convert the enumerable symbol-keyed own properties of an object.
+pub fn deserialize_symbol_properties(
+    context: *mut q::JSContext,
+    obj: &q::JSValue,
+) -> Result<Vec<(JsSymbol, JsValue)>, ValueError> {
+    let mut properties = Vec::new();
+    for_each_property(context, obj, q::JS_GPN_SYMBOL_MASK, &mut Graph::new(context), |key, value| {
+        let symbol = unsafe { q::JS_AtomToValue(context, key) };
+        let symbol = DroppableValue::new(symbol, |&mut symbol| unsafe {
+            q::JS_FreeValue(context, symbol);
+        });
+        match deserialize_value(context, &symbol)? {
+            JsValue::Symbol(symbol) => properties.push((symbol, value)),
+            _ => return Err(ValueError::Internal("Could not get property symbol".into())),
+        }
+        Ok(())
+    })?;
+    Ok(properties)
 }
 
-pub fn deserialize_object(context: *mut q::JSContext, obj: &q::JSValue) -> Result<HashMap<String, JsValue>, ValueError> {
+/// Call `f` with the key of each enumerable own property whose key matches
+/// `mask`, a `JS_GPN_*_MASK`.
+fn for_each_atom(
+    context: *mut q::JSContext,
+    obj: &q::JSValue,
+    mask: u32,
+    mut f: impl FnMut(q::JSAtom) -> Result<(), ValueError>,
+) -> Result<(), ValueError> {
     assert_eq!(JS_VALUE_GET_TAG(*obj), q::JS_TAG_OBJECT);
 
     let mut properties: *mut q::JSPropertyEnum = std::ptr::null_mut();
     let mut count: u32 = 0;
 
-    let flags = (q::JS_GPN_STRING_MASK | q::JS_GPN_SYMBOL_MASK | q::JS_GPN_ENUM_ONLY) as i32;
+    let flags = (mask | q::JS_GPN_ENUM_ONLY) as i32;
     let ret =
         unsafe { q::JS_GetOwnPropertyNames(context, &mut properties, &mut count, *obj, flags) };
     if ret != 0 {
@@ -367,47 +943,90 @@ pub fn deserialize_object(context: *mut q::JSContext, obj: &q::JSValue) -> Resul
         }
     });
 
-    let mut map = HashMap::new();
     for index in 0..count {
-        let prop = unsafe { (*properties).offset(index as isize) };
-        let raw_value = unsafe { q::JS_GetProperty(context, *obj, (*prop).atom) };
-        if q::JS_IsException(raw_value) {
-            return Err(ValueError::Internal("Could not get object property".into()));
-        }
-
-        let value_res = deserialize_value(context, &raw_value);
-        unsafe {
-            q::JS_FreeValue(context, raw_value);
-        }
-        let value = value_res?;
+        f(unsafe { (*(*properties).offset(index as isize)).atom })?;
+    }
+    Ok(())
+}
 
-        let key_value = unsafe { q::JS_AtomToString(context, (*prop).atom) };
-        if q::JS_IsException(key_value) {
-            return Err(ValueError::Internal(
-                "Could not get object property name".into(),
-            ));
-        }
+/// Call `f` with the key and converted value of each enumerable own
+/// property whose key matches `mask`, a `JS_GPN_*_MASK`.
+fn for_each_property(
+    context: *mut q::JSContext,
+    obj: &q::JSValue,
+    mask: u32,
+    graph: &mut Graph,
+    mut f: impl FnMut(q::JSAtom, JsValue) -> Result<(), ValueError>,
+) -> Result<(), ValueError> {
+    graph.enter(*obj, |graph| {
+        for_each_atom(context, obj, mask, |atom| {
+            let raw_value = unsafe { q::JS_GetProperty(context, *obj, atom) };
+            if q::JS_IsException(raw_value) {
+                return Err(ValueError::Internal("Could not get object property".into()));
+            }
 
-        let key_res = deserialize_value(context, &key_value);
-        unsafe {
-            q::JS_FreeValue(context, key_value);
-        }
-        let key = match key_res? {
-            JsValue::String(s) => s,
-            _ => {
-                return Err(ValueError::Internal("Could not get property name".into()));
+            let segment = PathSegment::Property(atom_name(context, atom));
+            let value_res = graph.child(segment, |graph| deserialize_nested(context, &raw_value, graph));
+            unsafe {
+                q::JS_FreeValue(context, raw_value);
             }
-        };
-        map.insert(key, value);
+            f(atom, value_res?)
+        })
+    })
+}
+
+/// The name of a property for error messages.
+fn atom_name(context: *mut q::JSContext, atom: q::JSAtom) -> String {
+    let ptr = unsafe { q::JS_AtomToCString(context, atom) };
+    if ptr.is_null() {
+        unsafe { discard_exception(context) };
+        return "?".into();
     }
+    let name = unsafe { std::ffi::CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+    unsafe { q::JS_FreeCString(context, ptr) };
+    name
+}
 
-    // Ok(JsValue::Object(map))
-    Ok(map)
+/// Set a symbol-keyed property of an object.
+pub(crate) fn set_symbol_property(
+    context: *mut q::JSContext,
+    obj: &q::JSValue,
+    symbol: &JsSymbol,
+    value: JsValue,
+) -> Result<(), ValueError> {
+    let symbol = serialize_symbol(context, symbol)?;
+    let key = unsafe { q::JS_ValueToAtom(context, symbol) };
+    unsafe { q::JS_FreeValue(context, symbol) };
+    let value = serialize_value(context, value);
+    let ret = value.map(|value| unsafe { q::JS_SetProperty(context, *obj, key, value) });
+    unsafe { q::JS_FreeAtom(context, key) };
+    if ret? < 0 {
+        unsafe { discard_exception(context) };
+        return Err(ValueError::Internal("Could not set property".into()));
+    }
+    Ok(())
 }
 
 pub fn deserialize_value(
     context: *mut q::JSContext,
     value: &q::JSValue,
+) -> Result<JsValue, ValueError> {
+    deserialize_nested(context, value, &mut Graph::new(context))
+}
+
+/// Convert a value, including the plain objects in it, which
+/// [deserialize_value] keeps as [JsValue::Raw].
+pub(crate) fn deserialize_deep(
+    context: *mut q::JSContext,
+    value: &q::JSValue,
+) -> Result<JsValue, ValueError> {
+    deserialize_nested(context, value, &mut Graph::deep(context))
+}
+
+fn deserialize_nested(
+    context: *mut q::JSContext,
+    value: &q::JSValue,
+    graph: &mut Graph,
 ) -> Result<JsValue, ValueError> {
     let r = value;
 
@@ -463,7 +1082,7 @@ pub fn deserialize_value(
             }
             let is_array = unsafe { q::JS_IsArray(context, *r) } > 0;
             if is_array {
-                deserialize_array(context, r)
+                deserialize_array(context, r, graph)
             } else {
                 let is_resource = unsafe {
                     Resource::class_id().id.get() > 0 && q::JS_GetClassID(*r) == Resource::class_id().id.get()
@@ -472,12 +1091,15 @@ pub fn deserialize_value(
                     unsafe {
                         let cls_id = JS_GetClassID(*value);
                         let cls_obj = JS_GetOpaque2(context, *value, cls_id) as *mut ResourceObject;
-                        let res = (*cls_obj).data.resource.clone();
-                        return Ok(JsValue::Resource(ResourceValue {
-                            resource: res
-                        }))
+                        return Ok(JsValue::Resource((*cls_obj).data.clone()))
                     }
                 }
+                if let Some(buffer) = deserialize_buffer(context, *r)? {
+                    return Ok(buffer);
+                }
+                if let Some(collection) = deserialize_collection(context, *r, graph)? {
+                    return Ok(collection);
+                }
                 #[cfg(feature = "chrono")]
                 {
                     use chrono::offset::TimeZone;
@@ -523,6 +1145,9 @@ pub fn deserialize_value(
                         unsafe { q::JS_FreeValue(context, date_constructor) };
                     }
                 }
+                if graph.deep {
+                    return Ok(JsValue::Object(object_properties(context, r, graph)?));
+                }
                 let raw_js_value = RawJSValue::new(context, value);
                 return Ok(JsValue::Raw(raw_js_value));
             }
@@ -556,6 +1181,7 @@ pub fn deserialize_value(
                 }))
             }
         }
+        q::JS_TAG_SYMBOL => deserialize_symbol(context, *r),
         q::JS_TAG_EXCEPTION => {
             let raw_js_value = RawJSValue::new(context, value);
             Ok(JsValue::Exception(raw_js_value))
@@ -2,26 +2,36 @@ mod compile;
 //TODO no pub?
 pub mod convert;
 mod droppable_value;
+mod panic;
 //TODO no pub?
 pub mod value;
 
-use std::{ffi::CString, os::raw::{c_int, c_void}, sync::Mutex};
+use std::{ffi::CString, os::raw::{c_char, c_int, c_void}};
 use std::any::Any;
-use std::cell::{Cell};
+use std::any::TypeId;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::mem::ManuallyDrop;
+use std::panic::AssertUnwindSafe;
 use std::ptr::{null_mut};
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
 use anyhow::Context;
 use libquickjs_sys as q;
-use libquickjs_sys::{JS_EVAL_TYPE_MODULE, JSClassID, JSContext, JSValue, JS_VALUE_GET_PTR};
+use libquickjs_sys::{JS_EVAL_TYPE_MODULE, JSClassID, JSContext, JSValue};
 
-use crate::{callback::{Arguments, Callback}, console::ConsoleBackend, ContextError, ExecutionError, JsValue, ResourceValue, ValueError};
+use crate::{callback::{Arguments, CallContext, Callback, CallbackMut, MutCallback}, console::ConsoleBackend, ContextError, ExecutionError, JsValue, PanicPolicy, RawJSValue, ResourceValue, Tracer, ValueError, ValueLimits};
 
 use value::{JsFunction, OwnedJsObject};
 
 pub use value::{JsCompiledFunction, OwnedJsValue};
-use crate::bindings::convert::deserialize_value;
+pub(crate) use panic::{discard_exception, install_panic_hook};
+use crate::bindings::convert::{deserialize_value, Intrinsics};
 use crate::exception::{HostPromiseRejectionTracker, HostPromiseRejectionTrackerWrapper};
 use crate::loader::{quickjs_rs_module_loader, JsModuleLoader};
+use crate::iterable::{JobWakers, StreamSlot};
+use crate::persistent::PersistentSlot;
+use crate::typescript::Declarations;
 
 // JS_TAG_* constants from quickjs.
 // For some reason bindgen does not pick them up.
@@ -44,8 +54,13 @@ extern "C" fn host_promise_rejection_tracker(
     is_handled: bool,
     opaque: *mut ::std::os::raw::c_void,
 ) {
-    let promise =  deserialize_value(ctx, &promise).unwrap();
-    let reason = deserialize_value(ctx, &reason).unwrap();
+    // A value that can't be converted, like a cyclic error, is passed on
+    // as is instead of panicking across the ffi boundary.
+    let convert = |value: &JSValue| {
+        deserialize_value(ctx, value).unwrap_or_else(|_| JsValue::Raw(RawJSValue::new(ctx, value)))
+    };
+    let promise = convert(&promise);
+    let reason = convert(&reason);
     let mut opaque = opaque as *mut HostPromiseRejectionTrackerWrapper;
     unsafe {
         (*opaque).tracker.track_promise_rejection(promise, reason, is_handled);
@@ -81,6 +96,45 @@ trait JsClass {
 
     fn class_id() -> Rc<ClassId>;
 
+    fn finalizer() -> q::JSClassFinalizer {
+        None
+    }
+
+    fn gc_mark() -> q::JSClassGCMark {
+        None
+    }
+
+    fn call() -> q::JSClassCall {
+        None
+    }
+
+    /// Get the class id, registering the class in the given runtime if needed.
+    ///
+    /// The id is allocated once per thread, but every runtime needs its own
+    /// class registration.
+    fn register(runtime: *mut q::JSRuntime) -> JSClassID {
+        let class_id = Self::class_id();
+        unsafe {
+            if class_id.id.get() == 0 {
+                let mut cls_id = 0;
+                q::JS_NewClassID(runtime, &mut cls_id);
+                class_id.id.set(cls_id);
+            }
+            let cls_id = class_id.id.get();
+            if !q::JS_IsRegisteredClass(runtime, cls_id) {
+                let class_name = make_cstring(Self::NAME).unwrap();
+                let cls_def = q::JSClassDef {
+                    class_name: class_name.as_ptr(),
+                    finalizer: Self::finalizer(),
+                    gc_mark: Self::gc_mark(),
+                    call: Self::call(),
+                    exotic: null_mut(),
+                };
+                q::JS_NewClass(runtime, cls_id, &cls_def);
+            }
+            cls_id
+        }
+    }
 }
 
 thread_local! {
@@ -95,46 +149,80 @@ impl JsClass for Resource {
     fn class_id() -> Rc<ClassId> {
         CLASS_ID.with(|c| c.clone())
     }
+
+    fn finalizer() -> q::JSClassFinalizer {
+        unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: JSValue) {
+            let opaque = q::JS_GetOpaque(val, Resource::class_id().id.get()) as *mut ResourceObject;
+            if !opaque.is_null() {
+                let object = Box::from_raw(opaque);
+                let wrapper = &object.data.wrapper;
+                if wrapper.get().map(|w| w.object) == Some(q::JS_VALUE_GET_PTR(val)) {
+                    wrapper.set(None);
+                }
+            }
+        }
+        Some(finalizer)
+    }
+
+    fn gc_mark() -> q::JSClassGCMark {
+        unsafe extern "C" fn gc_mark(rt: *mut q::JSRuntime, val: JSValue, mark_func: q::JS_MarkFunc) {
+            let opaque = q::JS_GetOpaque(val, Resource::class_id().id.get()) as *mut ResourceObject;
+            if !opaque.is_null() {
+                (*opaque).data.trace(&Tracer::new(rt, mark_func));
+            }
+        }
+        Some(gc_mark)
+    }
 }
 
-type WrappedCallback = dyn Fn(c_int, *mut q::JSValue) -> q::JSValue;
+type WrappedCallback = dyn Fn(*mut q::JSContext, q::JSValue, c_int, *mut q::JSValue, c_int) -> q::JSValue;
 
-/// Taken from: https://s3.amazonaws.com/temp.michaelfbryan.com/callbacks/index.html
-///
-/// Create a C wrapper function for a Rust closure to enable using it as a
-/// callback function in the Quickjs runtime.
+thread_local! {
+    static CALLBACK_CLASS_ID: Rc<ClassId> = Rc::new(ClassId::new());
+}
+
+/// The class of JS functions that are backed by a Rust closure.
 ///
-/// Both the boxed closure and the boxed data are returned and must be stored
-/// by the caller to guarantee they stay alive.
-unsafe fn build_closure_trampoline<F>(
-    closure: F,
-) -> ((Box<WrappedCallback>, Box<q::JSValue>), q::JSCFunctionData)
-where
-    F: Fn(c_int, *mut q::JSValue) -> q::JSValue + 'static,
-{
-    unsafe extern "C" fn trampoline<F>(
-        _ctx: *mut q::JSContext,
-        _this: q::JSValue,
-        argc: c_int,
-        argv: *mut q::JSValue,
-        _magic: c_int,
-        data: *mut q::JSValue,
-    ) -> q::JSValue
-    where
-        F: Fn(c_int, *mut q::JSValue) -> q::JSValue,
-    {
-        let closure_ptr = JS_VALUE_GET_PTR(*data);
-        let closure: &mut F = &mut *(closure_ptr as *mut F);
-        (*closure)(argc, argv)
-    }
+/// The boxed closure is stored as the opaque data of the function object, so
+/// it is dropped by the finalizer once the function is garbage collected.
+struct CallbackFunction;
 
-    let boxed_f = Box::new(closure);
+impl JsClass for CallbackFunction {
+    const NAME: &'static str = "CallbackFunction";
 
-    let data = Box::new(
-        q::JS_MKPTR(q::JS_TAG_NULL, (&*boxed_f) as *const F as *mut c_void)
-    );
+    fn class_id() -> Rc<ClassId> {
+        CALLBACK_CLASS_ID.with(|c| c.clone())
+    }
 
-    ((boxed_f, data), Some(trampoline::<F>))
+    fn finalizer() -> q::JSClassFinalizer {
+        unsafe extern "C" fn finalizer(_rt: *mut q::JSRuntime, val: JSValue) {
+            let opaque = q::JS_GetOpaque(val, CallbackFunction::class_id().id.get())
+                as *mut Box<WrappedCallback>;
+            if !opaque.is_null() {
+                let _ = Box::from_raw(opaque);
+            }
+        }
+        Some(finalizer)
+    }
+
+    fn call() -> q::JSClassCall {
+        unsafe extern "C" fn trampoline(
+            ctx: *mut q::JSContext,
+            func_obj: q::JSValue,
+            this: q::JSValue,
+            argc: c_int,
+            argv: *mut q::JSValue,
+            flags: c_int,
+        ) -> q::JSValue {
+            let closure = q::JS_GetOpaque(func_obj, CallbackFunction::class_id().id.get())
+                as *mut Box<WrappedCallback>;
+            if closure.is_null() {
+                return q::JS_ThrowTypeError(ctx, b"Callback function has no closure\0".as_ptr() as *const c_char);
+            }
+            (*closure)(ctx, this, argc, argv, flags)
+        }
+        Some(trampoline)
+    }
 }
 
 /// OwnedValueRef wraps a Javascript value from the quickjs runtime.
@@ -369,24 +457,134 @@ impl<'a> OwnedObjectRef<'a> {
     }
 }
 
-/*
-type ModuleInit = dyn Fn(*mut q::JSContext, *mut q::JSModuleDef);
-
-thread_local! {
-    static NATIVE_MODULE_INIT: RefCell<Option<Box<ModuleInit>>> = RefCell::new(None);
-}
+/// Creates the exports of a native module when it is first imported.
+pub(crate) type ModuleInit = dyn Fn(&crate::Context) -> Result<Vec<(String, JsValue)>, ExecutionError>;
 
 unsafe extern "C" fn native_module_init(
     ctx: *mut q::JSContext,
     m: *mut q::JSModuleDef,
 ) -> ::std::os::raw::c_int {
-    NATIVE_MODULE_INIT.with(|init| {
-        let init = init.replace(None).unwrap();
-        init(ctx, m);
+    let state = RuntimeState::get(q::JS_GetRuntime(ctx));
+    let name = {
+        let atom = q::JS_GetModuleName(ctx, m);
+        let ptr = q::JS_AtomToCString(ctx, atom);
+        q::JS_FreeAtom(ctx, atom);
+        if ptr.is_null() {
+            return -1;
+        }
+        let name = std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned();
+        q::JS_FreeCString(ctx, ptr);
+        name
+    };
+    let init = match state.native_modules.borrow().get(&name).cloned() {
+        Some(init) => init,
+        None => return -1,
+    };
+
+    // The context is owned by the caller, so it must never be dropped here.
+    let context = ManuallyDrop::new(crate::Context::from_wrapper(ContextWrapper::from_raw(ctx)));
+    let result = init(&context).and_then(|exports| {
+        for (export, value) in exports {
+            let cexport = make_cstring(export)?;
+            let value = convert::serialize_value(ctx, value)?;
+            if q::JS_SetModuleExport(ctx, m, cexport.as_ptr(), value) < 0 {
+                return Err(ExecutionError::Internal(format!(
+                    "Could not set export '{}' of module '{}'",
+                    cexport.to_string_lossy(),
+                    name
+                )));
+            }
+        }
+        Ok(())
     });
-    0
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            if let Ok(error) = convert::serialize_value(ctx, JsValue::String(e.to_string())) {
+                q::JS_Throw(ctx, error);
+            }
+            -1
+        }
+    }
+}
+
+/// State shared by all contexts of a runtime.
+///
+/// Stored as the runtime opaque, so it is reachable from callbacks.
+struct RuntimeState {
+    panic_policy: Cell<PanicPolicy>,
+    /// Payload of a callback panic that is unwinding through Javascript.
+    pending_panic: RefCell<Option<Box<dyn Any + Send>>>,
+    value_limits: Cell<ValueLimits>,
+    /// Prototypes of the host classes, by the type of their Rust value.
+    class_prototypes: RefCell<HashMap<TypeId, q::JSValue>>,
+    /// Initializers of the native modules of the context, by name.
+    native_modules: RefCell<HashMap<String, Rc<ModuleInit>>>,
+    /// TypeScript declarations of the host API of the context.
+    declarations: RefCell<Declarations>,
+    /// Values of the persistent handles of the context.
+    persistents: RefCell<Vec<Weak<PersistentSlot>>>,
+    /// Streams exposed to Javascript, see [JsValue::stream].
+    streams: RefCell<Vec<Weak<StreamSlot>>>,
+    /// Names of the globals added by [ContextWrapper::add_callback].
+    callbacks: RefCell<HashSet<String>>,
+    /// The `Map`, `Set` and `Symbol` functions used by conversions.
+    intrinsics: Cell<Option<Intrinsics>>,
+    /// The tasks waiting for jobs to be queued.
+    job_wakers: Arc<JobWakers>,
+}
+
+impl RuntimeState {
+    /// Safety: the runtime must have been created by [ContextWrapper::new].
+    unsafe fn get<'a>(runtime: *mut q::JSRuntime) -> &'a RuntimeState {
+        &*(q::JS_GetRuntimeOpaque(runtime) as *const RuntimeState)
+    }
+
+    /// Get the prototype of the host class of the given type.
+    ///
+    /// The returned value is not duplicated.
+    pub(crate) fn class_prototype(&self, type_id: TypeId) -> Option<q::JSValue> {
+        self.class_prototypes.borrow().get(&type_id).copied()
+    }
+
+    /// Release the state that belongs to the current context.
+    ///
+    /// Must be called before the context is freed.
+    unsafe fn clear_context(&self, context: *mut q::JSContext) {
+        for (_, prototype) in self.class_prototypes.borrow_mut().drain() {
+            q::JS_FreeValue(context, prototype);
+        }
+        self.native_modules.borrow_mut().clear();
+        self.declarations.borrow_mut().clear();
+        self.callbacks.borrow_mut().clear();
+        if let Some(intrinsics) = self.intrinsics.take() {
+            intrinsics.free(context);
+        }
+        let persistents = self.persistents.take();
+        for slot in persistents.iter().filter_map(Weak::upgrade) {
+            slot.release();
+        }
+        let streams = self.streams.take();
+        for slot in streams.iter().filter_map(Weak::upgrade) {
+            slot.release();
+        }
+    }
+
+    /// Poll the streams that were woken, returns whether one of them
+    /// settled a promise.
+    fn poll_streams(&self) -> bool {
+        let streams = {
+            let mut streams = self.streams.borrow_mut();
+            streams.retain(|slot| slot.strong_count() > 0);
+            streams.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        let mut settled = false;
+        for slot in streams.iter().filter(|slot| slot.is_woken()) {
+            settled |= slot.poll();
+        }
+        settled
+    }
 }
-*/
 
 /// Wraps a quickjs context.
 ///
@@ -394,11 +592,6 @@ unsafe extern "C" fn native_module_init(
 pub struct ContextWrapper {
     runtime: *mut q::JSRuntime,
     pub(crate) context: *mut q::JSContext,
-    /// Stores callback closures and quickjs data pointers.
-    /// This array is write-only and only exists to ensure the lifetime of
-    /// the closure.
-    // A Mutex is used over a RefCell because it needs to be unwind-safe.
-    callbacks: Mutex<Vec<(Box<WrappedCallback>, Box<q::JSValue>)>>,
     module_loader: Option<*mut Box<dyn JsModuleLoader>>,
     host_promise_rejection_tracker_wrapper: Option<*mut HostPromiseRejectionTrackerWrapper>,
 }
@@ -411,12 +604,34 @@ impl Drop for ContextWrapper {
                     let _ = Box::from_raw(p);
                 }
             }
+            let state = q::JS_GetRuntimeOpaque(self.runtime) as *mut RuntimeState;
+            (*state).clear_context(self.context);
             q::JS_FreeContext(self.context);
             q::JS_FreeRuntime(self.runtime);
+            let _ = Box::from_raw(state);
         }
     }
 }
 
+/// Wake the tasks waiting for jobs if the context queued any.
+pub(crate) fn wake_for_jobs(context: *mut q::JSContext) {
+    let runtime = unsafe { q::JS_GetRuntime(context) };
+    if unsafe { q::JS_IsJobPending(runtime) } {
+        unsafe { RuntimeState::get(runtime) }.job_wakers.wake();
+    }
+}
+
+/// Add a slot to a list of weak references, dropping the dead ones only
+/// when the list is full so that adding takes amortized constant time.
+fn push_slot<T>(slots: &mut Vec<Weak<T>>, slot: &Rc<T>) {
+    if slots.len() == slots.capacity() {
+        slots.retain(|slot| slot.strong_count() > 0);
+        // Leave room for as many slots as are alive before pruning again.
+        slots.reserve(slots.len().max(4));
+    }
+    slots.push(Rc::downgrade(slot));
+}
+
 impl ContextWrapper {
     /// Initialize a wrapper by creating a JSRuntime and JSContext.
     pub fn new(memory_limit: Option<usize>) -> Result<Self, ContextError> {
@@ -425,6 +640,23 @@ impl ContextWrapper {
             return Err(ContextError::RuntimeCreationFailed);
         }
 
+        let state = Box::new(RuntimeState {
+            panic_policy: Cell::new(PanicPolicy::default()),
+            pending_panic: RefCell::new(None),
+            value_limits: Cell::new(ValueLimits::default()),
+            class_prototypes: RefCell::new(HashMap::new()),
+            native_modules: RefCell::new(HashMap::new()),
+            declarations: RefCell::new(Declarations::default()),
+            persistents: RefCell::new(Vec::new()),
+            callbacks: RefCell::new(HashSet::new()),
+            streams: RefCell::new(Vec::new()),
+            intrinsics: Cell::new(None),
+            job_wakers: Arc::new(JobWakers::default()),
+        });
+        unsafe {
+            q::JS_SetRuntimeOpaque(runtime, Box::into_raw(state) as *mut c_void);
+        }
+
         // Configure memory limit if specified.
         if let Some(limit) = memory_limit {
             unsafe {
@@ -450,14 +682,43 @@ impl ContextWrapper {
         let wrapper = Self {
             runtime,
             context,
-            callbacks: Mutex::new(Vec::new()),
             module_loader: None,
             host_promise_rejection_tracker_wrapper: None,
         };
+        wrapper.capture_intrinsics()?;
 
         Ok(wrapper)
     }
 
+    /// Keep the functions conversions use before any script can replace
+    /// them, must be called on every new context.
+    fn capture_intrinsics(&self) -> Result<(), ContextError> {
+        let intrinsics = Intrinsics::capture(self.context).map_err(|_| ContextError::ContextCreationFailed)?;
+        unsafe { RuntimeState::get(self.runtime) }.intrinsics.set(Some(intrinsics));
+        Ok(())
+    }
+
+    /// Wrap a context that is owned elsewhere, e.g. the caller of a callback.
+    ///
+    /// Safety: the returned wrapper must never be dropped.
+    pub(crate) unsafe fn from_raw(context: *mut q::JSContext) -> Self {
+        Self {
+            runtime: q::JS_GetRuntime(context),
+            context,
+            module_loader: None,
+            host_promise_rejection_tracker_wrapper: None,
+        }
+    }
+
+    /// Set how panics in callbacks are reported.
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        unsafe { RuntimeState::get(self.runtime) }.panic_policy.set(policy);
+    }
+
+    pub fn set_value_limits(&self, limits: ValueLimits) {
+        unsafe { RuntimeState::get(self.runtime) }.value_limits.set(limits);
+    }
+
     pub fn set_host_promise_rejection_tracker<F: HostPromiseRejectionTracker + 'static>(&mut self, tracker: F) {
         let tracker = HostPromiseRejectionTrackerWrapper::new(Box::new(tracker));
         let ptr = Box::into_raw(Box::new(tracker));
@@ -508,9 +769,9 @@ impl ContextWrapper {
     /// Reset the wrapper by creating a new context.
     pub fn reset(self) -> Result<Self, ContextError> {
         unsafe {
+            RuntimeState::get(self.runtime).clear_context(self.context);
             q::JS_FreeContext(self.context);
         };
-        self.callbacks.lock().unwrap().clear();
         let context = unsafe { q::JS_NewContext(self.runtime) };
         if context.is_null() {
             return Err(ContextError::ContextCreationFailed);
@@ -518,6 +779,7 @@ impl ContextWrapper {
 
         let mut s = self;
         s.context = context;
+        s.capture_intrinsics()?;
         Ok(s)
     }
 
@@ -526,11 +788,34 @@ impl ContextWrapper {
         Ok(OwnedJsValue::new(self, serialized))
     }
 
+    /// Keep a value until it is dropped or the context is reset or dropped.
+    pub(crate) fn persist(&self, value: OwnedJsValue<'_>) -> Rc<PersistentSlot> {
+        let slot = Rc::new(PersistentSlot::new(self.context, unsafe { value.extract() }));
+        let mut persistents = unsafe { RuntimeState::get(self.runtime) }.persistents.borrow_mut();
+        push_slot(&mut persistents, &slot);
+        slot
+    }
+
+    /// The tasks waiting for jobs of the context.
+    pub(crate) fn job_wakers(&self) -> Arc<JobWakers> {
+        unsafe { RuntimeState::get(self.runtime) }.job_wakers.clone()
+    }
+
+    /// Poll the stream when the context runs its pending jobs.
+    pub(crate) fn register_stream(&self, slot: &Rc<StreamSlot>) {
+        let mut streams = unsafe { RuntimeState::get(self.runtime) }.streams.borrow_mut();
+        push_slot(&mut streams, slot);
+    }
+
     // Deserialize a quickjs runtime value into a Rust value.
     pub(crate) fn to_value(&self, value: &q::JSValue) -> Result<JsValue, ValueError> {
         convert::deserialize_value(self.context, value)
     }
 
+    pub(crate) fn to_value_deep(&self, value: &q::JSValue) -> Result<JsValue, ValueError> {
+        convert::deserialize_deep(self.context, value)
+    }
+
     /// Get the global object.
     pub fn global(&self) -> Result<OwnedJsObject<'_>, ExecutionError> {
         let global_raw = unsafe { q::JS_GetGlobalObject(self.context) };
@@ -540,11 +825,15 @@ impl ContextWrapper {
     }
 
     /// Get the last exception from the runtime, and if present, convert it to a ExceptionError.
+    ///
+    /// If the exception was raised by a callback panic under
+    /// [PanicPolicy::Resume], the panic is resumed instead.
     pub(crate) fn get_exception(&self) -> Option<ExecutionError> {
         let value = unsafe {
             let raw = q::JS_GetException(self.context);
             OwnedJsValue::new(self, raw)
         };
+        unsafe { panic::resume_pending_panic(self.context, value.value) };
 
         if value.is_null() {
             None
@@ -581,6 +870,7 @@ impl ContextWrapper {
         &'a self,
         value: OwnedJsValue<'a>,
     ) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        wake_for_jobs(self.context);
         if value.is_exception() {
             unsafe {
                 //TODO remove
@@ -616,9 +906,48 @@ impl ContextWrapper {
         self.resolve_value(value)
     }
 
-    /*
-    /// Call a constructor function.
-    fn call_constructor<'a>(
+    /// Parse a JSON document, like `JSON.parse`.
+    pub fn parse_json<'a>(&'a self, json: &str, filename: &str) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        let filename_c = make_cstring(filename)?;
+        // JS_ParseJSON expects a null terminated buffer.
+        let json_c = make_cstring(json)?;
+        let value_raw = unsafe { q::JS_ParseJSON(self.context, json_c.as_ptr(), json.len() as _, filename_c.as_ptr()) };
+        let value = OwnedJsValue::new(self, value_raw);
+        self.resolve_value(value)
+    }
+
+    /// Deep-copy a value of this context into `target`, see `JS_WriteObject`
+    /// and `JS_ReadObject`.
+    ///
+    /// Shared and cyclic references are kept. Fails for values that can not
+    /// be copied, like functions and host class instances.
+    pub fn copy_value<'b>(
+        &self,
+        value: &OwnedJsValue<'_>,
+        target: &'b ContextWrapper,
+    ) -> Result<OwnedJsValue<'b>, ExecutionError> {
+        let mut len = 0;
+        let buf = unsafe {
+            q::JS_WriteObject(self.context, &mut len, value.value, q::JS_WRITE_OBJ_REFERENCE as i32)
+        };
+        if buf.is_null() {
+            return Err(self
+                .get_exception()
+                .unwrap_or_else(|| ExecutionError::Internal("Could not copy value".into())));
+        }
+        let raw = unsafe { q::JS_ReadObject(target.context, buf, len, q::JS_READ_OBJ_REFERENCE as i32) };
+        unsafe { q::js_free(self.context, buf as *mut c_void) };
+        let copy = OwnedJsValue::new(target, raw);
+        if copy.is_exception() {
+            return Err(target
+                .get_exception()
+                .unwrap_or_else(|| ExecutionError::Internal("Could not copy value".into())));
+        }
+        Ok(copy)
+    }
+
+    /// Call a constructor function, like `new function(...args)`.
+    pub fn call_constructor<'a>(
         &'a self,
         function: OwnedJsValue<'a>,
         args: Vec<OwnedJsValue<'a>>,
@@ -634,16 +963,8 @@ impl ContextWrapper {
             )
         };
         let value = OwnedJsValue::new(self, value_raw);
-        if value.is_exception() {
-            let err = self
-                .get_exception()
-                .unwrap_or_else(|| ExecutionError::Exception("Unknown exception".into()));
-            Err(err)
-        } else {
-            Ok(value)
-        }
+        self.resolve_value(value)
     }
-    */
 
     /// Call a JS function with the given arguments.
     pub fn call_function<'a>(
@@ -655,65 +976,102 @@ impl ContextWrapper {
         self.resolve_value(ret)
     }
 
+    /// Call a JS function with `this` bound to the given value.
+    pub fn call_function_with_this<'a>(
+        &'a self,
+        function: JsFunction<'a>,
+        this: &OwnedJsValue<'a>,
+        args: Vec<OwnedJsValue<'a>>,
+    ) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        let ret = function.call_with_this(this, args)?;
+        self.resolve_value(ret)
+    }
+
     /// Helper for executing a callback closure.
     fn exec_callback<F>(
         context: *mut q::JSContext,
+        this: q::JSValue,
         argc: c_int,
         argv: *mut q::JSValue,
+        flags: c_int,
         callback: &impl Callback<F>,
     ) -> Result<q::JSValue, ExecutionError> {
-        let result = std::panic::catch_unwind(|| {
-            let arg_slice = unsafe { std::slice::from_raw_parts(argv, argc as usize) };
+        let call = unsafe { CallContext::new(context, this, argc, argv, flags) };
 
-            let mut args = Vec::with_capacity(arg_slice.len());
-            for a in arg_slice {
-                let a = deserialize_value(context, a)
+        match callback.call_with_context(&call) {
+            Ok(Ok(result)) => {
+                let serialized = convert::serialize_value(context, result)
                     .map_err(|e| {
-                        ExecutionError::Internal(
-                            format!("failed to deserialize arguments {} (zero-based) to JS value, {}", args.len(), e)
-                        )
+                        ExecutionError::Internal(format!("failed to serialize rust value to js value, {}", e))
                     })?;
-                args.push(a);
-            }
-
-            match callback.call(args) {
-                Ok(Ok(result)) => {
-                    let serialized = convert::serialize_value(context, result)
-                        .map_err(|e| {
-                            ExecutionError::Internal(format!("failed to serialize rust value to js value, {}", e))
-                        })?;
-                    Ok(serialized)
-                }
-                // TODO: better error reporting.
-                Ok(Err(e)) => Err(ExecutionError::Exception(JsValue::String(e))),
-                Err(e) => Err(e.into()),
+                Ok(serialized)
             }
-        });
-
-        match result {
-            Ok(r) => r,
-            Err(_e) => Err(ExecutionError::Internal("Callback panicked!".to_string())),
+            // TODO: better error reporting.
+            Ok(Err(e)) => Err(ExecutionError::Exception(JsValue::String(e))),
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// Add a global JS function that is backed by a Rust function or closure.
+    /// Create a JS function that is backed by a Rust function or closure.
     pub fn create_callback<'a, F>(
         &'a self,
         name: &str,
         callback: impl Callback<F> + 'static,
+    ) -> Result<JsFunction<'a>, ExecutionError> {
+        self.new_function(name, callback, false)
+    }
+
+    /// Create a JS function that can also be invoked with `new`, in which
+    /// case the callback must return an object.
+    pub fn create_constructor<'a, F>(
+        &'a self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+    ) -> Result<JsFunction<'a>, ExecutionError> {
+        self.new_function(name, callback, true)
+    }
+
+    fn new_function<'a, F>(
+        &'a self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+        constructor: bool,
     ) -> Result<JsFunction<'a>, ExecutionError> {
         let argcount = callback.argument_count() as i32;
 
-        let context = self.context;
-        let name = name.to_string();
-        let wrapper = move |argc: c_int, argv: *mut q::JSValue| -> q::JSValue {
-            match Self::exec_callback(context, argc, argv, &callback) {
+        let callback_name = name.to_string();
+        let wrapper = move |context: *mut q::JSContext,
+                            this: q::JSValue,
+                            argc: c_int,
+                            argv: *mut q::JSValue,
+                            flags: c_int|
+              -> q::JSValue {
+            // Callbacks do not have to be unwind safe: a panic is reported
+            // according to the panic policy and the callback state is not
+            // touched from Rust afterwards.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                Self::exec_callback(context, this, argc, argv, flags, &callback)
+            }));
+            let result = match result {
+                Ok(result) => result,
+                Err(payload) => {
+                    let policy = unsafe { RuntimeState::get(q::JS_GetRuntime(context)) }.panic_policy.get();
+                    return panic::throw_panic(context, policy, &callback_name, payload);
+                }
+            };
+            match result {
+                Ok(value) if flags & q::JS_CALL_FLAG_CONSTRUCTOR as c_int != 0 && !q::JS_IsObject(value) => unsafe {
+                    q::JS_FreeValue(context, value);
+                    let message = format!("Constructor {} did not return an object", &callback_name);
+                    let cmessage = make_cstring(message).unwrap_or_default();
+                    q::JS_ThrowTypeError(context, b"%s\0".as_ptr() as *const c_char, cmessage.as_ptr())
+                },
                 Ok(value) => value,
                 // TODO: better error reporting.
                 Err(e) => {
                     let js_exception_value = match e {
                         ExecutionError::Exception(e) => e,
-                        other => format!("Failed to call [{}], {}", &name,  other.to_string()).into(),
+                        other => format!("Failed to call [{}], {}", &callback_name,  other.to_string()).into(),
                     };
                     let js_exception =
                         convert::serialize_value(context, js_exception_value).unwrap();
@@ -726,14 +1084,41 @@ impl ContextWrapper {
             }
         };
 
-        let (pair, trampoline) = unsafe { build_closure_trampoline(wrapper) };
-        let data = (&*pair.1) as *const q::JSValue as *mut q::JSValue;
-        self.callbacks.lock().unwrap().push(pair);
-
+        let class_id = CallbackFunction::register(self.runtime);
         let obj = unsafe {
-            let f = q::JS_NewCFunctionData(self.context, trampoline, argcount, 0, 1, data);
+            let proto = q::JS_GetFunctionProto(self.context);
+            let f = q::JS_NewObjectProtoClass(self.context, proto, class_id);
+            q::JS_FreeValue(self.context, proto);
             OwnedJsValue::new(self, f)
         };
+        if obj.is_exception() {
+            return Err(ExecutionError::Internal(format!("Could not create function '{}'", name)));
+        }
+
+        let closure: Box<WrappedCallback> = Box::new(wrapper);
+        unsafe {
+            q::JS_SetOpaque(obj.value, Box::into_raw(Box::new(closure)) as *mut c_void);
+            if constructor {
+                // Allow `new`, the callback can inspect `new.target` via its CallContext.
+                q::JS_SetConstructorBit(self.context, obj.value, true);
+            }
+        }
+
+        // Like native functions, `length` and `name` are read-only but configurable.
+        let props = vec![("length", JsValue::Int(argcount)), ("name", JsValue::String(name.into()))];
+        for (prop, value) in props {
+            let cprop = make_cstring(prop)?;
+            let qvalue = convert::serialize_value(self.context, value)?;
+            unsafe {
+                q::JS_DefinePropertyValueStr(
+                    self.context,
+                    obj.value,
+                    cprop.as_ptr(),
+                    qvalue,
+                    q::JS_PROP_CONFIGURABLE as i32,
+                );
+            }
+        }
 
         let f = obj.try_into_function()?;
         Ok(f)
@@ -745,12 +1130,110 @@ impl ContextWrapper {
         callback: impl Callback<F> + 'static,
     ) -> Result<(), ExecutionError> {
         let cfunc = self.create_callback(name, callback)?;
+        self.set_callback_global(name, cfunc)
+    }
+
+    /// Add a global JS function that can also be invoked with `new`, see
+    /// [ContextWrapper::create_constructor].
+    pub fn add_constructor<'a, F>(
+        &'a self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+    ) -> Result<(), ExecutionError> {
+        let cfunc = self.create_constructor(name, callback)?;
+        self.set_callback_global(name, cfunc)
+    }
+
+    fn set_callback_global(&self, name: &str, function: JsFunction) -> Result<(), ExecutionError> {
+        let global = self.global()?;
+        global.set_property(name, function.into_value())?;
+        unsafe { RuntimeState::get(self.runtime) }.callbacks.borrow_mut().insert(name.to_string());
+        Ok(())
+    }
+
+    /// Add a global JS function that is backed by a `FnMut` closure.
+    pub fn add_callback_mut<'a, F>(
+        &'a self,
+        name: &str,
+        callback: impl CallbackMut<F> + 'static,
+    ) -> Result<(), ExecutionError> {
+        self.add_callback(name, MutCallback::new(callback))
+    }
+
+    /// Remove a global callback function.
+    ///
+    /// The Rust closure is dropped as soon as the function object is no
+    /// longer referenced from Javascript.
+    /// Only globals added by [ContextWrapper::add_callback] that still hold
+    /// a callback function are removed.
+    pub fn remove_callback(&self, name: &str) -> Result<(), ExecutionError> {
+        let state = unsafe { RuntimeState::get(self.runtime) };
+        if !state.callbacks.borrow_mut().remove(name) {
+            return Err(ExecutionError::Internal(format!("'{}' is not a callback", name)));
+        }
         let global = self.global()?;
-        global.set_property(name, cfunc.into_value())?;
+        let is_callback = global.property(name)?.is_some_and(|value| unsafe {
+            q::JS_GetClassID(value.value) == CallbackFunction::class_id().id.get()
+        });
+        if !is_callback {
+            return Err(ExecutionError::Internal(format!("'{}' was replaced by a script", name)));
+        }
+        if !global.delete_property(name)? {
+            return Err(ExecutionError::Internal(format!("Could not delete callback '{}'", name)));
+        }
+        Ok(())
+    }
+
+    /// Set the prototype of resources that hold a value of the given type.
+    pub(crate) fn set_class_prototype(&self, type_id: TypeId, prototype: OwnedJsValue) {
+        let state = unsafe { RuntimeState::get(self.runtime) };
+        let prototype = unsafe { prototype.extract() };
+        if let Some(old) = state.class_prototypes.borrow_mut().insert(type_id, prototype) {
+            unsafe { q::JS_FreeValue(self.context, old) };
+        }
+    }
+
+    /// The TypeScript declarations of the host API.
+    pub(crate) fn declarations(&self) -> &RefCell<Declarations> {
+        &unsafe { RuntimeState::get(self.runtime) }.declarations
+    }
+
+    /// Add a native ES module, whose exports are created by `init` when it
+    /// is first imported.
+    pub(crate) fn add_native_module(
+        &self,
+        name: &str,
+        exports: &[&str],
+        init: Rc<ModuleInit>,
+    ) -> Result<(), ExecutionError> {
+        let state = unsafe { RuntimeState::get(self.runtime) };
+        if state.native_modules.borrow().contains_key(name) {
+            return Err(ExecutionError::Internal(format!("Module '{}' is already defined", name)));
+        }
+        let cname = make_cstring(name)?;
+        unsafe {
+            let m = q::JS_NewCModule(self.context, cname.as_ptr(), Some(native_module_init));
+            if m.is_null() {
+                return Err(ExecutionError::Internal(format!("Could not create module '{}'", name)));
+            }
+            for export in exports {
+                let cexport = make_cstring(*export)?;
+                if q::JS_AddModuleExport(self.context, m, cexport.as_ptr()) < 0 {
+                    return Err(ExecutionError::Internal(format!(
+                        "Could not add export '{}' to module '{}'",
+                        export, name
+                    )));
+                }
+            }
+        }
+        state.native_modules.borrow_mut().insert(name.to_string(), init);
         Ok(())
     }
 
     /// return Ok(false) if no job pending, Ok(true) if a job was executed successfully.
+    ///
+    /// When no job is pending, the woken streams are polled, settling one of
+    /// their promises counts as executing a job.
     pub fn execute_pending_job(&self) -> Result<bool, ExecutionError> {
         let mut job_ctx = null_mut();
         let flag = unsafe {
@@ -763,7 +1246,14 @@ impl ContextWrapper {
             });
             return Err(e);
         }
-        Ok(flag != 0)
+        if flag == 0 {
+            return Ok(unsafe { RuntimeState::get(self.runtime) }.poll_streams());
+        }
+        Ok(true)
+    }
+
+    pub fn run_gc(&self) {
+        unsafe { q::JS_RunGC(self.runtime) };
     }
 
     pub fn execute_module(&self, module_name: &str) -> Result<(), ExecutionError> {
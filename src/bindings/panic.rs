@@ -0,0 +1,111 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::sync::Once;
+
+use libquickjs_sys as q;
+
+use crate::PanicPolicy;
+
+use super::RuntimeState;
+
+thread_local! {
+    /// Location of the last panic on this thread, recorded by the panic hook.
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Install a panic hook that records the panic location, because the payload
+/// caught by `catch_unwind` does not carry it, see
+/// [PanicPolicy::record_locations].
+///
+/// The previously installed hook is still invoked.
+pub(crate) fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let location = info.location().map(|l| l.to_string());
+            let _ = PANIC_LOCATION.try_with(|p| *p.borrow_mut() = location);
+            previous(info);
+        }));
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Throw a JS error for a panic in the callback `name` and return the
+/// exception marker.
+///
+/// With [PanicPolicy::Resume] the error is uncatchable and the payload is
+/// kept by the runtime until the exception reaches Rust, see
+/// [resume_pending_panic].
+pub(crate) fn throw_panic(
+    context: *mut q::JSContext,
+    policy: PanicPolicy,
+    name: &str,
+    payload: Box<dyn Any + Send>,
+) -> q::JSValue {
+    let location = PANIC_LOCATION.with(|p| p.borrow_mut().take());
+    let message = match location {
+        Some(location) => format!(
+            "Callback [{}] panicked at {}: {}",
+            name,
+            location,
+            panic_message(&*payload)
+        ),
+        None => format!("Callback [{}] panicked: {}", name, panic_message(&*payload)),
+    };
+
+    unsafe {
+        let error = q::JS_NewError(context);
+        let qmessage =
+            q::JS_NewStringLen(context, message.as_ptr() as *const _, message.len() as _);
+        q::JS_DefinePropertyValueStr(
+            context,
+            error,
+            CStr::from_bytes_with_nul(b"message\0").unwrap().as_ptr(),
+            qmessage,
+            (q::JS_PROP_WRITABLE | q::JS_PROP_CONFIGURABLE) as i32,
+        );
+        if let PanicPolicy::Resume = policy {
+            q::JS_SetUncatchableError(context, error);
+            RuntimeState::get(q::JS_GetRuntime(context)).pending_panic.replace(Some(payload));
+        }
+        q::JS_Throw(context, error);
+    }
+    q::JS_MKVAL(q::JS_TAG_EXCEPTION, 0)
+}
+
+/// Continue unwinding a callback panic that propagated out of Javascript as
+/// `exception`.
+///
+/// A pending panic whose error was consumed elsewhere, e.g. by a promise,
+/// is dropped instead, so it is never resumed by an unrelated exception.
+///
+/// Safety: the context must have been created by [super::ContextWrapper].
+pub(crate) unsafe fn resume_pending_panic(context: *mut q::JSContext, exception: q::JSValue) {
+    let payload = RuntimeState::get(q::JS_GetRuntime(context)).pending_panic.take();
+    if let Some(payload) = payload {
+        if q::JS_IsUncatchableError(context, exception) {
+            std::panic::resume_unwind(payload);
+        }
+    }
+}
+
+/// Free the pending exception of an operation whose failure is reported
+/// otherwise, along with the panic that raised it.
+///
+/// Safety: the context must have been created by [super::ContextWrapper].
+pub(crate) unsafe fn discard_exception(context: *mut q::JSContext) {
+    let exception = q::JS_GetException(context);
+    drop(RuntimeState::get(q::JS_GetRuntime(context)).pending_panic.take());
+    q::JS_FreeValue(context, exception);
+}
@@ -210,7 +210,7 @@ pub struct OwnedJsValue<'a> {
 
 impl<'a> OwnedJsValue<'a> {
     #[inline]
-    pub(crate) fn context(&self) -> &ContextWrapper {
+    pub(crate) fn context(&self) -> &'a ContextWrapper {
         self.context
     }
 
@@ -305,6 +305,12 @@ impl<'a> OwnedJsValue<'a> {
         self.context.to_value(&self.value)
     }
 
+    /// Serialize this value into a [`JsValue`], converting plain objects
+    /// to [`JsValue::Object`] instead of keeping them as [`JsValue::Raw`].
+    pub(crate) fn to_value_deep(&self) -> Result<JsValue, ValueError> {
+        self.context.to_value_deep(&self.value)
+    }
+
     pub(crate) fn to_bool(&self) -> Result<bool, ValueError> {
         match self.to_value()? {
             JsValue::Bool(b) => Ok(b),
@@ -316,6 +322,10 @@ impl<'a> OwnedJsValue<'a> {
         OwnedJsObject::try_from_value(self)
     }
 
+    pub(crate) fn try_into_array(self) -> Result<OwnedJsArray<'a>, ValueError> {
+        OwnedJsArray::new(self).ok_or_else(|| ValueError::Internal("Expected an array".into()))
+    }
+
     pub(crate) fn try_into_function(self) -> Result<JsFunction<'a>, ValueError> {
         JsFunction::try_from_value(self)
     }
@@ -336,6 +346,9 @@ impl<'a> OwnedJsValue<'a> {
             let raw = unsafe { q::JS_ToString(self.context.context, self.value) };
             let value = OwnedJsValue::new(self.context, raw);
 
+            if value.is_exception() {
+                return Err(self.exception("convert value to string"));
+            }
             if !value.is_string() {
                 return Err(ExecutionError::Exception(
                     "Could not convert value to string".into(),
@@ -347,6 +360,118 @@ impl<'a> OwnedJsValue<'a> {
         Ok(value.as_str().unwrap().to_string())
     }
 
+    /// The pending exception, after a QuickJS API reported one.
+    fn exception(&self, operation: &str) -> ExecutionError {
+        self.context
+            .get_exception()
+            .unwrap_or_else(|| ExecutionError::Internal(format!("Could not {}", operation)))
+    }
+
+    /// Values of different contexts can not be combined.
+    fn same_context(&self, other: &OwnedJsValue) -> Result<(), ExecutionError> {
+        if self.context.context == other.context.context {
+            Ok(())
+        } else {
+            Err(ExecutionError::InvalidHandle)
+        }
+    }
+
+    /// Convert to a number like `+value`, see `JS_ToFloat64`.
+    pub fn to_number(&self) -> Result<f64, ExecutionError> {
+        let mut number = 0.0;
+        if unsafe { q::JS_ToFloat64(self.context.context, &mut number, self.value) } < 0 {
+            return Err(self.exception("convert value to number"));
+        }
+        Ok(number)
+    }
+
+    /// Convert to a 32 bit integer like `value | 0`, see `JS_ToInt32`.
+    pub fn to_int32(&self) -> Result<i32, ExecutionError> {
+        let mut number = 0;
+        if unsafe { q::JS_ToInt32(self.context.context, &mut number, self.value) } < 0 {
+            return Err(self.exception("convert value to int32"));
+        }
+        Ok(number)
+    }
+
+    /// Convert to a boolean like `!!value`, see `JS_ToBool`.
+    pub fn to_boolean(&self) -> Result<bool, ExecutionError> {
+        match unsafe { q::JS_ToBool(self.context.context, self.value) } {
+            ret if ret < 0 => Err(self.exception("convert value to boolean")),
+            ret => Ok(ret != 0),
+        }
+    }
+
+    /// Serialize to JSON like `JSON.stringify(value, null, indent)`, see
+    /// `JS_JSONStringify`.
+    ///
+    /// Fails for values that have no JSON representation, like `undefined`
+    /// or functions.
+    pub fn to_json(&self, indent: usize) -> Result<String, ExecutionError> {
+        let context = self.context.context;
+        // Like in Javascript, the indentation is at most 10 spaces.
+        let indent = q::JS_MKVAL(q::JS_TAG_INT, indent.min(10) as i32);
+        let raw = unsafe { q::JS_JSONStringify(context, self.value, q::JS_UNDEFINED, indent) };
+        let json = OwnedJsValue::new(self.context, raw);
+        if json.is_exception() {
+            return Err(self.exception("convert value to JSON"));
+        }
+        if !json.is_string() {
+            return Err(ExecutionError::Internal(format!(
+                "Value of type {} can not be represented as JSON",
+                self.type_of()
+            )));
+        }
+        json.js_to_string()
+    }
+
+    /// The result of the `typeof` operator.
+    pub fn type_of(&self) -> &'static str {
+        match unsafe { q::JS_VALUE_GET_TAG(self.value) } {
+            q::JS_TAG_NULL => "object",
+            q::JS_TAG_BOOL => "boolean",
+            q::JS_TAG_INT | q::JS_TAG_FLOAT64 => "number",
+            q::JS_TAG_BIG_INT => "bigint",
+            q::JS_TAG_STRING => "string",
+            q::JS_TAG_SYMBOL => "symbol",
+            q::JS_TAG_OBJECT if unsafe { q::JS_IsFunction(self.context.context, self.value) } => "function",
+            q::JS_TAG_OBJECT => "object",
+            _ => "undefined",
+        }
+    }
+
+    /// Check `value instanceof constructor`, see `JS_IsInstanceOf`.
+    pub fn instance_of(&self, constructor: &OwnedJsValue) -> Result<bool, ExecutionError> {
+        self.same_context(constructor)?;
+        match unsafe { q::JS_IsInstanceOf(self.context.context, self.value, constructor.value) } {
+            ret if ret < 0 => Err(self.exception("check instanceof")),
+            ret => Ok(ret != 0),
+        }
+    }
+
+    /// Compare with `===`, see `JS_IsStrictEqual`.
+    pub fn strict_equals(&self, other: &OwnedJsValue) -> Result<bool, ExecutionError> {
+        self.same_context(other)?;
+        Ok(unsafe { q::JS_IsStrictEqual(self.context.context, self.value, other.value) })
+    }
+
+    /// Compare like `Object.is`, see `JS_IsSameValue`.
+    pub fn same_value(&self, other: &OwnedJsValue) -> Result<bool, ExecutionError> {
+        self.same_context(other)?;
+        Ok(unsafe { q::JS_IsSameValue(self.context.context, self.value, other.value) })
+    }
+
+    /// Compare with `==`, see `JS_IsEqual`.
+    ///
+    /// Objects are converted to primitives, which can run Javascript code.
+    pub fn loose_equals(&self, other: &OwnedJsValue) -> Result<bool, ExecutionError> {
+        self.same_context(other)?;
+        match unsafe { q::JS_IsEqual(self.context.context, self.value, other.value) } {
+            ret if ret < 0 => Err(self.exception("compare values")),
+            ret => Ok(ret != 0),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) fn get_ref_count(&self) -> i32 {
         if self.value.tag < 0 {
@@ -385,6 +510,9 @@ impl<'a> std::fmt::Debug for OwnedJsValue<'a> {
     }
 }
 
+/// Wraps an array from the QuickJs runtime.
+/// Provides element accessors.
+#[derive(Clone, Debug)]
 pub struct OwnedJsArray<'a> {
     value: OwnedJsValue<'a>,
 }
@@ -397,6 +525,56 @@ impl<'a> OwnedJsArray<'a> {
             None
         }
     }
+
+    pub fn as_value(&self) -> &OwnedJsValue<'a> {
+        &self.value
+    }
+
+    pub fn into_value(self) -> OwnedJsValue<'a> {
+        self.value
+    }
+
+    /// The `length` of the array.
+    pub fn len(&self) -> Result<usize, ExecutionError> {
+        let mut len = 0;
+        let ret = unsafe { q::JS_GetLength(self.value.context.context, self.value.value, &mut len) };
+        if ret < 0 {
+            return Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal("Could not get array length".into())
+            }));
+        }
+        Ok(len as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, ExecutionError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Get the element at `index`, `undefined` if it is out of bounds.
+    pub fn element(&self, index: u32) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        let raw = unsafe { q::JS_GetPropertyUint32(self.value.context.context, self.value.value, index) };
+        let value = OwnedJsValue::new(self.value.context, raw);
+        if value.is_exception() {
+            return Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Exception while getting element {}", index))
+            }));
+        }
+        Ok(value)
+    }
+
+    pub fn set_element(&self, index: u32, value: OwnedJsValue<'a>) -> Result<(), ExecutionError> {
+        // JS_SetPropertyUint32 takes ownership of the value, even if it fails.
+        let ret = unsafe {
+            q::JS_SetPropertyUint32(self.value.context.context, self.value.value, index, value.extract())
+        };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Exception("Could not set element".into())
+            }))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// Wraps an object from the QuickJs runtime.
@@ -415,6 +593,10 @@ impl<'a> OwnedJsObject<'a> {
         }
     }
 
+    pub fn as_value(&self) -> &OwnedJsValue<'a> {
+        &self.value
+    }
+
     pub fn into_value(self) -> OwnedJsValue<'a> {
         self.value
     }
@@ -431,10 +613,9 @@ impl<'a> OwnedJsObject<'a> {
         let tag = value.tag();
 
         if tag.is_exception() {
-            Err(ExecutionError::Internal(format!(
-                "Exception while getting property '{}'",
-                name
-            )))
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Exception while getting property '{}'", name))
+            }))
         } else if tag.is_undefined() {
             Ok(None)
         } else {
@@ -463,26 +644,127 @@ impl<'a> OwnedJsObject<'a> {
         Ok(false)
     }
 
+    /// Check if the object or its prototype chain has a property, like the
+    /// `in` operator.
+    pub fn has_property(&self, name: &str) -> Result<bool, ExecutionError> {
+        let cname = make_cstring(name)?;
+        let ret = unsafe {
+            let atom = q::JS_NewAtom(self.value.context.context, cname.as_ptr());
+            let ret = q::JS_HasProperty(self.value.context.context, self.value.value, atom);
+            q::JS_FreeAtom(self.value.context.context, atom);
+            ret
+        };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Exception("Could not check property".into())
+            }))
+        } else {
+            Ok(ret == 1)
+        }
+    }
+
+    /// The keys of the enumerable string-keyed own properties.
+    pub fn property_keys(&self) -> Result<Vec<String>, ExecutionError> {
+        Ok(super::convert::property_keys(self.value.context.context, &self.value.value)?)
+    }
+
+    /// Define a property, see `JS_DefineProperty`.
+    ///
+    /// Does not take ownership of the values.
+    pub fn define_property(
+        &self,
+        name: &str,
+        value: &OwnedJsValue<'a>,
+        getter: &OwnedJsValue<'a>,
+        setter: &OwnedJsValue<'a>,
+        flags: u32,
+    ) -> Result<(), ExecutionError> {
+        let cname = make_cstring(name)?;
+        let context = self.value.context.context;
+        let ret = unsafe {
+            let atom = q::JS_NewAtom(context, cname.as_ptr());
+            let ret = q::JS_DefineProperty(
+                context,
+                self.value.value,
+                atom,
+                value.value,
+                getter.value,
+                setter.value,
+                (flags | q::JS_PROP_THROW) as i32,
+            );
+            q::JS_FreeAtom(context, atom);
+            ret
+        };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Could not define property '{}'", name))
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply `JS_FreezeObject`, `JS_SealObject` or `JS_PreventExtensions`.
+    pub(crate) fn restrict(
+        &self,
+        f: unsafe extern "C" fn(*mut q::JSContext, q::JSValue) -> std::os::raw::c_int,
+    ) -> Result<(), ExecutionError> {
+        let ret = unsafe { f(self.value.context.context, self.value.value) };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal("Could not restrict object".into())
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_extensible(&self) -> Result<bool, ExecutionError> {
+        let ret = unsafe { q::JS_IsExtensible(self.value.context.context, self.value.value) };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal("Could not check object".into())
+            }))
+        } else {
+            Ok(ret == 1)
+        }
+    }
+
+    /// Delete a property, returns `false` if the property could not be deleted.
+    pub fn delete_property(&self, name: &str) -> Result<bool, ExecutionError> {
+        let cname = make_cstring(name)?;
+        let ret = unsafe {
+            let atom = q::JS_NewAtom(self.value.context.context, cname.as_ptr());
+            let ret = q::JS_DeleteProperty(self.value.context.context, self.value.value, atom, 0);
+            q::JS_FreeAtom(self.value.context.context, atom);
+            ret
+        };
+        if ret < 0 {
+            Err(self.value.context.get_exception().unwrap_or_else(|| {
+                ExecutionError::Exception("Could not delete property".into())
+            }))
+        } else {
+            Ok(ret == 1)
+        }
+    }
+
     pub fn set_property(&self, name: &str, value: OwnedJsValue<'a>) -> Result<(), ExecutionError> {
         let cname = make_cstring(name)?;
         unsafe {
-            // NOTE: SetPropertyStr takes ownership of the value.
-            // We do not, however, call OwnedJsValue::extract immediately, so
-            // the inner JSValue is still managed.
-            // `mem::forget` is called below only if SetProperty succeeds.
-            // This prevents leaks when an error occurs.
+            // NOTE: SetPropertyStr takes ownership of the value, even if it
+            // fails.
             let ret = q::JS_SetPropertyStr(
                 self.value.context.context,
                 self.value.value,
                 cname.as_ptr(),
-                value.value,
+                value.extract(),
             );
 
             if ret < 0 {
-                Err(ExecutionError::Exception("Could not set property".into()))
+                Err(self.value.context.get_exception().unwrap_or_else(|| {
+                    ExecutionError::Exception("Could not set property".into())
+                }))
             } else {
-                // Now we can call forget to prevent calling the destructor.
-                std::mem::forget(value);
                 Ok(())
             }
         }
@@ -513,13 +795,22 @@ impl<'a> JsFunction<'a> {
     }
 
     pub fn call(&self, args: Vec<OwnedJsValue<'a>>) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        self.call_with_this(&OwnedJsValue::new(self.value.context, q::JS_NULL), args)
+    }
+
+    /// Call the function with `this` bound to the given value.
+    pub fn call_with_this(
+        &self,
+        this: &OwnedJsValue<'a>,
+        args: Vec<OwnedJsValue<'a>>,
+    ) -> Result<OwnedJsValue<'a>, ExecutionError> {
         let mut qargs = args.iter().map(|arg| arg.value).collect::<Vec<_>>();
 
         let qres_raw = unsafe {
             q::JS_Call(
                 self.value.context.context,
                 self.value.value,
-                q::JS_NULL,
+                this.value,
                 qargs.len() as i32,
                 qargs.as_mut_ptr(),
             )
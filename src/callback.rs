@@ -1,6 +1,12 @@
-use std::{convert::TryFrom, marker::PhantomData, panic::RefUnwindSafe};
+use std::{cell::RefCell, convert::TryFrom, marker::PhantomData, mem::ManuallyDrop, os::raw::c_int};
 
+use libquickjs_sys as q;
+
+use crate::bindings::convert::{borrow_buffer, deserialize_value};
+use crate::bindings::{make_cstring, ContextWrapper, OwnedJsValue};
+use crate::typescript::TsFunction;
 use crate::value::{JsValue, ValueError};
+use crate::{Context, ExecutionError};
 
 pub trait IntoCallbackResult {
     fn into_callback_res(self) -> Result<JsValue, String>;
@@ -23,7 +29,10 @@ impl<T: Into<JsValue>, E: std::fmt::Display> IntoCallbackResult for Result<T, E>
 
 /// The Callback trait is implemented for functions/closures that can be
 /// used as callbacks in the JS runtime.
-pub trait Callback<F>: RefUnwindSafe {
+///
+/// Panics are caught when the callback is invoked from Javascript, so
+/// callbacks do not have to be `RefUnwindSafe`.
+pub trait Callback<F> {
     /// Returns the number of required Javascript arguments.
     fn argument_count(&self) -> usize;
 
@@ -35,133 +44,583 @@ pub trait Callback<F>: RefUnwindSafe {
     ///       The given error will be raised as a JS exception.
     ///   - Ok(Ok(result)) when execution succeeded.
     fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError>;
+
+    /// Execute the callback with access to the calling context.
+    ///
+    /// The default implementation converts the arguments and delegates
+    /// to [Callback::call].
+    fn call_with_context(&self, call: &CallContext) -> Result<Result<JsValue, String>, ValueError> {
+        self.call(call.args()?)
+    }
+
+    /// The TypeScript signature of the callback.
+    ///
+    /// The default implementation declares [Callback::argument_count]
+    /// parameters of type `any`.
+    fn declaration(&self) -> TsFunction {
+        TsFunction::from_params(&vec![(true, false); self.argument_count()])
+    }
 }
 
-macro_rules! impl_callback {
-    (@call $len:literal $self:ident $args:ident ) => {
-        $self()
-    };
+/// The CallbackMut trait is implemented for `FnMut` closures, which can be
+/// used as callbacks with [Context::add_callback_mut].
+///
+/// A mutable callback can not be re-entered: calling it from Javascript
+/// while it is already running raises an error.
+pub trait CallbackMut<F> {
+    /// Returns the number of required Javascript arguments.
+    fn argument_count(&self) -> usize;
 
-    (@call $len:literal $self:ident $args:ident $( $arg:ident ),* ) => {
-        {
-            let mut iter = $args.into_iter();
-            $self(
-                $(
-                    $arg::try_from(iter.next().unwrap())?,
-                )*
-            )
-        }
-    };
+    /// Execute the callback, see [Callback::call].
+    fn call_mut(&mut self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError>;
 
-    [ $(  $len:literal : ( $( $arg:ident, )* ), )* ] => {
-        $(
+    /// Execute the callback with access to the calling context.
+    fn call_mut_with_context(&mut self, call: &CallContext) -> Result<Result<JsValue, String>, ValueError> {
+        self.call_mut(call.args()?)
+    }
 
-            impl<
-                $( $arg, )*
-                E,
-                R,
-                F,
-            > Callback<PhantomData<(
-                $( &$arg, )*
-                &E,
-                &R,
-                &F,
-            )>> for F
-            where
-                $( $arg: TryFrom<JsValue, Error = E>, )*
-                ValueError: From<E>,
-                R: IntoCallbackResult,
-                F: Fn( $( $arg, )*  ) -> R + Sized + RefUnwindSafe,
-            {
-                fn argument_count(&self) -> usize {
-                    $len
-                }
+    /// The TypeScript signature of the callback, see [Callback::declaration].
+    fn declaration(&self) -> TsFunction {
+        TsFunction::from_params(&vec![(true, false); self.argument_count()])
+    }
+}
 
-                fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
-                    if args.len() != $len {
-                        return Ok(Err(format!(
-                            "Invalid argument count: Expected {}, got {}",
-                            self.argument_count(),
-                            args.len()
-                        )));
-                    }
-
-                    let res = impl_callback!(@call $len self args $($arg),* );
-                    Ok(res.into_callback_res())
-                }
-            }
-        )*
-    };
+/// Adapts a [CallbackMut] to [Callback] by rejecting re-entrant calls.
+pub(crate) struct MutCallback<C>(RefCell<C>);
+
+impl<C> MutCallback<C> {
+    pub(crate) fn new(callback: C) -> Self {
+        Self(RefCell::new(callback))
+    }
 }
 
-impl<R, F> Callback<PhantomData<(&R, &F)>> for F
+const REENTRANT_CALL_ERROR: &str = "Callback is already running and can not be called re-entrantly";
+
+impl<C, F> Callback<F> for MutCallback<C>
 where
-    R: IntoCallbackResult,
-    F: Fn() -> R + Sized + RefUnwindSafe,
+    C: CallbackMut<F>,
 {
     fn argument_count(&self) -> usize {
-        0
+        self.0.borrow().argument_count()
     }
 
     fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
-        if args.len() != 0 {
-            return Ok(Err(format!(
-                "Invalid argument count: Expected 0, got {}",
-                args.len(),
-            )));
+        match self.0.try_borrow_mut() {
+            Ok(mut callback) => callback.call_mut(args),
+            Err(_) => Ok(Err(REENTRANT_CALL_ERROR.into())),
+        }
+    }
+
+    fn call_with_context(&self, call: &CallContext) -> Result<Result<JsValue, String>, ValueError> {
+        match self.0.try_borrow_mut() {
+            Ok(mut callback) => callback.call_mut_with_context(call),
+            Err(_) => Ok(Err(REENTRANT_CALL_ERROR.into())),
         }
+    }
 
-        let res = self();
-        Ok(res.into_callback_res())
+    fn declaration(&self) -> TsFunction {
+        self.0.borrow().declaration()
     }
 }
 
-impl_callback![
-    1: (A1,),
-    2: (A1, A2,),
-    3: (A1, A2, A3,),
-    4: (A1, A2, A3, A4,),
-    5: (A1, A2, A3, A4, A5,),
-];
+/// Marker types that select how a [CallbackArg] consumes arguments.
+#[doc(hidden)]
+pub mod marker {
+    use std::marker::PhantomData;
 
-/// A wrapper around Vec<JsValue>, used for vararg callbacks.
+    /// A value converted with `TryFrom<JsValue>`, whose error converts to
+    /// `ValueError`.
+    pub struct Value<E>(PhantomData<E>);
+    /// An `Option<T>` that may be omitted.
+    pub struct Optional<M>(PhantomData<M>);
+    /// A trailing `Rest<T>`.
+    pub struct Variadic<M>(PhantomData<M>);
+
+    /// The markers of parameters that can be followed by others, which
+    /// is every parameter but `Rest<T>`.
+    pub trait Positional {}
+
+    impl<E> Positional for Value<E> {}
+    impl<M: Positional> Positional for Optional<M> {}
+}
+
+pub(crate) type ArgIter = std::iter::Peekable<std::vec::IntoIter<JsValue>>;
+
+/// A type that can be used as a callback parameter.
 ///
-/// To create a callback with a variable number of arguments, a callback closure
-/// must take a single `Arguments` argument.
-pub struct Arguments(Vec<JsValue>);
+/// Implemented for:
+///   - every `T: TryFrom<JsValue>` whose error converts to `ValueError`,
+///     including `JsValue`, which are required arguments
+///   - `Option<T>`, which is `None` if the argument is missing or `undefined`.
+///     `Option<JsValue>` is ambiguous, because `JsValue` converts into it:
+///     use a `JsValue` parameter, or a [CallContext] callback for an
+///     optional untyped argument
+///   - `Rest<T>`, which collects all remaining arguments and must be the
+///     last parameter
+pub trait CallbackArg<M>: Sized {
+    /// Whether the caller has to pass this argument.
+    const REQUIRED: bool = true;
+    /// Whether this parameter consumes all remaining arguments.
+    const VARIADIC: bool = false;
 
-impl Arguments {
-    /// Unpack the arguments into a Vec.
-    pub fn into_vec(self) -> Vec<JsValue> {
-        self.0
+    #[doc(hidden)]
+    fn from_args(args: &mut ArgIter) -> Result<Self, ValueError>;
+}
+
+impl<T, E> CallbackArg<marker::Value<E>> for T
+where
+    T: TryFrom<JsValue, Error = E>,
+    ValueError: From<E>,
+{
+    fn from_args(args: &mut ArgIter) -> Result<Self, ValueError> {
+        Ok(T::try_from(args.next().unwrap_or(JsValue::Undefined))?)
     }
 }
 
-impl<F> Callback<PhantomData<(&Arguments, &F)>> for F
+impl<T, M> CallbackArg<marker::Optional<M>> for Option<T>
 where
-    F: Fn(Arguments) + Sized + RefUnwindSafe,
+    T: CallbackArg<M>,
 {
-    fn argument_count(&self) -> usize {
-        0
+    const REQUIRED: bool = false;
+
+    fn from_args(args: &mut ArgIter) -> Result<Self, ValueError> {
+        match args.peek() {
+            None => Ok(None),
+            Some(JsValue::Undefined) => {
+                args.next();
+                Ok(None)
+            }
+            Some(_) => T::from_args(args).map(Some),
+        }
     }
+}
 
-    fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
-        (self)(Arguments(args));
-        Ok(Ok(JsValue::Undefined))
+/// The trailing arguments of a callback.
+///
+/// A callback whose last parameter is a `Rest<T>` accepts any number of
+/// additional arguments, each converted to `T`.
+///
+/// ```rust
+/// use deft_quick_js::{Context, Rest};
+/// let context = Context::new().unwrap();
+///
+/// context.add_callback("join", |sep: String, parts: Rest<String>| parts.0.join(&sep)).unwrap();
+/// let joined = context.eval_as::<String>("join('-', 'a', 'b')", "join.js").unwrap();
+/// assert_eq!(joined, "a-b");
+/// ```
+///
+/// A `Rest<T>` before other parameters is rejected at compile time:
+///
+/// ```compile_fail
+/// use deft_quick_js::{Context, Rest};
+/// let context = Context::new().unwrap();
+///
+/// context.add_callback("join", |parts: Rest<String>, sep: String| parts.0.join(&sep)).unwrap();
+/// ```
+pub struct Rest<T>(pub Vec<T>);
+
+impl<T> Rest<T> {
+    /// Unpack the arguments into a Vec.
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
     }
 }
 
-impl<F, R> Callback<PhantomData<(&Arguments, &F, &R)>> for F
+impl<T, M> CallbackArg<marker::Variadic<M>> for Rest<T>
 where
-    R: IntoCallbackResult,
-    F: Fn(Arguments) -> R + Sized + RefUnwindSafe,
+    T: CallbackArg<M>,
 {
-    fn argument_count(&self) -> usize {
-        0
+    const REQUIRED: bool = false;
+    const VARIADIC: bool = true;
+
+    fn from_args(args: &mut ArgIter) -> Result<Self, ValueError> {
+        let mut items = Vec::new();
+        while args.peek().is_some() {
+            items.push(T::from_args(args)?);
+        }
+        Ok(Rest(items))
     }
+}
 
-    fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
-        let res = (self)(Arguments(args));
-        Ok(res.into_callback_res())
+/// Check the number of passed arguments against the callback parameters.
+pub(crate) fn check_argument_count(required: &[bool], variadic: bool, count: usize) -> Result<(), String> {
+    let min = required.iter().filter(|r| **r).count();
+    let max = required.len();
+    if count >= min && (variadic || count <= max) {
+        return Ok(());
+    }
+    let expected = if variadic {
+        format!("at least {}", min)
+    } else if min == max {
+        min.to_string()
+    } else {
+        format!("{} to {}", min, max)
+    };
+    Err(format!(
+        "Invalid argument count: Expected {}, got {}",
+        expected, count
+    ))
+}
+
+/// The context of a callback invocation.
+///
+/// A callback closure that takes a single `&CallContext` argument gets access
+/// to `this`, the unconverted arguments, `new.target` and the calling
+/// [Context], so it can re-enter the runtime (e.g. with `eval` or
+/// `call_js_function`) while it runs.
+///
+/// ```rust
+/// use deft_quick_js::{CallContext, Context};
+/// let context = Context::new().unwrap();
+///
+/// context.add_callback("getName", |call: &CallContext| {
+///     call.this_property("name")
+/// }).unwrap();
+///
+/// let name = context.eval_as::<String>("({ name: 'x', getName }).getName()", "call.js").unwrap();
+/// assert_eq!(name, "x");
+/// ```
+pub struct CallContext<'a> {
+    context: ManuallyDrop<Context>,
+    this: q::JSValue,
+    args: &'a [q::JSValue],
+    is_construct_call: bool,
+}
+
+impl<'a> CallContext<'a> {
+    /// Safety: `argv` must point to `argc` values that outlive `'a`.
+    pub(crate) unsafe fn new(
+        context: *mut q::JSContext,
+        this: q::JSValue,
+        argc: c_int,
+        argv: *mut q::JSValue,
+        flags: c_int,
+    ) -> Self {
+        let args = if argc > 0 {
+            std::slice::from_raw_parts(argv, argc as usize)
+        } else {
+            &[]
+        };
+        Self {
+            // The context is owned by the caller, so it must never be dropped here.
+            context: ManuallyDrop::new(Context::from_wrapper(ContextWrapper::from_raw(context))),
+            this,
+            args,
+            is_construct_call: flags & q::JS_CALL_FLAG_CONSTRUCTOR as c_int != 0,
+        }
+    }
+
+    pub(crate) fn raw_context(&self) -> *mut q::JSContext {
+        self.context.wrapper.context
+    }
+
+    /// The raw `this` value, which is `new.target` for construct calls.
+    pub(crate) fn raw_this(&self) -> q::JSValue {
+        self.this
+    }
+
+    pub(crate) fn is_construct_call(&self) -> bool {
+        self.is_construct_call
+    }
+
+    /// The context the callback was called from.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// The `this` value of the call.
+    ///
+    /// Always `undefined` when the callback is invoked with `new`.
+    pub fn this(&self) -> Result<JsValue, ValueError> {
+        if self.is_construct_call {
+            return Ok(JsValue::Undefined);
+        }
+        deserialize_value(self.raw_context(), &self.this)
+    }
+
+    /// Read a property of `this`.
+    pub fn this_property(&self, name: &str) -> Result<JsValue, ExecutionError> {
+        let this = if self.is_construct_call { q::JS_UNDEFINED } else { self.this };
+        let cname = make_cstring(name)?;
+        let raw = unsafe { q::JS_GetPropertyStr(self.raw_context(), this, cname.as_ptr()) };
+        let value = OwnedJsValue::new(&self.context.wrapper, raw);
+        if value.is_exception() {
+            return Err(self.context.wrapper.get_exception().unwrap_or_else(|| {
+                ExecutionError::Exception(format!("Could not get property '{}'", name).into())
+            }));
+        }
+        Ok(value.to_value()?)
     }
+
+    /// The function invoked with `new`, or `None` for a regular call.
+    pub fn new_target(&self) -> Option<JsValue> {
+        if self.is_construct_call {
+            deserialize_value(self.raw_context(), &self.this).ok()
+        } else {
+            None
+        }
+    }
+
+    /// The number of arguments passed by the caller.
+    pub fn args_len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Convert the argument at `index`, `undefined` if it was not passed.
+    pub fn arg(&self, index: usize) -> Result<JsValue, ValueError> {
+        match self.args.get(index) {
+            Some(value) => deserialize_value(self.raw_context(), value),
+            None => Ok(JsValue::Undefined),
+        }
+    }
+
+    /// Convert all arguments.
+    pub fn args(&self) -> Result<Vec<JsValue>, ValueError> {
+        self.args
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                deserialize_value(self.raw_context(), value).map_err(|e| {
+                    ValueError::Internal(format!(
+                        "failed to deserialize arguments {} (zero-based) to JS value, {}",
+                        index, e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// The unconverted arguments.
+    ///
+    /// The values are borrowed from the caller and are only valid for the
+    /// duration of the call.
+    pub fn raw_args(&self) -> &[q::JSValue] {
+        self.args
+    }
+
+    /// Borrow the bytes of an ArrayBuffer or typed array argument without
+    /// copying them.
+    ///
+    /// Fails with `ValueError::UnexpectedType` for other values.
+    ///
+    /// # Safety
+    ///
+    /// No Javascript code may run while the slice is in use, e.g. by
+    /// evaluating code through [CallContext::context]: it could detach or
+    /// resize the buffer and free the memory.
+    pub unsafe fn arg_bytes(&self, index: usize) -> Result<&[u8], ValueError> {
+        self.arg_bytes_mut(index).map(|bytes| &*bytes)
+    }
+
+    /// Mutably borrow the bytes of an ArrayBuffer or typed array argument,
+    /// see [CallContext::arg_bytes].
+    ///
+    /// # Safety
+    ///
+    /// In addition to the requirements of [CallContext::arg_bytes], the
+    /// bytes must not be borrowed again while the slice is in use, including
+    /// through another argument that refers to the same buffer.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn arg_bytes_mut(&self, index: usize) -> Result<&mut [u8], ValueError> {
+        let value = self.args.get(index).copied().unwrap_or(q::JS_UNDEFINED);
+        borrow_buffer(self.raw_context(), value)?.ok_or(ValueError::UnexpectedType)
+    }
+}
+
+/// A wrapper around Vec<JsValue>, used for vararg callbacks.
+///
+/// To create a callback with a variable number of arguments, a callback closure
+/// must take a single `Arguments` argument.
+pub struct Arguments(Vec<JsValue>);
+
+impl Arguments {
+    /// Unpack the arguments into a Vec.
+    pub fn into_vec(self) -> Vec<JsValue> {
+        self.0
+    }
+}
+
+/// Implements a callback trait for closures of the given `Fn*` trait.
+///
+/// The receiver and `self` are passed in so both come from the same
+/// hygiene context.
+macro_rules! impl_callback {
+    (@args $trait:ident, $fn_trait:ident, $call:ident, ($($recv:tt)+), $self:ident, []) => {};
+
+    // Only the last parameter can be a `Rest<T>`: the others must have a
+    // positional marker.
+    (
+        @args $trait:ident, $fn_trait:ident, $call:ident, ($($recv:tt)+), $self:ident,
+        [ ( $( $arg:ident $marker:ident, )* ; $last:ident $last_marker:ident ), $( $rest:tt, )* ]
+    ) => {
+        impl<
+            $( $arg, $marker, )*
+            $last,
+            $last_marker,
+            R,
+            F,
+        > $trait<PhantomData<(
+            $( &$arg, &$marker, )*
+            &$last,
+            &$last_marker,
+            &R,
+            &F,
+        )>> for F
+        where
+            $( $arg: CallbackArg<$marker>, $marker: marker::Positional, )*
+            $last: CallbackArg<$last_marker>,
+            R: IntoCallbackResult,
+            F: $fn_trait( $( $arg, )* $last ) -> R + Sized,
+        {
+            fn argument_count(&self) -> usize {
+                // Like `Function.length`, count the parameters before the
+                // first optional one.
+                [ $( $arg::REQUIRED, )* $last::REQUIRED ].iter().take_while(|r| **r).count()
+            }
+
+            fn declaration(&self) -> TsFunction {
+                TsFunction::from_params(&[ $( ($arg::REQUIRED, false), )* ($last::REQUIRED, $last::VARIADIC) ])
+            }
+
+            fn $call($($recv)+, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+                let required = [ $( $arg::REQUIRED, )* $last::REQUIRED ];
+                if let Err(e) = check_argument_count(&required, $last::VARIADIC, args.len()) {
+                    return Ok(Err(e));
+                }
+
+                let mut iter = args.into_iter().peekable();
+                let res = $self(
+                    $(
+                        $arg::from_args(&mut iter)?,
+                    )*
+                    $last::from_args(&mut iter)?
+                );
+                Ok(res.into_callback_res())
+            }
+        }
+
+        impl_callback!(@args $trait, $fn_trait, $call, ($($recv)+), $self, [ $( $rest, )* ]);
+    };
+
+    (
+        $trait:ident, $fn_trait:ident, $call:ident, $call_with_context:ident, ($($recv:tt)+), $self:ident,
+        [ $( $list:tt, )* ]
+    ) => {
+        impl<R, F> $trait<PhantomData<(&R, &F)>> for F
+        where
+            R: IntoCallbackResult,
+            F: $fn_trait() -> R + Sized,
+        {
+            fn argument_count(&self) -> usize {
+                0
+            }
+
+            fn $call($($recv)+, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+                if args.len() != 0 {
+                    return Ok(Err(format!(
+                        "Invalid argument count: Expected 0, got {}",
+                        args.len(),
+                    )));
+                }
+
+                let res = $self();
+                Ok(res.into_callback_res())
+            }
+        }
+
+        impl_callback!(@args $trait, $fn_trait, $call, ($($recv)+), $self, [ $( $list, )* ]);
+
+        impl<F> $trait<PhantomData<(&Arguments, &F)>> for F
+        where
+            F: $fn_trait(Arguments) + Sized,
+        {
+            fn argument_count(&self) -> usize {
+                0
+            }
+
+            fn declaration(&self) -> TsFunction {
+                TsFunction::new().rest("args", "any")
+            }
+
+            fn $call($($recv)+, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+                ($self)(Arguments(args));
+                Ok(Ok(JsValue::Undefined))
+            }
+        }
+
+        impl<F, R> $trait<PhantomData<(&Arguments, &F, &R)>> for F
+        where
+            R: IntoCallbackResult,
+            F: $fn_trait(Arguments) -> R + Sized,
+        {
+            fn argument_count(&self) -> usize {
+                0
+            }
+
+            fn declaration(&self) -> TsFunction {
+                TsFunction::new().rest("args", "any")
+            }
+
+            fn $call($($recv)+, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+                let res = ($self)(Arguments(args));
+                Ok(res.into_callback_res())
+            }
+        }
+
+        impl<F, R> $trait<PhantomData<(&CallContext<'static>, &F, &R)>> for F
+        where
+            R: IntoCallbackResult,
+            F: $fn_trait(&CallContext) -> R + Sized,
+        {
+            fn argument_count(&self) -> usize {
+                0
+            }
+
+            fn declaration(&self) -> TsFunction {
+                TsFunction::new().rest("args", "any")
+            }
+
+            fn $call($($recv)+, _args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+                Ok(Err("Callback can only be called with a call context".into()))
+            }
+
+            fn $call_with_context($($recv)+, call: &CallContext) -> Result<Result<JsValue, String>, ValueError> {
+                let res = ($self)(call);
+                Ok(res.into_callback_res())
+            }
+        }
+    };
+}
+
+macro_rules! impl_callbacks {
+    ( $( $trait:ident, $fn_trait:ident, $call:ident, $call_with_context:ident, ($($recv:tt)+), $self:ident; )* ) => {
+        $(
+            impl_callback!(
+                $trait, $fn_trait, $call, $call_with_context, ($($recv)+), $self,
+                [
+                    (; A1 M1),
+                    (A1 M1, ; A2 M2),
+                    (A1 M1, A2 M2, ; A3 M3),
+                    (A1 M1, A2 M2, A3 M3, ; A4 M4),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, ; A5 M5),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, ; A6 M6),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, ; A7 M7),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, ; A8 M8),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, ; A9 M9),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, ; A10 M10),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, ; A11 M11),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, A11 M11, ; A12 M12),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, A11 M11, A12 M12, ; A13 M13),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, A11 M11, A12 M12, A13 M13, ; A14 M14),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, A11 M11, A12 M12, A13 M13, A14 M14, ; A15 M15),
+                    (A1 M1, A2 M2, A3 M3, A4 M4, A5 M5, A6 M6, A7 M7, A8 M8, A9 M9, A10 M10, A11 M11, A12 M12, A13 M13, A14 M14, A15 M15, ; A16 M16),
+                ]
+            );
+        )*
+    };
+}
+
+impl_callbacks! {
+    Callback, Fn, call, call_with_context, (&self), self;
+    CallbackMut, FnMut, call_mut, call_mut_with_context, (&mut self), self;
 }
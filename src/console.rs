@@ -101,6 +101,26 @@ mod log {
                     .join(", ");
                 format!("{{{}}}", parts)
             }
+            JsValue::ArrayBuffer(bytes) => format!("ArrayBuffer {{ byteLength: {} }}", bytes.len()),
+            JsValue::TypedArray(array) => format!("{}({})", array.name(), array.len()),
+            JsValue::Symbol(symbol) => format!("Symbol({})", symbol.description().unwrap_or_default()),
+            JsValue::Map(entries) => {
+                let parts = entries
+                    .into_iter()
+                    .map(|(key, value)| format!("{} => {}", print_value(key), print_value(value)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Map {{{}}}", parts)
+            }
+            JsValue::Set(values) => {
+                let parts = values
+                    .into_iter()
+                    .map(print_value)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Set {{{}}}", parts)
+            }
+            JsValue::Iterator(_) => "[object Iterator]".to_string(),
             #[cfg(feature = "chrono")]
             JsValue::Date(v) => v.to_string(),
             #[cfg(feature = "bigint")]
@@ -0,0 +1,203 @@
+//! Support code for `#[derive(IntoJs, FromJs)]` and the `#[js_function]`,
+//! `#[js_class]` and `#[js_methods]` attribute macros.
+//!
+//! Only used by the generated code, this is not a stable API.
+
+use std::any::Any;
+use std::cell::{Ref, RefMut};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+use crate::callback::{check_argument_count, ArgIter};
+use crate::host::{ClassBuilder, Instance};
+use crate::typescript::{TsClass, TsType};
+use crate::{CallContext, Callback, CallbackArg, ExecutionError, JsObject, JsValue, ValueError};
+
+pub use crate::callback::IntoCallbackResult;
+
+pub type Map = JsObject;
+
+/// Get the properties of an object.
+pub fn into_object(value: JsValue) -> Result<Map, ValueError> {
+    match value {
+        JsValue::Object(map) => Ok(map),
+        JsValue::Raw(_) => value.get_object(),
+        _ => Err(ValueError::UnexpectedType),
+    }
+}
+
+/// Get the items of an array with exactly `len` items.
+pub fn into_array(value: JsValue, len: usize) -> Result<Vec<JsValue>, ValueError> {
+    match value {
+        JsValue::Array(items) if items.len() == len => Ok(items),
+        JsValue::Array(items) => Err(ValueError::Internal(format!(
+            "expected an array of length {}, got {}",
+            len,
+            items.len()
+        ))),
+        _ => Err(ValueError::UnexpectedType),
+    }
+}
+
+/// Convert the value of the field or variant `name`.
+pub fn convert<T>(value: JsValue, name: &str) -> Result<T, ValueError>
+where
+    T: TryFrom<JsValue>,
+    ValueError: From<T::Error>,
+{
+    T::try_from(value).map_err(|e| {
+        ValueError::Internal(format!("invalid value for `{}`: {}", name, ValueError::from(e)))
+    })
+}
+
+/// Take a field, `None` if it is missing or `undefined`.
+pub fn field<T>(map: &mut Map, key: &str) -> Result<Option<T>, ValueError>
+where
+    T: TryFrom<JsValue>,
+    ValueError: From<T::Error>,
+{
+    match map.remove(key) {
+        None | Some(JsValue::Undefined) => Ok(None),
+        Some(value) => convert(value, key).map(Some),
+    }
+}
+
+/// Take an optional field, `None` if it is missing, `undefined` or `null`.
+pub fn optional_field<T>(map: &mut Map, key: &str) -> Result<Option<T>, ValueError>
+where
+    T: TryFrom<JsValue>,
+    ValueError: From<T::Error>,
+{
+    match map.remove(key) {
+        None | Some(JsValue::Undefined) | Some(JsValue::Null) => Ok(None),
+        Some(value) => convert(value, key).map(Some),
+    }
+}
+
+pub fn missing_field(key: &str) -> ValueError {
+    ValueError::Internal(format!("missing field `{}`", key))
+}
+
+/// Take the tag of an internally tagged enum.
+pub fn tag(map: &mut Map, tag: &str) -> Result<String, ValueError> {
+    match map.remove(tag) {
+        Some(JsValue::String(name)) => Ok(name),
+        Some(_) => Err(ValueError::Internal(format!("tag `{}` must be a string", tag))),
+        None => Err(missing_field(tag)),
+    }
+}
+
+pub fn unknown_variant(name: &str, type_name: &str) -> ValueError {
+    ValueError::Internal(format!("unknown variant `{}` of `{}`", name, type_name))
+}
+
+pub fn no_variant_matched(type_name: &str) -> ValueError {
+    ValueError::Internal(format!(
+        "value did not match any variant of untagged enum `{}`",
+        type_name
+    ))
+}
+
+/// Defines the members of a class, implemented by `#[js_methods]`.
+pub trait ClassMethods: Sized {
+    fn define_methods(class: &mut ClassBuilder<Self>) -> Result<(), ExecutionError>;
+
+    fn declare_methods(class: &mut TsClass);
+}
+
+/// A callback with an explicit `length`.
+pub struct ArgumentCount<C>(pub usize, pub C);
+
+impl<C, F> Callback<F> for ArgumentCount<C>
+where
+    C: Callback<F>,
+{
+    fn argument_count(&self) -> usize {
+        self.0
+    }
+
+    fn call(&self, args: Vec<JsValue>) -> Result<Result<JsValue, String>, ValueError> {
+        self.1.call(args)
+    }
+
+    fn call_with_context(&self, call: &CallContext) -> Result<Result<JsValue, String>, ValueError> {
+        self.1.call_with_context(call)
+    }
+}
+
+/// Whether a parameter is required and whether it is variadic.
+pub fn param<T: CallbackArg<M>, M>() -> (bool, bool) {
+    (T::REQUIRED, T::VARIADIC)
+}
+
+/// The number of parameters before the first optional one.
+pub fn length(params: &[(bool, bool)]) -> usize {
+    params.iter().take_while(|(required, _)| *required).count()
+}
+
+/// Check the argument count of a call to `function` and convert the arguments.
+pub fn args(call: &CallContext, function: &str, params: &[(bool, bool)]) -> Result<ArgIter, String> {
+    let required = params.iter().map(|(required, _)| *required).collect::<Vec<_>>();
+    let variadic = params.iter().any(|(_, variadic)| *variadic);
+    check_argument_count(&required, variadic, call.args_len())
+        .map_err(|e| format!("Failed to call {}: {}", function, e))?;
+    let args = call.args().map_err(|e| format!("Failed to call {}: {}", function, e))?;
+    Ok(args.into_iter().peekable())
+}
+
+/// Convert the next argument, which is the parameter `name` of `function`.
+pub fn arg<T: CallbackArg<M>, M>(args: &mut ArgIter, function: &str, name: &str) -> Result<T, String> {
+    T::from_args(args).map_err(|e| format!("Invalid argument `{}` of {}: {}", name, function, e))
+}
+
+/// Get the instance a method is called on.
+pub fn this<T: Any>(call: &CallContext, method: &str) -> Result<Instance<T>, String> {
+    let this = call.this().map_err(|e| e.to_string())?;
+    Instance::try_from(this).map_err(|_| format!("{} called on an incompatible receiver", method))
+}
+
+pub fn borrow<'a, T: Any>(instance: &'a Instance<T>, method: &str) -> Result<Ref<'a, T>, String> {
+    instance
+        .try_borrow()
+        .map_err(|e| format!("Failed to call {}: {}", method, e))
+}
+
+pub fn borrow_mut<'a, T: Any>(instance: &'a Instance<T>, method: &str) -> Result<RefMut<'a, T>, String> {
+    instance
+        .try_borrow_mut()
+        .map_err(|e| format!("Failed to call {}: {}", method, e))
+}
+
+/// The TypeScript type of `T`, `any` if `T` does not implement [TsType].
+///
+/// Called as `(&TsTypeOf::<T>::new()).ts_type()` with [KnownTsType] and
+/// [UnknownTsType] in scope, method resolution picks the [TsType] impl if
+/// there is one.
+pub struct TsTypeOf<T: ?Sized>(PhantomData<T>);
+
+impl<T: ?Sized> TsTypeOf<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        TsTypeOf(PhantomData)
+    }
+}
+
+pub trait KnownTsType {
+    fn ts_type(&self) -> String;
+}
+
+impl<T: TsType> KnownTsType for TsTypeOf<T> {
+    fn ts_type(&self) -> String {
+        T::ts_type()
+    }
+}
+
+pub trait UnknownTsType {
+    fn ts_type(&self) -> String;
+}
+
+impl<T: ?Sized> UnknownTsType for &TsTypeOf<T> {
+    fn ts_type(&self) -> String {
+        "any".into()
+    }
+}
@@ -0,0 +1,364 @@
+use crate::bindings::value::{OwnedJsArray, OwnedJsObject};
+use crate::bindings::OwnedJsValue;
+use crate::{Context, ExecutionError, JsAsyncIterator, JsIterator, JsValue, Persistent, Property, ValueError};
+use libquickjs_sys as q;
+
+/// A handle to a Javascript object whose properties are converted on demand.
+///
+/// Returned by [Context::eval_ref](crate::Context::eval_ref) and
+/// [Context::call_ref](crate::Context::call_ref), so a large result does
+/// not have to be converted to read a few values. The handle borrows the
+/// context and can not outlive it.
+///
+/// ```rust
+/// use deft_quick_js::{Context, JsValue};
+/// let context = Context::new().unwrap();
+///
+/// let result = context
+///     .eval_ref("({ total: 3, items: Array.from({ length: 100000 }, (_, i) => i) })", "big.js")
+///     .unwrap();
+/// assert!(matches!(result.get("total").unwrap(), JsValue::Int(3)));
+/// let items = result.array("items").unwrap();
+/// assert_eq!(items.len().unwrap(), 100000);
+/// assert!(matches!(items.index(99999).unwrap(), JsValue::Int(99999)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct JsObjectRef<'a> {
+    object: OwnedJsObject<'a>,
+}
+
+impl<'a> JsObjectRef<'a> {
+    pub(crate) fn from_value(value: OwnedJsValue<'a>) -> Result<Self, ExecutionError> {
+        if !value.is_object() {
+            return Err(ValueError::UnexpectedType.into());
+        }
+        Ok(JsObjectRef {
+            object: value.try_into_object()?,
+        })
+    }
+
+    fn property(&self, key: &str) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        match self.object.property(key)? {
+            Some(value) => Ok(value),
+            None => Err(ExecutionError::Internal(format!("Property '{}' not found", key))),
+        }
+    }
+
+    /// Get and convert a property, `undefined` if it is missing.
+    pub fn get(&self, key: &str) -> Result<JsValue, ExecutionError> {
+        match self.object.property(key)? {
+            Some(value) => Ok(value.to_value()?),
+            None => Ok(JsValue::Undefined),
+        }
+    }
+
+    /// Get a property as an object handle.
+    pub fn object(&self, key: &str) -> Result<JsObjectRef<'a>, ExecutionError> {
+        JsObjectRef::from_value(self.property(key)?)
+    }
+
+    /// Get a property as an array handle.
+    pub fn array(&self, key: &str) -> Result<JsArrayRef<'a>, ExecutionError> {
+        JsArrayRef::from_value(self.property(key)?)
+    }
+
+    pub fn set(&self, key: &str, value: impl Into<JsValue>) -> Result<(), ExecutionError> {
+        let value = self.object.as_value().context().serialize_value(value.into())?;
+        self.object.set_property(key, value)
+    }
+
+    /// Check if the object has a property, like the `in` operator.
+    pub fn has(&self, key: &str) -> Result<bool, ExecutionError> {
+        self.object.has_property(key)
+    }
+
+    /// Delete a property, returns `false` if it could not be deleted.
+    pub fn delete(&self, key: &str) -> Result<bool, ExecutionError> {
+        self.object.delete_property(key)
+    }
+
+    /// Define or redefine a property, like `Object.defineProperty`.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, Property};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let object = context.eval_ref("({})", "object.js").unwrap();
+    /// object.define_property("secret", Property::value(1).enumerable(false)).unwrap();
+    /// assert!(object.has("secret").unwrap());
+    /// assert!(object.keys().unwrap().is_empty());
+    /// ```
+    pub fn define_property(&self, name: &str, property: Property) -> Result<(), ExecutionError> {
+        let context = self.object.as_value().context();
+        let (flags, value, getter, setter) = property.into_descriptor(context, name)?;
+        let value = context.serialize_value(value)?;
+        let getter = context.serialize_value(getter)?;
+        let setter = context.serialize_value(setter)?;
+        self.object.define_property(name, &value, &getter, &setter, flags)
+    }
+
+    /// Make the object immutable, like `Object.freeze`.
+    pub fn freeze(&self) -> Result<(), ExecutionError> {
+        self.object.restrict(q::JS_FreezeObject)
+    }
+
+    /// Prevent adding or removing properties, like `Object.seal`.
+    pub fn seal(&self) -> Result<(), ExecutionError> {
+        self.object.restrict(q::JS_SealObject)
+    }
+
+    /// Prevent adding properties, like `Object.preventExtensions`.
+    pub fn prevent_extensions(&self) -> Result<(), ExecutionError> {
+        self.object.restrict(q::JS_PreventExtensions)
+    }
+
+    /// Whether properties can be added, like `Object.isExtensible`.
+    pub fn is_extensible(&self) -> Result<bool, ExecutionError> {
+        self.object.is_extensible()
+    }
+
+    /// The keys of the enumerable string-keyed own properties, like
+    /// `Object.keys`.
+    pub fn keys(&self) -> Result<Vec<String>, ExecutionError> {
+        self.object.property_keys()
+    }
+
+    /// Call the method `name` of the object, like `object.name(...args)`.
+    pub fn call_method(
+        &self,
+        name: &str,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let context = self.object.as_value().context();
+        let args = args
+            .into_iter()
+            .map(|arg| context.serialize_value(arg.into()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let func = self.property(name)?.try_into_function()?;
+        Ok(context.call_function_with_this(func, self.object.as_value(), args)?.to_value()?)
+    }
+
+    /// Iterate the object with `Symbol.iterator`, like `for...of`.
+    pub fn iter(&self) -> Result<JsIterator<'a>, ExecutionError> {
+        JsIterator::from_value(self.object.as_value())
+    }
+
+    /// Iterate the object with `Symbol.asyncIterator`, like
+    /// `for await...of`.
+    pub fn iter_async(&self) -> Result<JsAsyncIterator<'a>, ExecutionError> {
+        JsAsyncIterator::from_value(self.object.as_value())
+    }
+
+    /// Keep the object in a handle that can be stored across calls.
+    pub fn persist<T>(&self) -> Persistent<T> {
+        let value = self.object.as_value();
+        Persistent::new(value.context().persist(value.clone()))
+    }
+
+    /// Get a property without converting it, `undefined` if it is missing.
+    pub fn property_ref(&self, key: &str) -> Result<JsValueRef<'a>, ExecutionError> {
+        match self.object.property(key)? {
+            Some(value) => Ok(JsValueRef { value }),
+            None => Ok(JsValueRef {
+                value: OwnedJsValue::new(self.object.as_value().context(), q::JS_UNDEFINED),
+            }),
+        }
+    }
+
+    /// This object as a value handle.
+    pub fn as_value_ref(&self) -> JsValueRef<'a> {
+        JsValueRef {
+            value: self.object.as_value().clone(),
+        }
+    }
+
+    /// Serialize to JSON like `JSON.stringify(object, null, indent)`.
+    pub fn to_json(&self, indent: usize) -> Result<String, ExecutionError> {
+        self.object.as_value().to_json(indent)
+    }
+
+    /// This object as an array handle, `None` if it is not an array.
+    pub fn as_array(&self) -> Option<JsArrayRef<'a>> {
+        self.object.as_value().clone().try_into_array().ok().map(|array| JsArrayRef { array })
+    }
+
+    /// Convert the whole object to a [JsValue::Object], including the
+    /// objects nested in it, within the
+    /// [ValueLimits](crate::ValueLimits) of the context.
+    pub fn to_value(&self) -> Result<JsValue, ExecutionError> {
+        Ok(self.object.as_value().to_value_deep()?)
+    }
+}
+
+/// A handle to a Javascript array whose elements are converted on demand.
+///
+/// See [JsObjectRef].
+#[derive(Clone, Debug)]
+pub struct JsArrayRef<'a> {
+    array: OwnedJsArray<'a>,
+}
+
+impl<'a> JsArrayRef<'a> {
+    pub(crate) fn from_value(value: OwnedJsValue<'a>) -> Result<Self, ExecutionError> {
+        match value.try_into_array() {
+            Ok(array) => Ok(JsArrayRef { array }),
+            Err(_) => Err(ValueError::UnexpectedType.into()),
+        }
+    }
+
+    fn element(&self, index: usize) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        let len = self.len()?;
+        if index >= len {
+            return Err(ExecutionError::Internal(format!(
+                "Index {} out of bounds for array of length {}",
+                index, len
+            )));
+        }
+        self.array.element(index as u32)
+    }
+
+    /// The `length` of the array.
+    pub fn len(&self) -> Result<usize, ExecutionError> {
+        self.array.len()
+    }
+
+    pub fn is_empty(&self) -> Result<bool, ExecutionError> {
+        self.array.is_empty()
+    }
+
+    /// Get and convert the element at `index`.
+    ///
+    /// Fails if the index is out of bounds.
+    pub fn index(&self, index: usize) -> Result<JsValue, ExecutionError> {
+        Ok(self.element(index)?.to_value()?)
+    }
+
+    /// Get the element at `index` as an object handle.
+    pub fn object(&self, index: usize) -> Result<JsObjectRef<'a>, ExecutionError> {
+        JsObjectRef::from_value(self.element(index)?)
+    }
+
+    /// Get the element at `index` as an array handle.
+    pub fn array(&self, index: usize) -> Result<JsArrayRef<'a>, ExecutionError> {
+        JsArrayRef::from_value(self.element(index)?)
+    }
+
+    /// Set the element at `index`, growing the array if needed.
+    pub fn set(&self, index: usize, value: impl Into<JsValue>) -> Result<(), ExecutionError> {
+        let value = self.array.as_value().context().serialize_value(value.into())?;
+        self.array.set_element(index as u32, value)
+    }
+
+    /// This array as an object handle.
+    pub fn as_object(&self) -> JsObjectRef<'a> {
+        JsObjectRef {
+            object: OwnedJsObject::try_from_value(self.array.as_value().clone()).expect("arrays are objects"),
+        }
+    }
+
+    /// Convert the whole array, including the objects nested in it, within
+    /// the [ValueLimits](crate::ValueLimits) of the context.
+    pub fn to_value(&self) -> Result<JsValue, ExecutionError> {
+        Ok(self.array.as_value().to_value_deep()?)
+    }
+}
+
+/// A handle to any Javascript value, to apply the operations of the
+/// language to it without converting it.
+///
+/// Returned by [Context::value_ref](crate::Context::value_ref). Operations
+/// that combine two values fail with [ExecutionError::InvalidHandle] if
+/// they belong to different contexts.
+///
+/// ```rust
+/// use deft_quick_js::Context;
+/// let context = Context::new().unwrap();
+///
+/// let value = context.value_ref(context.eval("'42'", "value.js").unwrap()).unwrap();
+/// assert_eq!(value.type_of(), "string");
+/// assert_eq!(value.to_number().unwrap(), 42.0);
+/// assert!(value.loose_equals(&context.value_ref(42).unwrap()).unwrap());
+/// assert!(!value.strict_equals(&context.value_ref(42).unwrap()).unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct JsValueRef<'a> {
+    value: OwnedJsValue<'a>,
+}
+
+impl<'a> JsValueRef<'a> {
+    pub(crate) fn from_value(value: OwnedJsValue<'a>) -> Self {
+        JsValueRef { value }
+    }
+
+    /// Convert to a number like `+value`.
+    ///
+    /// Fails for symbols and BigInts, or if `valueOf` throws.
+    pub fn to_number(&self) -> Result<f64, ExecutionError> {
+        self.value.to_number()
+    }
+
+    /// Convert to a 32 bit integer like `value | 0`.
+    pub fn to_int32(&self) -> Result<i32, ExecutionError> {
+        self.value.to_int32()
+    }
+
+    /// Convert to a boolean like `!!value`.
+    pub fn to_boolean(&self) -> Result<bool, ExecutionError> {
+        self.value.to_boolean()
+    }
+
+    /// Convert to a string like `String(value)`, but fails for symbols.
+    pub fn to_string(&self) -> Result<String, ExecutionError> {
+        self.value.js_to_string()
+    }
+
+    /// The result of the `typeof` operator, e.g. `"function"`.
+    pub fn type_of(&self) -> &'static str {
+        self.value.type_of()
+    }
+
+    /// Check `value instanceof constructor`.
+    ///
+    /// Fails if `constructor` is not callable.
+    pub fn instance_of(&self, constructor: &JsValueRef) -> Result<bool, ExecutionError> {
+        self.value.instance_of(&constructor.value)
+    }
+
+    /// Compare with `===`.
+    pub fn strict_equals(&self, other: &JsValueRef) -> Result<bool, ExecutionError> {
+        self.value.strict_equals(&other.value)
+    }
+
+    /// Compare like `Object.is`, which unlike `===` treats `NaN` as equal
+    /// to itself and `+0` as different from `-0`.
+    pub fn same_value(&self, other: &JsValueRef) -> Result<bool, ExecutionError> {
+        self.value.same_value(&other.value)
+    }
+
+    /// Compare with `==`.
+    pub fn loose_equals(&self, other: &JsValueRef) -> Result<bool, ExecutionError> {
+        self.value.loose_equals(&other.value)
+    }
+
+    /// Serialize to JSON like `JSON.stringify(value, null, indent)`.
+    pub fn to_json(&self, indent: usize) -> Result<String, ExecutionError> {
+        self.value.to_json(indent)
+    }
+
+    /// Deep-copy the value into another context, see
+    /// [Context::copy_to](crate::Context::copy_to).
+    pub fn copy_to<'b>(&self, target: &'b Context) -> Result<JsValueRef<'b>, ExecutionError> {
+        let context = self.value.context();
+        Ok(JsValueRef::from_value(context.copy_value(&self.value, &target.wrapper)?))
+    }
+
+    /// This value as an object handle, `None` if it is not an object.
+    pub fn as_object(&self) -> Option<JsObjectRef<'a>> {
+        JsObjectRef::from_value(self.value.clone()).ok()
+    }
+
+    /// Convert the value, as [Context::eval](crate::Context::eval) would.
+    pub fn to_value(&self) -> Result<JsValue, ExecutionError> {
+        Ok(self.value.to_value()?)
+    }
+}
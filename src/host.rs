@@ -0,0 +1,414 @@
+//! Rust functions and types exposed to Javascript.
+//!
+//! [HostFunction] and [HostClass] are usually implemented with the
+//! `#[js_function]`, `#[js_class]` and `#[js_methods]` attribute macros of
+//! the `derive` feature, and collected into a [HostModule] that installs
+//! them on a [Context].
+//!
+//! Instances of a host class are resources (see [JsValue::new_resource])
+//! that get the prototype of their class, so a `T: HostClass` can be
+//! returned to Javascript as a `JsValue` and taken back as an [Instance].
+
+use std::any::{Any, TypeId};
+use std::cell::{BorrowError, BorrowMutError, Ref, RefMut};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use libquickjs_sys as q;
+
+use crate::bindings::{convert, make_cstring, ContextWrapper, OwnedJsValue};
+use crate::typescript::{TsClass, TsDeclaration, TsFunction};
+use crate::{CallContext, Callback, Context, ExecutionError, JsValue, RawJSValue, ResourceValue, ValueError};
+
+/// A Rust function that can be installed as a Javascript function.
+///
+/// Implemented by `#[js_function]`.
+pub trait HostFunction {
+    /// The Javascript name of the function.
+    const NAME: &'static str;
+    /// The documentation of the function.
+    const DOC: &'static str;
+
+    /// Create the Javascript function.
+    fn create(context: &Context) -> Result<JsValue, ExecutionError>;
+
+    /// The TypeScript signature of the function.
+    fn declaration() -> TsFunction {
+        TsFunction::new().doc(Self::DOC)
+    }
+}
+
+/// A Rust type that can be installed as a Javascript class.
+///
+/// Implemented by `#[js_class]`, the members are defined by `#[js_methods]`.
+pub trait HostClass: Any + Sized {
+    /// The Javascript name of the class.
+    const NAME: &'static str;
+    /// The documentation of the class.
+    const DOC: &'static str;
+
+    /// Define the constructor, methods and accessors of the class.
+    fn define(class: &mut ClassBuilder<Self>) -> Result<(), ExecutionError>;
+
+    /// The TypeScript declaration of the class.
+    fn declaration() -> TsClass {
+        TsClass::new().doc(Self::DOC)
+    }
+}
+
+/// Create the constructor function of a class.
+///
+/// Values of type `T` that are converted to Javascript afterwards get the
+/// prototype of this constructor.
+pub fn create_class<T: HostClass>(context: &Context) -> Result<JsValue, ExecutionError> {
+    let wrapper = &context.wrapper;
+    let prototype = unsafe { q::JS_NewObject(wrapper.context) };
+    let prototype = OwnedJsValue::new(wrapper, prototype);
+    if prototype.is_exception() {
+        return Err(ExecutionError::Internal(format!("Could not create class '{}'", T::NAME)));
+    }
+    let mut class = ClassBuilder {
+        wrapper,
+        prototype,
+        constructor: None,
+        statics: Vec::new(),
+        _marker: PhantomData,
+    };
+    T::define(&mut class)?;
+    class.build()
+}
+
+/// Defines the members of a [HostClass].
+///
+/// Methods and accessors are defined on the prototype, static methods on
+/// the constructor.
+pub struct ClassBuilder<'a, T> {
+    wrapper: &'a ContextWrapper,
+    prototype: OwnedJsValue<'a>,
+    constructor: Option<OwnedJsValue<'a>>,
+    statics: Vec<(String, OwnedJsValue<'a>)>,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: HostClass> ClassBuilder<'a, T> {
+    /// Set the constructor, which creates the Rust value of a new instance.
+    ///
+    /// `length` is the number of required arguments. Without a constructor,
+    /// the class can not be instantiated from Javascript.
+    pub fn constructor<C>(&mut self, length: usize, constructor: C) -> Result<(), ExecutionError>
+    where
+        C: Fn(&CallContext) -> Result<T, String> + 'static,
+    {
+        let function = self.wrapper.create_constructor(T::NAME, move |call: &CallContext| {
+            if !call.is_construct_call() {
+                return Err(format!("Class constructor {} cannot be invoked without 'new'", T::NAME));
+            }
+            let value = constructor(call)?;
+            construct(call, value).map_err(|e| e.to_string())
+        })?;
+        let function = function.into_value();
+        define_value(&function, "length", JsValue::Int(length as i32), q::JS_PROP_CONFIGURABLE)?;
+        self.constructor = Some(function);
+        Ok(())
+    }
+
+    /// Add a method to the prototype.
+    pub fn method<F>(&mut self, name: &str, callback: impl Callback<F> + 'static) -> Result<(), ExecutionError> {
+        let function = self.wrapper.create_callback(name, callback)?.into_value();
+        define_value(&self.prototype, name, function.to_value()?, q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE)
+    }
+
+    /// Add a static method to the constructor.
+    pub fn static_method<F>(&mut self, name: &str, callback: impl Callback<F> + 'static) -> Result<(), ExecutionError> {
+        let function = self.wrapper.create_callback(name, callback)?.into_value();
+        self.statics.push((name.to_string(), function));
+        Ok(())
+    }
+
+    /// Add a getter to the prototype.
+    pub fn getter<F>(&mut self, name: &str, callback: impl Callback<F> + 'static) -> Result<(), ExecutionError> {
+        let function = self.wrapper.create_callback(&format!("get {}", name), callback)?.into_value();
+        self.define_accessor(name, q::JS_PROP_HAS_GET, function.value, q::JS_UNDEFINED)
+    }
+
+    /// Add a setter to the prototype.
+    pub fn setter<F>(&mut self, name: &str, callback: impl Callback<F> + 'static) -> Result<(), ExecutionError> {
+        let function = self.wrapper.create_callback(&format!("set {}", name), callback)?.into_value();
+        self.define_accessor(name, q::JS_PROP_HAS_SET, q::JS_UNDEFINED, function.value)
+    }
+
+    /// Define one half of an accessor property, keeping the other half.
+    fn define_accessor(
+        &self,
+        name: &str,
+        has: u32,
+        getter: q::JSValue,
+        setter: q::JSValue,
+    ) -> Result<(), ExecutionError> {
+        let cname = make_cstring(name)?;
+        let flags = has | q::JS_PROP_HAS_CONFIGURABLE | q::JS_PROP_CONFIGURABLE | q::JS_PROP_HAS_ENUMERABLE;
+        let ret = unsafe {
+            let atom = q::JS_NewAtom(self.wrapper.context, cname.as_ptr());
+            // JS_DefineProperty does not take ownership of the accessors.
+            let ret = q::JS_DefineProperty(
+                self.wrapper.context,
+                self.prototype.value,
+                atom,
+                q::JS_UNDEFINED,
+                getter,
+                setter,
+                flags as i32,
+            );
+            q::JS_FreeAtom(self.wrapper.context, atom);
+            ret
+        };
+        if ret < 0 {
+            return Err(self.wrapper.get_exception().unwrap_or_else(|| {
+                ExecutionError::Internal(format!("Could not define accessor '{}'", name))
+            }));
+        }
+        Ok(())
+    }
+
+    fn build(self) -> Result<JsValue, ExecutionError> {
+        let constructor = match self.constructor {
+            Some(constructor) => constructor,
+            None => {
+                let function = self.wrapper.create_constructor(T::NAME, |_: &CallContext| -> Result<JsValue, String> {
+                    Err(format!("{} can not be constructed from Javascript", T::NAME))
+                })?;
+                function.into_value()
+            }
+        };
+        unsafe {
+            // Links `constructor.prototype` and `prototype.constructor`.
+            q::JS_SetConstructor(self.wrapper.context, constructor.value, self.prototype.value);
+        }
+        for (name, function) in self.statics {
+            define_value(&constructor, &name, function.to_value()?, q::JS_PROP_CONFIGURABLE | q::JS_PROP_WRITABLE)?;
+        }
+        self.wrapper.set_class_prototype(TypeId::of::<T>(), self.prototype);
+        Ok(constructor.to_value()?)
+    }
+}
+
+/// Define a non-enumerable property.
+fn define_value(object: &OwnedJsValue, name: &str, value: JsValue, flags: u32) -> Result<(), ExecutionError> {
+    let context = object.context().context;
+    let cname = make_cstring(name)?;
+    let value = convert::serialize_value(context, value)?;
+    // JS_DefinePropertyValueStr takes ownership of the value.
+    let ret = unsafe { q::JS_DefinePropertyValueStr(context, object.value, cname.as_ptr(), value, flags as i32) };
+    if ret < 0 {
+        return Err(ExecutionError::Internal(format!("Could not define property '{}'", name)));
+    }
+    Ok(())
+}
+
+/// Create the instance of a construct call, with the prototype of `new.target`.
+fn construct<T: Any>(call: &CallContext, value: T) -> Result<JsValue, ValueError> {
+    let context = call.raw_context();
+    unsafe {
+        let instance = convert::create_resource(context, ResourceValue::new(value));
+        let cname = make_cstring("prototype")?;
+        let prototype = q::JS_GetPropertyStr(context, call.raw_this(), cname.as_ptr());
+        if q::JS_IsObject(prototype) {
+            q::JS_SetPrototype(context, instance, prototype);
+        }
+        q::JS_FreeValue(context, prototype);
+        let raw = RawJSValue::new(context, &instance);
+        q::JS_FreeValue(context, instance);
+        Ok(JsValue::Raw(raw))
+    }
+}
+
+/// A Rust value owned by a Javascript object, e.g. an instance of a
+/// [HostClass].
+///
+/// Can be used as a callback argument to take an instance by reference.
+pub struct Instance<T> {
+    resource: ResourceValue,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Any> Instance<T> {
+    /// Wrap a value, which is moved to Javascript when the instance is
+    /// converted to a `JsValue`.
+    pub fn new(value: T) -> Self {
+        Self {
+            resource: ResourceValue::new(value),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Immutably borrow the value.
+    ///
+    /// Panics if the value is currently mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.try_borrow().expect("already mutably borrowed")
+    }
+
+    /// Mutably borrow the value.
+    ///
+    /// Panics if the value is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.try_borrow_mut().expect("already borrowed")
+    }
+
+    /// Immutably borrow the value, failing if it is mutably borrowed.
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        let value = self.resource.resource.try_borrow()?;
+        // The type was checked when the instance was created.
+        Ok(Ref::map(value, |v| v.downcast_ref::<T>().unwrap()))
+    }
+
+    /// Mutably borrow the value, failing if it is borrowed.
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        let value = self.resource.resource.try_borrow_mut()?;
+        Ok(RefMut::map(value, |v| v.downcast_mut::<T>().unwrap()))
+    }
+}
+
+impl<T> Clone for Instance<T> {
+    fn clone(&self) -> Self {
+        Self {
+            resource: self.resource.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Any> TryFrom<JsValue> for Instance<T> {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        match value {
+            JsValue::Resource(resource) => {
+                let is_instance = resource
+                    .resource
+                    .try_borrow()
+                    .map_err(|e| ValueError::Internal(e.to_string()))?
+                    .is::<T>();
+                if !is_instance {
+                    return Err(ValueError::UnexpectedType);
+                }
+                Ok(Self {
+                    resource,
+                    _marker: PhantomData,
+                })
+            }
+            _ => Err(ValueError::UnexpectedType),
+        }
+    }
+}
+
+impl<T> From<Instance<T>> for JsValue {
+    fn from(instance: Instance<T>) -> Self {
+        JsValue::Resource(instance.resource)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HostItem {
+    name: &'static str,
+    create: fn(&Context) -> Result<JsValue, ExecutionError>,
+    declaration: fn() -> TsDeclaration,
+}
+
+/// A set of host functions and classes.
+///
+/// ```rust
+/// use deft_quick_js::host::{HostFunction, HostModule};
+/// use deft_quick_js::{Context, ExecutionError, JsValue};
+///
+/// // Usually implemented with `#[js_function]`.
+/// struct Add;
+///
+/// impl HostFunction for Add {
+///     const NAME: &'static str = "add";
+///     const DOC: &'static str = "Add two numbers.";
+///
+///     fn create(context: &Context) -> Result<JsValue, ExecutionError> {
+///         context.create_callback(Self::NAME, |a: i32, b: i32| a + b)
+///     }
+/// }
+///
+/// let context = Context::new().unwrap();
+/// let module = HostModule::new().function::<Add>();
+///
+/// // Install as globals...
+/// module.register(&context).unwrap();
+/// assert_eq!(context.eval_as::<i32>("add(1, 2)", "global.js").unwrap(), 3);
+///
+/// // ...or as a native module.
+/// module.register_module(&context, "math").unwrap();
+/// context.eval_module("import { add } from 'math'; globalThis.sum = add(3, 4);", "main.js").unwrap();
+/// assert_eq!(context.eval_as::<i32>("sum", "sum.js").unwrap(), 7);
+/// ```
+#[derive(Clone, Default)]
+pub struct HostModule {
+    items: Vec<HostItem>,
+}
+
+impl HostModule {
+    /// Create an empty module.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a function.
+    pub fn function<F: HostFunction>(mut self) -> Self {
+        self.items.push(HostItem {
+            name: F::NAME,
+            create: F::create,
+            declaration: || F::declaration().into(),
+        });
+        self
+    }
+
+    /// Add a class.
+    pub fn class<T: HostClass>(mut self) -> Self {
+        self.items.push(HostItem {
+            name: T::NAME,
+            create: create_class::<T>,
+            declaration: || T::declaration().into(),
+        });
+        self
+    }
+
+    /// Install the functions and classes as globals.
+    pub fn register(&self, context: &Context) -> Result<(), ExecutionError> {
+        for item in &self.items {
+            let value = (item.create)(context)?;
+            context.set_global(item.name, value)?;
+            context.declare(item.name, (item.declaration)());
+        }
+        Ok(())
+    }
+
+    /// Define a native ES module that exports the functions and classes.
+    ///
+    /// The exports are created when the module is first imported.
+    pub fn register_module(&self, context: &Context, name: &str) -> Result<(), ExecutionError> {
+        let items = self.items.clone();
+        let exports = items.iter().map(|item| item.name).collect::<Vec<_>>();
+        context.wrapper.add_native_module(
+            name,
+            &exports,
+            Rc::new(move |context: &Context| {
+                items
+                    .iter()
+                    .map(|item| Ok((item.name.to_string(), (item.create)(context)?)))
+                    .collect()
+            }),
+        )?;
+        let declarations = self
+            .items
+            .iter()
+            .map(|item| (item.name.to_string(), (item.declaration)()))
+            .collect();
+        context.wrapper.declarations().borrow_mut().add_module(name, declarations);
+        Ok(())
+    }
+}
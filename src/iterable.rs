@@ -0,0 +1,400 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context as TaskContext, Poll, Wake, Waker};
+
+use libquickjs_sys as q;
+
+use crate::bindings::convert::serialize_value;
+use crate::bindings::{discard_exception, ContextWrapper, OwnedJsValue};
+use crate::{CallContext, ExecutionError, JsObject, JsValue, RawJSValue, ValueError};
+
+/// An asynchronous sequence of values.
+///
+/// This has the shape of the `Stream` trait of the `futures` crate, which
+/// this crate does not depend on: a futures stream is adapted with a
+/// `poll_next` that delegates to it.
+pub trait Stream {
+    type Item;
+
+    /// Poll the next value, `Poll::Ready(None)` once the stream is finished.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>>;
+}
+
+/// Converts the items of a stream to `JsValue`.
+struct IntoValues<S>(Pin<Box<S>>);
+
+impl<S> Stream for IntoValues<S>
+where
+    S: Stream,
+    S::Item: Into<JsValue>,
+{
+    type Item = JsValue;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<JsValue>> {
+        self.0.as_mut().poll_next(cx).map(|item| item.map(Into::into))
+    }
+}
+
+enum Source {
+    Iterator(Box<dyn Iterator<Item = JsValue>>),
+    Stream(Pin<Box<dyn Stream<Item = JsValue>>>),
+}
+
+/// A Rust iterator or stream that becomes a Javascript iterator object
+/// when it is converted, see [JsValue::iterator] and [JsValue::stream].
+///
+/// The values are produced lazily, when Javascript calls `next()`. A
+/// sequence can only be converted once, clones share it.
+#[derive(Clone)]
+pub struct HostIterator {
+    source: Rc<Cell<Option<Source>>>,
+}
+
+impl HostIterator {
+    fn new(source: Source) -> Self {
+        HostIterator {
+            source: Rc::new(Cell::new(Some(source))),
+        }
+    }
+
+    /// Create the Javascript iterator object.
+    pub(crate) fn create(self, context: *mut q::JSContext) -> Result<q::JSValue, ValueError> {
+        // The context is owned by the caller, so the wrapper must never be dropped.
+        let wrapper = ManuallyDrop::new(unsafe { ContextWrapper::from_raw(context) });
+        let object = match self.source.take() {
+            Some(Source::Iterator(iterator)) => create_iterator(&wrapper, iterator),
+            Some(Source::Stream(stream)) => create_async_iterator(&wrapper, stream),
+            None => Err(ExecutionError::Internal("The iterator was already converted to Javascript".into())),
+        };
+        match object {
+            Ok(object) => Ok(unsafe { q::JS_DupValue(context, object.value) }),
+            Err(ExecutionError::Conversion(e)) => Err(e),
+            Err(e) => Err(ValueError::Internal(e.to_string())),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        let source = self.source.take();
+        let kind = match &source {
+            Some(Source::Iterator(_)) => "iterator",
+            Some(Source::Stream(_)) => "stream",
+            None => "converted",
+        };
+        self.source.set(source);
+        kind
+    }
+
+    /// Whether this becomes an async iterator.
+    pub(crate) fn is_stream(&self) -> bool {
+        self.kind() == "stream"
+    }
+}
+
+impl fmt::Debug for HostIterator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("HostIterator").field(&self.kind()).finish()
+    }
+}
+
+impl JsValue {
+    /// Expose a Rust iterator to Javascript as an iterator object, usable
+    /// with `for...of`, spread and the `Iterator` helpers.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// context.add_callback("range", |n: i32| JsValue::iterator(0..n)).unwrap();
+    /// let sum = context.eval("let sum = 0; for (const i of range(4)) sum += i; sum", "range.js").unwrap();
+    /// assert!(matches!(sum, JsValue::Int(6)));
+    /// ```
+    pub fn iterator<I>(iterator: I) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: 'static,
+        I::Item: Into<JsValue> + 'static,
+    {
+        let iterator = iterator.into_iter().map(Into::into);
+        JsValue::Iterator(HostIterator::new(Source::Iterator(Box::new(iterator))))
+    }
+
+    /// Expose a Rust [Stream] to Javascript as an async iterator object,
+    /// usable with `for await...of`.
+    ///
+    /// `next()` returns a promise. When the stream is not ready, the promise
+    /// settles once the stream wakes its waker and the host runs
+    /// [Context::execute_pending_job](crate::Context::execute_pending_job).
+    pub fn stream<S>(stream: S) -> Self
+    where
+        S: Stream + 'static,
+        S::Item: Into<JsValue>,
+    {
+        let stream = IntoValues(Box::pin(stream));
+        JsValue::Iterator(HostIterator::new(Source::Stream(Box::pin(stream))))
+    }
+}
+
+/// The `{ value, done }` result of an iterator step.
+fn step_result(value: Option<JsValue>) -> JsValue {
+    let done = value.is_none();
+    let value = value.unwrap_or(JsValue::Undefined);
+    JsObject::from_iter(vec![("value".to_string(), value), ("done".to_string(), JsValue::Bool(done))]).into()
+}
+
+/// Set a property keyed by a well-known symbol.
+fn set_symbol_property(object: &OwnedJsValue, atom: q::JSAtom, value: &OwnedJsValue) -> Result<(), ExecutionError> {
+    let context = object.context();
+    // JS_SetProperty takes ownership of the value.
+    let ret = unsafe {
+        q::JS_SetProperty(context.context, object.value, atom, q::JS_DupValue(context.context, value.value))
+    };
+    if ret < 0 {
+        return Err(context
+            .get_exception()
+            .unwrap_or_else(|| ExecutionError::Internal("Could not set property".into())));
+    }
+    Ok(())
+}
+
+fn create_iterator<'a>(
+    wrapper: &'a ContextWrapper,
+    iterator: Box<dyn Iterator<Item = JsValue>>,
+) -> Result<OwnedJsValue<'a>, ExecutionError> {
+    // Inherit `[Symbol.iterator]` and the helpers from `Iterator.prototype`.
+    let prototype = wrapper.global()?.property_require("Iterator")?.try_into_object()?.property_require("prototype")?;
+    let object = unsafe { OwnedJsValue::new(wrapper, q::JS_NewObjectProto(wrapper.context, prototype.value)) };
+    let object = object.try_into_object()?;
+
+    let iterator = Rc::new(RefCell::new(Some(iterator)));
+    let source = iterator.clone();
+    let next = wrapper.create_callback("next", move || -> Result<JsValue, String> {
+        let mut iterator = source.try_borrow_mut().map_err(|_| "Iterator is already running".to_string())?;
+        let value = iterator.as_mut().and_then(|iterator| iterator.next());
+        if value.is_none() {
+            // Release the iterator once it is exhausted.
+            *iterator = None;
+        }
+        Ok(step_result(value))
+    })?;
+    let close = wrapper.create_callback("return", move || -> Result<JsValue, String> {
+        let mut iterator = iterator.try_borrow_mut().map_err(|_| "Iterator is already running".to_string())?;
+        *iterator = None;
+        Ok(step_result(None))
+    })?;
+    object.set_property("next", next.into_value())?;
+    object.set_property("return", close.into_value())?;
+    Ok(object.into_value())
+}
+
+/// The tasks waiting for the jobs of a context, see
+/// [NextItem](crate::NextItem).
+///
+/// Shared with the wakers of the streams, which may be called from other
+/// threads.
+#[derive(Default)]
+pub(crate) struct JobWakers(Mutex<Vec<Waker>>);
+
+impl JobWakers {
+    /// Wake the task of `waker` the next time jobs may have been queued.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake the waiting tasks.
+    pub(crate) fn wake(&self) {
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Records that a stream was woken.
+struct WakeFlag {
+    woken: AtomicBool,
+    /// The stream queues jobs when the context polls it.
+    jobs: Arc<JobWakers>,
+}
+
+impl Wake for WakeFlag {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.jobs.wake();
+    }
+}
+
+struct StreamState {
+    context: *mut q::JSContext,
+    stream: Option<Pin<Box<dyn Stream<Item = JsValue>>>>,
+    /// The resolving functions of the promises returned by `next()`.
+    requests: VecDeque<[q::JSValue; 2]>,
+}
+
+impl StreamState {
+    /// Settle the promise of the oldest request.
+    fn settle(&mut self, result: Result<JsValue, String>) {
+        let [resolve, reject] = match self.requests.pop_front() {
+            Some(request) => request,
+            None => return,
+        };
+        let (function, value) = match result.map(|value| serialize_value(self.context, value)) {
+            Ok(Ok(value)) => (resolve, Ok(value)),
+            Ok(Err(e)) => (reject, serialize_value(self.context, JsValue::String(e.to_string()))),
+            Err(e) => (reject, serialize_value(self.context, JsValue::String(e))),
+        };
+        unsafe {
+            if let Ok(mut value) = value {
+                let ret = q::JS_Call(self.context, function, q::JS_UNDEFINED, 1, &mut value);
+                if q::JS_IsException(ret) {
+                    discard_exception(self.context);
+                } else {
+                    q::JS_FreeValue(self.context, ret);
+                }
+                q::JS_FreeValue(self.context, value);
+            }
+            q::JS_FreeValue(self.context, resolve);
+            q::JS_FreeValue(self.context, reject);
+        }
+    }
+
+    fn release(&mut self) {
+        self.stream = None;
+        for [resolve, reject] in self.requests.drain(..) {
+            unsafe {
+                q::JS_FreeValue(self.context, resolve);
+                q::JS_FreeValue(self.context, reject);
+            }
+        }
+    }
+}
+
+/// A stream exposed to Javascript, polled while it has pending requests.
+///
+/// The context releases the stream and its requests when it is reset or
+/// dropped.
+pub(crate) struct StreamSlot {
+    state: RefCell<StreamState>,
+    woken: Arc<WakeFlag>,
+}
+
+impl StreamSlot {
+    /// Whether the stream was woken since it was last polled.
+    pub(crate) fn is_woken(&self) -> bool {
+        self.woken.woken.load(Ordering::SeqCst)
+    }
+
+    /// Poll the stream for the pending requests, returns whether one of
+    /// them was settled.
+    pub(crate) fn poll(&self) -> bool {
+        let mut state = match self.state.try_borrow_mut() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        let waker = Waker::from(self.woken.clone());
+        let mut cx = TaskContext::from_waker(&waker);
+        let mut settled = false;
+        while !state.requests.is_empty() {
+            self.woken.woken.store(false, Ordering::SeqCst);
+            let poll = match state.stream.as_mut() {
+                Some(stream) => stream.as_mut().poll_next(&mut cx),
+                None => Poll::Ready(None),
+            };
+            match poll {
+                Poll::Pending => break,
+                Poll::Ready(value) => {
+                    if value.is_none() {
+                        state.stream = None;
+                    }
+                    state.settle(Ok(step_result(value)));
+                    settled = true;
+                }
+            }
+        }
+        settled
+    }
+
+    pub(crate) fn release(&self) {
+        if let Ok(mut state) = self.state.try_borrow_mut() {
+            state.release();
+        }
+    }
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.state.get_mut().release();
+    }
+}
+
+/// Create a promise, returning it and its resolving functions.
+fn new_promise(call: &CallContext) -> Result<(JsValue, [q::JSValue; 2]), String> {
+    let context = call.raw_context();
+    let mut functions = [q::JS_UNDEFINED; 2];
+    let promise = unsafe { q::JS_NewPromiseCapability(context, functions.as_mut_ptr()) };
+    if q::JS_IsException(promise) {
+        return Err("Could not create promise".into());
+    }
+    Ok((JsValue::Raw(RawJSValue::from_owned(context, promise)), functions))
+}
+
+fn create_async_iterator<'a>(
+    wrapper: &'a ContextWrapper,
+    stream: Pin<Box<dyn Stream<Item = JsValue>>>,
+) -> Result<OwnedJsValue<'a>, ExecutionError> {
+    let object = unsafe { OwnedJsValue::new(wrapper, q::JS_NewObject(wrapper.context)) };
+    let object = object.try_into_object()?;
+
+    let slot = Rc::new(StreamSlot {
+        state: RefCell::new(StreamState {
+            context: wrapper.context,
+            stream: Some(stream),
+            requests: VecDeque::new(),
+        }),
+        woken: Arc::new(WakeFlag {
+            woken: AtomicBool::new(false),
+            jobs: wrapper.job_wakers(),
+        }),
+    });
+    wrapper.register_stream(&slot);
+
+    let source = slot.clone();
+    let next = wrapper.create_callback("next", move |call: &CallContext| -> Result<JsValue, String> {
+        // Borrow first, the resolving functions would leak on failure.
+        let mut state = source.state.try_borrow_mut().map_err(|_| "Stream is already running".to_string())?;
+        let (promise, functions) = new_promise(call)?;
+        state.requests.push_back(functions);
+        drop(state);
+        source.poll();
+        Ok(promise)
+    })?;
+    let close = wrapper.create_callback("return", move |call: &CallContext| -> Result<JsValue, String> {
+        let mut state = slot.state.try_borrow_mut().map_err(|_| "Stream is already running".to_string())?;
+        let (promise, functions) = new_promise(call)?;
+        state.stream = None;
+        state.requests.push_back(functions);
+        while !state.requests.is_empty() {
+            state.settle(Ok(step_result(None)));
+        }
+        Ok(promise)
+    })?;
+    let this = wrapper.create_callback("[Symbol.asyncIterator]", |call: &CallContext| call.this())?;
+    object.set_property("next", next.into_value())?;
+    object.set_property("return", close.into_value())?;
+    let object = object.into_value();
+    set_symbol_property(&object, q::JS_ATOM_Symbol_asyncIterator, &this.into_value())?;
+    Ok(object)
+}
@@ -0,0 +1,389 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use libquickjs_sys as q;
+
+use crate::bindings::value::JsFunction;
+use crate::bindings::OwnedJsValue;
+use crate::{ExecutionError, JsValue, ValueError};
+
+/// Get a property by atom, e.g. a well-known symbol.
+fn property<'a>(value: &OwnedJsValue<'a>, atom: q::JSAtom) -> Result<OwnedJsValue<'a>, ExecutionError> {
+    let context = value.context();
+    let raw = unsafe { q::JS_GetProperty(context.context, value.value, atom) };
+    let property = OwnedJsValue::new(context, raw);
+    if property.is_exception() {
+        return Err(context
+            .get_exception()
+            .unwrap_or_else(|| ExecutionError::Internal("Could not get property".into())));
+    }
+    Ok(property)
+}
+
+fn call<'a>(
+    function: &OwnedJsValue<'a>,
+    this: &OwnedJsValue<'a>,
+    args: Vec<OwnedJsValue<'a>>,
+) -> Result<OwnedJsValue<'a>, ExecutionError> {
+    let function = JsFunction::try_from_value(function.clone())?;
+    this.context().call_function_with_this(function, this, args)
+}
+
+/// Call the iterator method `atom` of `iterable` and get the `next` method
+/// of the returned iterator.
+fn open<'a>(
+    iterable: &OwnedJsValue<'a>,
+    atom: q::JSAtom,
+) -> Result<Option<(OwnedJsValue<'a>, OwnedJsValue<'a>)>, ExecutionError> {
+    if !iterable.is_object() {
+        return Err(ValueError::UnexpectedType.into());
+    }
+    let method = property(iterable, atom)?;
+    if method.is_undefined() || method.is_null() {
+        return Ok(None);
+    }
+    let iterator = call(&method, iterable, Vec::new())?;
+    if !iterator.is_object() {
+        return Err(ExecutionError::Internal("Iterator is not an object".into()));
+    }
+    let next = property(&iterator, q::JS_ATOM_next)?;
+    Ok(Some((iterator, next)))
+}
+
+/// Read an iterator result, `None` if it is done.
+fn read_result<'a>(result: &OwnedJsValue<'a>) -> Result<Option<OwnedJsValue<'a>>, ExecutionError> {
+    if !result.is_object() {
+        return Err(ExecutionError::Internal("Iterator result is not an object".into()));
+    }
+    let done = property(result, q::JS_ATOM_done)?;
+    let done = unsafe { q::JS_ToBool(result.context().context, done.value) };
+    if done < 0 {
+        return Err(result.context().get_exception().unwrap_or_else(|| {
+            ExecutionError::Internal("Could not read iterator result".into())
+        }));
+    }
+    if done != 0 {
+        Ok(None)
+    } else {
+        Ok(Some(property(result, q::JS_ATOM_value)?))
+    }
+}
+
+/// Call `return()` on an iterator that was not exhausted, like a `break` in
+/// a `for...of` loop does.
+fn close(iterator: &OwnedJsValue) -> Result<(), ExecutionError> {
+    let method = property(iterator, q::JS_ATOM_return)?;
+    if method.is_undefined() || method.is_null() {
+        return Ok(());
+    }
+    call(&method, iterator, Vec::new())?;
+    Ok(())
+}
+
+/// Convert an item, closing the iterator if that fails: unlike an
+/// exception of the iterator, a conversion error leaves it unfinished.
+fn convert_item(iterator: &OwnedJsValue, item: &OwnedJsValue) -> Result<JsValue, ExecutionError> {
+    item.to_value().map_err(|e| {
+        // The conversion error is the one to report.
+        let _ = close(iterator);
+        e.into()
+    })
+}
+
+/// A Rust iterator over a Javascript iterable, such as an array, a `Map`,
+/// or the result of a generator function.
+///
+/// Each item is converted as [Context::eval](crate::Context::eval) would.
+/// An exception thrown by the iterator is returned as an `Err` item, after
+/// which the iteration ends. Dropping the iterator before it is exhausted,
+/// or failing to convert an item, calls its `return()` method, so `finally`
+/// blocks of generators run.
+///
+/// ```rust
+/// use deft_quick_js::{Context, JsValue};
+/// let context = Context::new().unwrap();
+///
+/// let numbers = context
+///     .eval_ref("(function* () { let i = 0; while (true) yield i++; })()", "gen.js")
+///     .unwrap();
+/// let first: Vec<JsValue> = numbers.iter().unwrap().take(3).collect::<Result<_, _>>().unwrap();
+/// assert!(matches!(first[..], [JsValue::Int(0), JsValue::Int(1), JsValue::Int(2)]));
+/// ```
+pub struct JsIterator<'a> {
+    iterator: OwnedJsValue<'a>,
+    next: OwnedJsValue<'a>,
+    done: bool,
+}
+
+impl<'a> JsIterator<'a> {
+    pub(crate) fn from_value(value: &OwnedJsValue<'a>) -> Result<Self, ExecutionError> {
+        match open(value, q::JS_ATOM_Symbol_iterator)? {
+            Some((iterator, next)) => Ok(JsIterator {
+                iterator,
+                next,
+                done: false,
+            }),
+            None => Err(ExecutionError::Internal("Value is not iterable".into())),
+        }
+    }
+
+    fn step(&mut self) -> Result<Option<JsValue>, ExecutionError> {
+        let result = call(&self.next, &self.iterator, Vec::new())?;
+        match read_result(&result)? {
+            Some(value) => Ok(Some(convert_item(&self.iterator, &value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stop iterating and call the `return()` method of the iterator.
+    pub fn close(mut self) -> Result<(), ExecutionError> {
+        if self.done {
+            return Ok(());
+        }
+        self.done = true;
+        close(&self.iterator)
+    }
+}
+
+impl<'a> Iterator for JsIterator<'a> {
+    type Item = Result<JsValue, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.step() {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for JsIterator<'a> {
+    fn drop(&mut self) {
+        if !self.done {
+            // There is nobody to report an exception of `return()` to.
+            let _ = close(&self.iterator);
+        }
+    }
+}
+
+/// Execute pending jobs until the promise settles.
+fn settle<'a>(promise: &OwnedJsValue<'a>) -> Poll<Result<OwnedJsValue<'a>, ExecutionError>> {
+    let context = promise.context();
+    loop {
+        let state = unsafe { q::JS_PromiseState(context.context, promise.value) };
+        let result = || OwnedJsValue::new(context, unsafe { q::JS_PromiseResult(context.context, promise.value) });
+        match state {
+            q::JSPromiseStateEnum_JS_PROMISE_FULFILLED => return Poll::Ready(Ok(result())),
+            q::JSPromiseStateEnum_JS_PROMISE_REJECTED => {
+                return Poll::Ready(match result().js_to_string() {
+                    Ok(reason) => Err(ExecutionError::Exception(JsValue::String(reason))),
+                    Err(e) => Err(e),
+                })
+            }
+            _ => match context.execute_pending_job() {
+                Ok(true) => {}
+                Ok(false) => return Poll::Pending,
+                Err(e) => return Poll::Ready(Err(e)),
+            },
+        }
+    }
+}
+
+fn is_promise(value: &OwnedJsValue) -> bool {
+    unsafe { q::JS_IsPromise(value.value) }
+}
+
+/// Where an asynchronous iterator is in its current step.
+enum AsyncStep<'a> {
+    Idle,
+    /// Waiting for the promise returned by `next()`.
+    Result(OwnedJsValue<'a>),
+    /// Waiting for a promise yielded by a synchronous iterator.
+    Value(OwnedJsValue<'a>),
+}
+
+/// An iterator over a Javascript async iterable, such as the result of an
+/// async generator function, like `for await...of`.
+///
+/// Synchronous iterables are accepted too, their promises are awaited.
+/// While waiting for a promise, the pending jobs of the context are
+/// executed. If there are no jobs left and the promise did not settle, for
+/// example because it waits for a [JsPromise](crate::JsPromise) resolved
+/// by the host, [poll_next](JsAsyncIterator::poll_next) returns
+/// `Poll::Pending` and can be called again later.
+///
+/// ```rust
+/// use deft_quick_js::{Context, JsValue};
+/// let context = Context::new().unwrap();
+///
+/// let pages = context
+///     .eval_ref("(async function* () { yield 1; await null; yield 2; })()", "pages.js")
+///     .unwrap();
+/// let pages: Vec<JsValue> = pages.iter_async().unwrap().collect::<Result<_, _>>().unwrap();
+/// assert!(matches!(pages[..], [JsValue::Int(1), JsValue::Int(2)]));
+/// ```
+pub struct JsAsyncIterator<'a> {
+    iterator: OwnedJsValue<'a>,
+    next: OwnedJsValue<'a>,
+    sync: bool,
+    step: AsyncStep<'a>,
+    done: bool,
+}
+
+impl<'a> JsAsyncIterator<'a> {
+    pub(crate) fn from_value(value: &OwnedJsValue<'a>) -> Result<Self, ExecutionError> {
+        let (sync, opened) = match open(value, q::JS_ATOM_Symbol_asyncIterator)? {
+            Some(opened) => (false, opened),
+            None => match open(value, q::JS_ATOM_Symbol_iterator)? {
+                Some(opened) => (true, opened),
+                None => return Err(ExecutionError::Internal("Value is not async iterable".into())),
+            },
+        };
+        let (iterator, next) = opened;
+        Ok(JsAsyncIterator {
+            iterator,
+            next,
+            sync,
+            step: AsyncStep::Idle,
+            done: false,
+        })
+    }
+
+    fn poll_step(&mut self) -> Poll<Result<Option<JsValue>, ExecutionError>> {
+        loop {
+            match std::mem::replace(&mut self.step, AsyncStep::Idle) {
+                AsyncStep::Idle => {
+                    let result = match call(&self.next, &self.iterator, Vec::new()) {
+                        Ok(result) => result,
+                        Err(e) => return Poll::Ready(Err(e)),
+                    };
+                    self.step = AsyncStep::Result(result);
+                }
+                AsyncStep::Result(result) => {
+                    let result = if is_promise(&result) {
+                        match settle(&result) {
+                            Poll::Ready(Ok(result)) => result,
+                            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                            Poll::Pending => {
+                                self.step = AsyncStep::Result(result);
+                                return Poll::Pending;
+                            }
+                        }
+                    } else {
+                        result
+                    };
+                    match read_result(&result) {
+                        Ok(Some(value)) if self.sync && is_promise(&value) => self.step = AsyncStep::Value(value),
+                        Ok(Some(value)) => return Poll::Ready(convert_item(&self.iterator, &value).map(Some)),
+                        Ok(None) => return Poll::Ready(Ok(None)),
+                        Err(e) => return Poll::Ready(Err(e)),
+                    }
+                }
+                AsyncStep::Value(value) => match settle(&value) {
+                    Poll::Ready(result) => {
+                        return Poll::Ready(result.and_then(|value| Ok(Some(convert_item(&self.iterator, &value)?))))
+                    }
+                    Poll::Pending => {
+                        self.step = AsyncStep::Value(value);
+                        return Poll::Pending;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Advance the iterator as far as the pending jobs allow.
+    ///
+    /// Returns `Poll::Pending` if the current step waits for a promise that
+    /// no pending job settles, calling it again continues that step.
+    pub fn poll_next(&mut self) -> Poll<Option<Result<JsValue, ExecutionError>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        match self.poll_step() {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(Some(value))) => Poll::Ready(Some(Ok(value))),
+            Poll::Ready(Ok(None)) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(Err(e)) => {
+                self.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+
+    /// Wait for the next item in an async context.
+    ///
+    /// While waiting, the task is woken when jobs may have been queued: when
+    /// a [JsPromise](crate::JsPromise) is settled, a
+    /// [stream](crate::JsValue::stream) is woken, or a script or function
+    /// of the context queued jobs.
+    pub fn next_async(&mut self) -> NextItem<'_, 'a> {
+        NextItem { iterator: self }
+    }
+
+    /// Stop iterating and call the `return()` method of the iterator.
+    ///
+    /// The promise returned by `return()` is not awaited.
+    pub fn close(mut self) -> Result<(), ExecutionError> {
+        if self.done {
+            return Ok(());
+        }
+        self.done = true;
+        close(&self.iterator)
+    }
+}
+
+/// Blocks on each item: fails with an error item instead of returning
+/// `Poll::Pending` when the pending jobs do not settle a promise.
+impl<'a> Iterator for JsAsyncIterator<'a> {
+    type Item = Result<JsValue, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.poll_next() {
+            Poll::Ready(item) => item,
+            Poll::Pending => {
+                self.done = true;
+                Some(Err(ExecutionError::Internal(
+                    "Async iterator waits for a promise that no pending job settles".into(),
+                )))
+            }
+        }
+    }
+}
+
+impl<'a> Drop for JsAsyncIterator<'a> {
+    fn drop(&mut self) {
+        if !self.done {
+            // There is nobody to report an exception of `return()` to.
+            let _ = close(&self.iterator);
+        }
+    }
+}
+
+/// The future returned by [JsAsyncIterator::next_async].
+pub struct NextItem<'i, 'a> {
+    iterator: &'i mut JsAsyncIterator<'a>,
+}
+
+impl<'i, 'a> Future for NextItem<'i, 'a> {
+    type Output = Option<Result<JsValue, ExecutionError>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        // Registered first, so a stream woken while polling is not missed.
+        self.iterator.iterator.context().job_wakers().register(cx.waker());
+        self.iterator.poll_next()
+    }
+}
@@ -35,9 +35,17 @@
 // #![deny(missing_docs)]
 
 extern crate core;
+// Lets the derive macros refer to `::deft_quick_js` inside of this crate.
+#[cfg(feature = "derive")]
+extern crate self as deft_quick_js;
 
 pub mod bindings;
 mod callback;
+mod handle;
+mod iterable;
+mod iterator;
+mod persistent;
+mod property;
 pub mod console;
 mod value;
 
@@ -45,6 +53,11 @@ mod value;
 mod tests;
 pub mod loader;
 pub mod exception;
+pub mod host;
+pub mod typescript;
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod derive;
 
 use std::{convert::TryFrom, error, fmt};
 use std::any::Any;
@@ -52,12 +65,20 @@ use libquickjs_sys::{JS_EVAL_TYPE_GLOBAL, JS_EVAL_TYPE_MODULE};
 use loader::JsModuleLoader;
 
 pub use self::{
-    callback::{Arguments, Callback},
+    callback::{Arguments, CallContext, Callback, CallbackArg, CallbackMut, IntoCallbackResult, Rest},
+    handle::{JsArrayRef, JsObjectRef, JsValueRef},
+    iterable::{HostIterator, Stream},
+    iterator::{JsAsyncIterator, JsIterator, NextItem},
+    persistent::Persistent,
+    property::Property,
     value::*,
 };
 
 pub use libquickjs_sys;
+#[cfg(feature = "derive")]
+pub use deft_quick_js_derive::{js_class, js_function, js_methods, js_module, FromJs, IntoJs};
 use crate::exception::HostPromiseRejectionTracker;
+use crate::typescript::TsDeclaration;
 
 /// Error on Javascript execution.
 #[derive(Debug)]
@@ -72,6 +93,9 @@ pub enum ExecutionError {
     Exception(JsValue),
     /// JS Runtime exceeded the memory limit.
     OutOfMemory,
+    /// A [Persistent] handle was used after its context was reset or
+    /// dropped, or with another context.
+    InvalidHandle,
     #[doc(hidden)]
     __NonExhaustive,
 }
@@ -85,6 +109,7 @@ impl fmt::Display for ExecutionError {
             Internal(e) => write!(f, "Internal error: {}", e),
             Exception(e) => write!(f, "{:?}", e),
             OutOfMemory => write!(f, "Out of memory: runtime memory limit exceeded"),
+            InvalidHandle => write!(f, "Invalid handle: the value belongs to another context, or to one that was reset or dropped"),
             __NonExhaustive => unreachable!(),
         }
     }
@@ -125,11 +150,45 @@ impl fmt::Display for ContextError {
 
 impl error::Error for ContextError {}
 
+/// Decides how a panic in a Rust callback is reported.
+///
+/// The error thrown for a panic carries the panic message. It also carries
+/// the location of the panic once [PanicPolicy::record_locations] was
+/// called, which installs a process-wide panic hook.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Throw a Javascript `Error` carrying the panic message.
+    ///
+    /// Scripts can catch the error like any other exception.
+    #[default]
+    ThrowError,
+    /// Throw an uncatchable error that unwinds out of the running script,
+    /// then resume the panic with [std::panic::resume_unwind] when it
+    /// reaches Rust (e.g. in [Context::eval]).
+    ///
+    /// The panic is dropped if the error does not reach Rust as the result
+    /// of a call, for example when it rejects a promise.
+    Resume,
+}
+
+impl PanicPolicy {
+    /// Include the location of panics in the errors thrown for them.
+    ///
+    /// This installs a panic hook for the whole process, once, which records
+    /// the location and then calls the previously installed hook. A hook
+    /// installed later must call this one for the locations to be recorded.
+    pub fn record_locations() {
+        bindings::install_panic_hook();
+    }
+}
+
 /// A builder for [Context](Context).
 ///
 /// Create with [Context::builder](Context::builder).
 pub struct ContextBuilder {
     memory_limit: Option<usize>,
+    panic_policy: PanicPolicy,
+    value_limits: ValueLimits,
     console_backend: Option<Box<dyn console::ConsoleBackend>>,
     module_loader: Option<Box<dyn JsModuleLoader>>,
 }
@@ -138,6 +197,8 @@ impl ContextBuilder {
     fn new() -> Self {
         Self {
             memory_limit: None,
+            panic_policy: PanicPolicy::default(),
+            value_limits: ValueLimits::default(),
             console_backend: None,
             module_loader: None,
         }
@@ -154,6 +215,23 @@ impl ContextBuilder {
         s
     }
 
+    /// Sets how panics in callbacks are reported, see [PanicPolicy].
+    ///
+    /// Defaults to [PanicPolicy::ThrowError].
+    pub fn panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Sets the limits on converting nested values to Rust, see [ValueLimits].
+    ///
+    /// Values that exceed them fail to convert with
+    /// [ValueError::LimitExceeded].
+    pub fn value_limits(mut self, limits: ValueLimits) -> Self {
+        self.value_limits = limits;
+        self
+    }
+
     /// Set a console handler that will proxy `console.{log,trace,debug,...}`
     /// calls.
     ///
@@ -180,6 +258,8 @@ impl ContextBuilder {
     /// Finalize the builder and build a JS Context.
     pub fn build(self) -> Result<Context, ContextError> {
         let mut wrapper = bindings::ContextWrapper::new(self.memory_limit)?;
+        wrapper.set_panic_policy(self.panic_policy);
+        wrapper.set_value_limits(self.value_limits);
         if let Some(be) = self.console_backend {
             wrapper.set_console(be).map_err(ContextError::Execution)?;
         }
@@ -312,6 +392,103 @@ impl Context {
         Ok(ret)
     }
 
+    /// Evaluates Javascript code that results in an object or array, and
+    /// returns a handle that converts its contents on demand.
+    ///
+    /// Promises are resolved as in [eval](Context::eval). Fails with
+    /// `ValueError::UnexpectedType` if the result is not an object.
+    pub fn eval_ref(&self, code: &str, filename: &str) -> Result<JsObjectRef<'_>, ExecutionError> {
+        let value_raw = self.wrapper.eval(code, JS_EVAL_TYPE_GLOBAL, filename)?;
+        JsObjectRef::from_value(value_raw)
+    }
+
+    /// Iterate a Javascript iterable, such as an array or a generator, see
+    /// [JsIterator].
+    pub fn iterate(&self, iterable: impl Into<JsValue>) -> Result<JsIterator<'_>, ExecutionError> {
+        let iterable = self.wrapper.serialize_value(iterable.into())?;
+        JsIterator::from_value(&iterable)
+    }
+
+    /// Iterate a Javascript async iterable, such as the result of an async
+    /// generator, see [JsAsyncIterator].
+    pub fn iterate_async(&self, iterable: impl Into<JsValue>) -> Result<JsAsyncIterator<'_>, ExecutionError> {
+        let iterable = self.wrapper.serialize_value(iterable.into())?;
+        JsAsyncIterator::from_value(&iterable)
+    }
+
+    /// Parse a JSON document directly into Javascript values, like
+    /// `JSON.parse`.
+    ///
+    /// Unlike parsing into a `JsValue` in Rust, the document is converted
+    /// only once. The handle can be passed to scripts with
+    /// [JsValueRef::to_value], which keeps objects as `JsValue::Raw`.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let config = context.parse_json(r#"{"items": [1, 2, 3]}"#).unwrap();
+    /// context.set_global("config", config.to_value().unwrap()).unwrap();
+    /// let count = context.eval("config.items.length", "main.js").unwrap();
+    /// assert!(matches!(count, JsValue::Int(3)));
+    /// assert_eq!(context.eval_json("config.items.map(i => i * 2)", "main.js").unwrap(), "[2,4,6]");
+    /// ```
+    pub fn parse_json(&self, json: &str) -> Result<JsValueRef<'_>, ExecutionError> {
+        Ok(JsValueRef::from_value(self.wrapper.parse_json(json, "<json>")?))
+    }
+
+    /// Serialize a value to JSON like `JSON.stringify(value, null, indent)`.
+    ///
+    /// Fails for values that have no JSON representation, like `undefined`
+    /// or functions, and for cyclic objects.
+    pub fn stringify(&self, value: impl Into<JsValue>, indent: usize) -> Result<String, ExecutionError> {
+        self.wrapper.serialize_value(value.into())?.to_json(indent)
+    }
+
+    /// Evaluates Javascript code and returns its result as a compact JSON
+    /// string, without converting it to a `JsValue` first.
+    pub fn eval_json(&self, code: &str, filename: &str) -> Result<String, ExecutionError> {
+        self.wrapper.eval(code, JS_EVAL_TYPE_GLOBAL, filename)?.to_json(0)
+    }
+
+    /// Deep-copy a value into another context, like `structuredClone`.
+    ///
+    /// Objects, arrays, ArrayBuffers, typed arrays, Dates, Maps and Sets are
+    /// copied, keeping shared and cyclic references. Functions and host class
+    /// instances can not be copied. `JsValue::Raw` values must belong to this
+    /// context: using them in another context fails with
+    /// [ValueError::ForeignValue], copy them instead.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let source = Context::new().unwrap();
+    /// let target = Context::new().unwrap();
+    ///
+    /// let settings = source.eval("new Map([['theme', { dark: true }]])", "settings.js").unwrap();
+    /// assert!(target.set_global("settings", settings.clone()).is_err());
+    ///
+    /// let copy = source.copy_to(settings, &target).unwrap();
+    /// target.set_global("settings", copy).unwrap();
+    /// let dark = target.eval("settings.get('theme').dark", "main.js").unwrap();
+    /// assert!(matches!(dark, JsValue::Bool(true)));
+    /// ```
+    pub fn copy_to(&self, value: impl Into<JsValue>, target: &Context) -> Result<JsValue, ExecutionError> {
+        let value = self.wrapper.serialize_value(value.into())?;
+        Ok(self.wrapper.copy_value(&value, &target.wrapper)?.to_value()?)
+    }
+
+    /// Convert a value and get a handle to it, to apply Javascript
+    /// operations such as `typeof` or `==`, see [JsValueRef].
+    pub fn value_ref(&self, value: impl Into<JsValue>) -> Result<JsValueRef<'_>, ExecutionError> {
+        Ok(JsValueRef::from_value(self.wrapper.serialize_value(value.into())?))
+    }
+
+    /// A handle to the global object, e.g. to define read-only globals with
+    /// [JsObjectRef::define_property].
+    pub fn global_ref(&self) -> Result<JsObjectRef<'_>, ExecutionError> {
+        JsObjectRef::from_value(self.wrapper.global()?.into_value())
+    }
+
     /// Set a global variable.
     ///
     /// ```rust
@@ -332,9 +509,17 @@ impl Context {
         let global = self.wrapper.global()?;
         let v = self.wrapper.serialize_value(value.into())?;
         global.set_property(name, v)?;
+        self.wrapper.declarations().borrow_mut().infer_global(name);
         Ok(())
     }
 
+    /// Keep a value in a handle that can be stored across calls, see
+    /// [Persistent].
+    pub fn persist<T>(&self, value: impl Into<JsValue>) -> Result<Persistent<T>, ExecutionError> {
+        let value = self.wrapper.serialize_value(value.into())?;
+        Ok(Persistent::new(self.wrapper.persist(value)))
+    }
+
     /// Call a global function in the Javascript namespace.
     ///
     /// **Promises**:
@@ -371,6 +556,28 @@ impl Context {
         Ok(v)
     }
 
+    /// Call a global function that returns an object or array, and return
+    /// a handle that converts its contents on demand.
+    ///
+    /// See [call_function](Context::call_function) and
+    /// [eval_ref](Context::eval_ref).
+    pub fn call_ref(
+        &self,
+        function_name: &str,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsObjectRef<'_>, ExecutionError> {
+        let qargs = args
+            .into_iter()
+            .map(|arg| self.wrapper.serialize_value(arg.into()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let global = self.wrapper.global()?;
+        let func = global
+            .property_require(function_name)?
+            .try_into_function()?;
+        JsObjectRef::from_value(self.wrapper.call_function(func, qargs)?)
+    }
+
     /// Call a js function
     pub fn call_js_function(
         &self,
@@ -386,11 +593,182 @@ impl Context {
         Ok(v)
     }
 
+    fn serialize_args(
+        &self,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<Vec<bindings::OwnedJsValue<'_>>, ExecutionError> {
+        args.into_iter()
+            .map(|arg| self.wrapper.serialize_value(arg.into()))
+            .collect()
+    }
+
+    /// Call a js function with `this` bound to the given value.
+    pub fn call_with_this(
+        &self,
+        function: impl Into<JsValue>,
+        this: impl Into<JsValue>,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let qargs = self.serialize_args(args)?;
+        let func = self.wrapper.serialize_value(function.into())?.try_into_function()?;
+        let this = self.wrapper.serialize_value(this.into())?;
+        let v = self.wrapper.call_function_with_this(func, &this, qargs)?.to_value()?;
+        Ok(v)
+    }
+
+    /// Call the method `name` of an object, like `object.name(...args)`.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let counter = context.eval("({ count: 1, add(n) { return this.count += n; } })", "counter.js").unwrap();
+    /// let count = context.call_method(counter, "add", vec![2]).unwrap();
+    /// assert!(matches!(count, JsValue::Int(3)));
+    /// ```
+    pub fn call_method(
+        &self,
+        object: impl Into<JsValue>,
+        name: &str,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let qargs = self.serialize_args(args)?;
+        let object = self.wrapper.serialize_value(object.into())?.try_into_object()?;
+        let func = object.property_require(name)?.try_into_function()?;
+        let v = self.wrapper.call_function_with_this(func, object.as_value(), qargs)?.to_value()?;
+        Ok(v)
+    }
+
+    /// Call a function by its path from the global object, like
+    /// `a.b.c(...args)`, with `this` bound to `a.b`.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let max = context.call_path("Math.max", vec![1, 3, 2]).unwrap();
+    /// assert!(matches!(max, JsValue::Int(3)));
+    /// ```
+    pub fn call_path(
+        &self,
+        path: &str,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let qargs = self.serialize_args(args)?;
+        let mut segments = path.split('.').collect::<Vec<_>>();
+        let name = segments.pop().unwrap_or_default();
+        let mut object = self.wrapper.global()?;
+        for (index, segment) in segments.iter().enumerate() {
+            object = object
+                .property(segment)?
+                .ok_or_else(|| {
+                    ExecutionError::Internal(format!("Property '{}' not found", segments[..=index].join(".")))
+                })?
+                .try_into_object()?;
+        }
+        let func = object
+            .property(name)?
+            .ok_or_else(|| ExecutionError::Internal(format!("Property '{}' not found", path)))?
+            .try_into_function()?;
+        let v = self.wrapper.call_function_with_this(func, object.as_value(), qargs)?.to_value()?;
+        Ok(v)
+    }
+
+    /// Call a constructor, like `new constructor(...args)`.
+    ///
+    /// Exceptions are reported as in [call_function](Context::call_function).
+    pub fn construct(
+        &self,
+        constructor: impl Into<JsValue>,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let qargs = self.serialize_args(args)?;
+        let constructor = self.wrapper.serialize_value(constructor.into())?;
+        let v = self.wrapper.call_constructor(constructor, qargs)?.to_value()?;
+        Ok(v)
+    }
+
+    /// Call a global constructor, like `new Name(...args)`.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let map = context.construct_global("Map", vec![JsValue::Array(vec![])]).unwrap();
+    /// assert!(matches!(map, JsValue::Map(entries) if entries.is_empty()));
+    /// ```
+    pub fn construct_global(
+        &self,
+        name: &str,
+        args: impl IntoIterator<Item = impl Into<JsValue>>,
+    ) -> Result<JsValue, ExecutionError> {
+        let qargs = self.serialize_args(args)?;
+        let global = self.wrapper.global()?;
+        let constructor = global.property_require(name)?;
+        let v = self.wrapper.call_constructor(constructor, qargs)?.to_value()?;
+        Ok(v)
+    }
+
+    /// Create a new unique symbol, like `Symbol(description)`.
+    ///
+    /// Registered and well-known symbols can be created without a context,
+    /// see [JsSymbol].
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsSymbol, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let id = context.create_symbol("id").unwrap();
+    /// context.set_global("id", id.clone()).unwrap();
+    /// let symbol = context.eval_as::<JsSymbol>("id", "symbol.js").unwrap();
+    /// assert_eq!(symbol.description(), Some("id"));
+    /// assert!(matches!(id, JsValue::Symbol(id) if id == symbol));
+    /// ```
+    pub fn create_symbol(&self, description: &str) -> Result<JsValue, ExecutionError> {
+        let context = self.wrapper.context;
+        let description = bindings::make_cstring(description)?;
+        let symbol = unsafe { libquickjs_sys::JS_NewSymbol(context, description.as_ptr(), false) };
+        let symbol = bindings::OwnedJsValue::new(&self.wrapper, symbol);
+        if symbol.is_exception() {
+            unsafe { bindings::discard_exception(context) };
+            return Err(ExecutionError::Internal("Could not create symbol".into()));
+        }
+        Ok(symbol.to_value()?)
+    }
+
+    /// Create an ArrayBuffer that uses the memory of `owner` without copying
+    /// it, e.g. a `Vec<u8>` or `Box<[u8]>`.
+    ///
+    /// `owner` is dropped when the buffer is garbage collected or detached.
+    /// Javascript code can write to the bytes, so immutable shared owners
+    /// like `bytes::Bytes` or `Arc<[u8]>` can't be used; copy them into a
+    /// `Vec<u8>` instead.
+    ///
+    /// ```rust
+    /// use deft_quick_js::Context;
+    /// let context = Context::new().unwrap();
+    ///
+    /// let frames = context.external_array_buffer(vec![1u8, 2, 3]).unwrap();
+    /// context.set_global("frames", frames).unwrap();
+    /// let length = context.eval_as::<i32>("frames.byteLength", "frames.js").unwrap();
+    /// assert_eq!(length, 3);
+    /// ```
+    pub fn external_array_buffer<B>(&self, owner: B) -> Result<JsValue, ExecutionError>
+    where
+        B: AsMut<[u8]> + 'static,
+    {
+        let context = self.wrapper.context;
+        let buffer = bindings::convert::create_external_array_buffer(context, owner)?;
+        Ok(JsValue::Raw(RawJSValue::from_owned(context, buffer)))
+    }
+
     /// Add a global JS function that is backed by a Rust function or closure.
     ///
     /// The callback must satisfy several requirements:
-    /// * accepts 0 - 5 arguments
+    /// * accepts 0 - 16 arguments
     /// * each argument must be convertible from a JsValue
+    /// * `Option<T>` arguments may be omitted, a trailing `Rest<T>` collects
+    ///   any remaining arguments
     /// * must return a value
     /// * the return value must either:
     ///   - be convertible to JsValue
@@ -417,13 +795,160 @@ impl Context {
         name: &str,
         callback: impl Callback<F> + 'static,
     ) -> Result<(), ExecutionError> {
-        self.wrapper.add_callback(name, callback)
+        let declaration = callback.declaration();
+        self.wrapper.add_callback(name, callback)?;
+        self.declare(name, declaration);
+        Ok(())
+    }
+
+    /// Create a JS function that is backed by a Rust function or closure,
+    /// without adding it to the global object.
+    ///
+    /// Accepts the same callbacks as [Context::add_callback].
+    ///
+    /// ```rust
+    /// use deft_quick_js::{Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// let add = context.create_callback("add", |a: i32, b: i32| a + b).unwrap();
+    /// let output = context.call_js_function(add, vec![1, 2]).unwrap();
+    /// assert!(matches!(output, JsValue::Int(3)));
+    /// ```
+    pub fn create_callback<F>(
+        &self,
+        name: &str,
+        callback: impl Callback<F> + 'static,
+    ) -> Result<JsValue, ExecutionError> {
+        let function = self.wrapper.create_callback(name, callback)?;
+        Ok(function.into_value().to_value()?)
+    }
+
+    /// Add a global JS function that can also be invoked with `new`.
+    ///
+    /// Functions added with [Context::add_callback] are not constructors.
+    /// The callback can tell both kinds of calls apart with
+    /// [CallContext::new_target], and must return an object when it is
+    /// invoked with `new`, otherwise a `TypeError` is thrown.
+    ///
+    /// ```rust
+    /// use deft_quick_js::{CallContext, Context, JsValue};
+    /// let context = Context::new().unwrap();
+    ///
+    /// context.add_constructor("Point", |call: &CallContext| -> Result<JsValue, String> {
+    ///     if call.new_target().is_none() {
+    ///         return Err("Point must be called with new".into());
+    ///     }
+    ///     let x = call.arg(0).map_err(|e| e.to_string())?;
+    ///     Ok(JsValue::Object(vec![("x".to_string(), x)].into_iter().collect()))
+    /// }).unwrap();
+    ///
+    /// let x = context.eval_as::<i32>("new Point(3).x", "point.js").unwrap();
+    /// assert_eq!(x, 3);
+    /// ```
+    pub fn add_constructor<C, R>(&self, name: &str, callback: C) -> Result<(), ExecutionError>
+    where
+        C: Fn(&CallContext) -> R + 'static,
+        R: IntoCallbackResult + 'static,
+    {
+        let declaration = Callback::declaration(&callback);
+        self.wrapper.add_constructor(name, callback)?;
+        self.declare(name, declaration);
+        Ok(())
+    }
+
+    /// Add a global JS function that is backed by a `FnMut` closure.
+    ///
+    /// Accepts the same closures as [Context::add_callback], but allows them
+    /// to mutate their state. Calling the function re-entrantly (e.g. from
+    /// code evaluated inside of the callback) raises a Javascript error.
+    ///
+    /// ```rust
+    /// use deft_quick_js::Context;
+    /// let context = Context::new().unwrap();
+    ///
+    /// let mut calls = 0;
+    /// context.add_callback_mut("count", move || {
+    ///     calls += 1;
+    ///     calls
+    /// }).unwrap();
+    ///
+    /// let output = context.eval_as::<i32>("count(); count()", "count.js").unwrap();
+    /// assert_eq!(output, 2);
+    /// ```
+    pub fn add_callback_mut<F>(
+        &self,
+        name: &str,
+        callback: impl CallbackMut<F> + 'static,
+    ) -> Result<(), ExecutionError> {
+        let declaration = callback.declaration();
+        self.wrapper.add_callback_mut(name, callback)?;
+        self.declare(name, declaration);
+        Ok(())
+    }
+
+    /// Remove a global JS function that was added with [Context::add_callback].
+    ///
+    /// The Rust closure is dropped once Javascript code no longer holds a
+    /// reference to the function.
+    ///
+    /// ```rust
+    /// use deft_quick_js::Context;
+    /// let context = Context::new().unwrap();
+    ///
+    /// context.add_callback("add", |a: i32, b: i32| a + b).unwrap();
+    /// context.remove_callback("add").unwrap();
+    ///
+    /// assert!(context.eval("add(1, 2)", "remove.js").is_err());
+    /// ```
+    pub fn remove_callback(&self, name: &str) -> Result<(), ExecutionError> {
+        self.wrapper.remove_callback(name)?;
+        self.wrapper.declarations().borrow_mut().remove_global(name);
+        Ok(())
+    }
+
+    /// Set the TypeScript declaration of a global.
+    ///
+    /// Replaces the declaration that was inferred when the global was added.
+    /// See the [typescript] module.
+    pub fn declare(&self, name: &str, declaration: impl Into<TsDeclaration>) {
+        self.wrapper
+            .declarations()
+            .borrow_mut()
+            .set_global(name, declaration.into());
+    }
+
+    /// Render the declarations of the globals and native modules that were
+    /// added from Rust as the contents of a `.d.ts` file.
+    ///
+    /// The types of globals set with [Context::set_global] are inferred from
+    /// their current values.
+    pub fn typescript_declarations(&self) -> String {
+        // Converting the values can run scripts, which may add declarations.
+        let names = self.wrapper.declarations().borrow().inferred_globals();
+        let inferred = names
+            .into_iter()
+            .filter_map(|name| {
+                let value = self.wrapper.global().ok()?.property(&name).ok()??.to_value_deep().ok()?;
+                Some((name, typescript::value_type(&value)))
+            })
+            .collect();
+        self.wrapper.declarations().borrow().render(&inferred)
+    }
+
+    /// Write [Context::typescript_declarations] to a file.
+    pub fn write_typescript_declarations(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.typescript_declarations())
     }
     
     pub fn execute_pending_job(&self) -> Result<bool, ExecutionError> {
         self.wrapper.execute_pending_job()
     }
 
+    /// Run the garbage collector, which also collects reference cycles.
+    pub fn run_gc(&self) {
+        self.wrapper.run_gc();
+    }
+
     /// Execute module
     pub fn execute_module(&self, module_name: &str) -> Result<(), ExecutionError> {
         self.wrapper.execute_module(module_name)
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use libquickjs_sys as q;
+
+use crate::bindings::OwnedJsValue;
+use crate::{Context, ExecutionError, JsObjectRef, JsValue, ValueError};
+
+/// A value kept alive by a [Persistent] handle.
+///
+/// The context releases the value when it is reset or dropped, after that
+/// the slot is empty.
+pub(crate) struct PersistentSlot {
+    context: *mut q::JSContext,
+    value: Cell<Option<q::JSValue>>,
+}
+
+impl PersistentSlot {
+    /// Takes over the reference of `value`.
+    pub(crate) fn new(context: *mut q::JSContext, value: q::JSValue) -> Self {
+        PersistentSlot {
+            context,
+            value: Cell::new(Some(value)),
+        }
+    }
+
+    /// Free the value, if it was not released yet.
+    pub(crate) fn release(&self) {
+        if let Some(value) = self.value.take() {
+            unsafe { q::JS_FreeValue(self.context, value) };
+        }
+    }
+}
+
+impl Drop for PersistentSlot {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// A handle to a Javascript value that can be kept across calls.
+///
+/// Unlike `JsValue::Raw`, the handle knows which context it belongs to: the
+/// value is released when that context is reset or dropped, and any later
+/// use fails with [ExecutionError::InvalidHandle] instead of touching freed
+/// memory. Dropping the handle afterwards is safe.
+///
+/// `T` is the Rust type the value is converted to by [get](Persistent::get).
+///
+/// ```rust
+/// use deft_quick_js::{Context, ExecutionError, Persistent};
+/// let context = Context::new().unwrap();
+///
+/// let counter = context.eval("({ count: 1 })", "counter.js").unwrap();
+/// let counter: Persistent = context.persist(counter).unwrap();
+/// counter.get_ref(&context).unwrap().set("count", 2).unwrap();
+///
+/// let context = context.reset().unwrap();
+/// assert!(!counter.is_alive());
+/// assert!(matches!(counter.get(&context), Err(ExecutionError::InvalidHandle)));
+/// ```
+pub struct Persistent<T = JsValue> {
+    slot: Rc<PersistentSlot>,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Persistent<T> {
+    pub(crate) fn new(slot: Rc<PersistentSlot>) -> Self {
+        Persistent {
+            slot,
+            _type: PhantomData,
+        }
+    }
+
+    /// Whether the value is still held, i.e. its context was not reset or
+    /// dropped.
+    pub fn is_alive(&self) -> bool {
+        let value = self.slot.value.get();
+        value.is_some()
+    }
+
+    /// Whether the handle can be used with `context`.
+    pub fn belongs_to(&self, context: &Context) -> bool {
+        self.is_alive() && self.slot.context == context.wrapper.context
+    }
+
+    fn value<'a>(&self, context: &'a Context) -> Result<OwnedJsValue<'a>, ExecutionError> {
+        match self.slot.value.get() {
+            Some(value) if self.slot.context == context.wrapper.context => {
+                unsafe { q::JS_DupValue(self.slot.context, value) };
+                Ok(OwnedJsValue::new(&context.wrapper, value))
+            }
+            _ => Err(ExecutionError::InvalidHandle),
+        }
+    }
+
+    /// Convert the value, as [Context::eval] would.
+    pub fn to_value(&self, context: &Context) -> Result<JsValue, ExecutionError> {
+        Ok(self.value(context)?.to_value()?)
+    }
+
+    /// Get a handle to the object, see [JsObjectRef].
+    pub fn get_ref<'a>(&self, context: &'a Context) -> Result<JsObjectRef<'a>, ExecutionError> {
+        JsObjectRef::from_value(self.value(context)?)
+    }
+
+    /// Change the type the value is converted to.
+    pub fn cast<U>(self) -> Persistent<U> {
+        Persistent::new(self.slot)
+    }
+}
+
+impl<T> Persistent<T>
+where
+    T: TryFrom<JsValue>,
+    T::Error: Into<ValueError>,
+{
+    /// Convert the value to `T`.
+    pub fn get(&self, context: &Context) -> Result<T, ExecutionError> {
+        let value = self.to_value(context)?;
+        Ok(T::try_from(value).map_err(Into::into)?)
+    }
+}
+
+impl<T> Clone for Persistent<T> {
+    /// The clone refers to the same value.
+    fn clone(&self) -> Self {
+        Persistent::new(self.slot.clone())
+    }
+}
+
+impl<T> fmt::Debug for Persistent<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Persistent").field("alive", &self.is_alive()).finish()
+    }
+}
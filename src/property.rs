@@ -0,0 +1,175 @@
+use std::fmt;
+
+use libquickjs_sys as q;
+
+use crate::bindings::ContextWrapper;
+use crate::{Callback, ExecutionError, JsValue};
+
+type Accessor = Box<dyn FnOnce(&ContextWrapper, &str) -> Result<JsValue, ExecutionError>>;
+
+/// A property descriptor for [JsObjectRef::define_property](crate::JsObjectRef::define_property).
+///
+/// A property is either a data property holding a value, or an accessor
+/// property backed by Rust callbacks. Properties are writable, enumerable
+/// and configurable unless restricted.
+///
+/// ```rust
+/// use deft_quick_js::{Context, JsValue, Property};
+/// let context = Context::new().unwrap();
+///
+/// let config = context.eval_ref("({})", "config.js").unwrap();
+/// config.define_property("version", Property::value("1.0").read_only()).unwrap();
+/// config.define_property("uptime", Property::accessor().getter(|| 42)).unwrap();
+/// config.freeze().unwrap();
+/// context.global_ref().unwrap().set("config", config.as_value_ref().to_value().unwrap()).unwrap();
+///
+/// let value = context.eval("config.version = '2.0'; config.version + ' ' + config.uptime", "main.js").unwrap();
+/// assert_eq!(value.as_str(), Some("1.0 42"));
+/// ```
+pub struct Property {
+    kind: PropertyKind,
+    writable: bool,
+    enumerable: bool,
+    configurable: bool,
+}
+
+enum PropertyKind {
+    Value(JsValue),
+    Accessor {
+        getter: Option<Accessor>,
+        setter: Option<Accessor>,
+    },
+}
+
+impl Property {
+    /// A data property holding `value`.
+    pub fn value(value: impl Into<JsValue>) -> Self {
+        Self::new(PropertyKind::Value(value.into()))
+    }
+
+    /// An accessor property, without getter or setter yet.
+    ///
+    /// Reading a property without getter gives `undefined`, writing one
+    /// without setter is ignored, or throws in strict mode.
+    pub fn accessor() -> Self {
+        Self::new(PropertyKind::Accessor {
+            getter: None,
+            setter: None,
+        })
+    }
+
+    fn new(kind: PropertyKind) -> Self {
+        Property {
+            kind,
+            writable: true,
+            enumerable: true,
+            configurable: true,
+        }
+    }
+
+    /// Set the getter of an accessor property.
+    ///
+    /// Panics if this is a data property.
+    pub fn getter<F>(mut self, callback: impl Callback<F> + 'static) -> Self {
+        match &mut self.kind {
+            PropertyKind::Accessor { getter, .. } => *getter = Some(Self::callback("get", callback)),
+            PropertyKind::Value(_) => panic!("a data property can not have a getter"),
+        }
+        self
+    }
+
+    /// Set the setter of an accessor property, it is called with the new
+    /// value.
+    ///
+    /// Panics if this is a data property.
+    pub fn setter<F>(mut self, callback: impl Callback<F> + 'static) -> Self {
+        match &mut self.kind {
+            PropertyKind::Accessor { setter, .. } => *setter = Some(Self::callback("set", callback)),
+            PropertyKind::Value(_) => panic!("a data property can not have a setter"),
+        }
+        self
+    }
+
+    fn callback<F>(prefix: &'static str, callback: impl Callback<F> + 'static) -> Accessor {
+        Box::new(move |wrapper: &ContextWrapper, name: &str| {
+            let function = wrapper.create_callback(&format!("{} {}", prefix, name), callback)?;
+            Ok(function.into_value().to_value()?)
+        })
+    }
+
+    /// Whether the value can be assigned. Ignored for accessor properties.
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    /// Whether the property shows up in `Object.keys` and `for ... in`.
+    pub fn enumerable(mut self, enumerable: bool) -> Self {
+        self.enumerable = enumerable;
+        self
+    }
+
+    /// Whether the property can be deleted or redefined.
+    pub fn configurable(mut self, configurable: bool) -> Self {
+        self.configurable = configurable;
+        self
+    }
+
+    /// Neither writable nor configurable.
+    pub fn read_only(self) -> Self {
+        self.writable(false).configurable(false)
+    }
+
+    /// The values, getter and setter to pass to `JS_DefineProperty` along
+    /// with its flags.
+    pub(crate) fn into_descriptor(
+        self,
+        wrapper: &ContextWrapper,
+        name: &str,
+    ) -> Result<(u32, JsValue, JsValue, JsValue), ExecutionError> {
+        let mut flags = q::JS_PROP_HAS_ENUMERABLE | q::JS_PROP_HAS_CONFIGURABLE;
+        if self.enumerable {
+            flags |= q::JS_PROP_ENUMERABLE;
+        }
+        if self.configurable {
+            flags |= q::JS_PROP_CONFIGURABLE;
+        }
+        match self.kind {
+            PropertyKind::Value(value) => {
+                flags |= q::JS_PROP_HAS_VALUE | q::JS_PROP_HAS_WRITABLE;
+                if self.writable {
+                    flags |= q::JS_PROP_WRITABLE;
+                }
+                Ok((flags, value, JsValue::Undefined, JsValue::Undefined))
+            }
+            PropertyKind::Accessor { getter, setter } => {
+                flags |= q::JS_PROP_HAS_GET | q::JS_PROP_HAS_SET;
+                let getter = match getter {
+                    Some(getter) => getter(wrapper, name)?,
+                    None => JsValue::Undefined,
+                };
+                let setter = match setter {
+                    Some(setter) => setter(wrapper, name)?,
+                    None => JsValue::Undefined,
+                };
+                Ok((flags, JsValue::Undefined, getter, setter))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Property {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Property");
+        match &self.kind {
+            PropertyKind::Value(value) => debug.field("value", value).field("writable", &self.writable),
+            PropertyKind::Accessor { getter, setter } => debug
+                .field("getter", &getter.is_some())
+                .field("setter", &setter.is_some()),
+        };
+        debug
+            .field("enumerable", &self.enumerable)
+            .field("configurable", &self.configurable)
+            .finish()
+    }
+}
@@ -49,19 +49,19 @@ fn test_eval_pass() {
     let obj_cases = vec![
         (
             r#" {"a": null, "b": undefined} "#,
-            Ok(JsValue::Object(HashMap::from_iter(vec![
+            Ok(JsValue::Object(JsObject::from_iter(vec![
                 ("a".to_string(), JsValue::Null),
                 ("b".to_string(), JsValue::Undefined),
             ]))),
         ),
         (
             r#" {a: 1, b: true, c: {c1: false}} "#,
-            Ok(JsValue::Object(HashMap::from_iter(vec![
+            Ok(JsValue::Object(JsObject::from_iter(vec![
                 ("a".to_string(), JsValue::Int(1)),
                 ("b".to_string(), JsValue::Bool(true)),
                 (
                     "c".to_string(),
-                    JsValue::Object(HashMap::from_iter(vec![(
+                    JsValue::Object(JsObject::from_iter(vec![(
                         "c1".to_string(),
                         JsValue::Bool(false),
                     )])),
@@ -622,3 +622,1605 @@ fn test_global_setter() {
     ctx.set_global("a", "a").unwrap();
     ctx.eval("a + 1").unwrap();
 }
+
+#[test]
+fn test_traced_resource_cycle_is_collected() {
+    use std::{cell::Cell, rc::Rc};
+
+    struct Listeners {
+        listener: Option<JsValue>,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl Drop for Listeners {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    impl Trace for Listeners {
+        fn trace(&self, tracer: &Tracer) {
+            self.listener.trace(tracer);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let ctx = Context::new().unwrap();
+    let listeners = JsValue::new_traced_resource(Listeners {
+        listener: None,
+        dropped: dropped.clone(),
+    });
+    // Every conversion of a resource gives the same object.
+    ctx.set_global("listeners", listeners.clone()).unwrap();
+    ctx.set_global("alias", listeners.clone()).unwrap();
+    assert!(matches!(ctx.eval("listeners === alias", "same.js").unwrap(), JsValue::Bool(true)));
+    ctx.add_callback("listen", |res: JsValue, f: JsValue| {
+        res.as_resource(|l: &mut Listeners| l.listener = Some(f));
+        0
+    })
+    .unwrap();
+    // The listener closes over the resource it is registered on.
+    ctx.eval(
+        "(function() { const l = listeners; listen(l, () => l); })(); listeners = alias = undefined;",
+        "cycle.js",
+    )
+    .unwrap();
+
+    // The resource is still held from Rust, so its listener stays alive.
+    ctx.run_gc();
+    assert!(!dropped.get());
+    let listener = listeners.as_resource(|l: &mut Listeners| l.listener.clone()).unwrap().unwrap();
+    ctx.set_global("listener", listener).unwrap();
+    assert_eq!(ctx.eval("typeof listener()", "alive.js").unwrap().as_str(), Some("object"));
+    ctx.eval("listener = undefined", "release.js").unwrap();
+
+    drop(listeners);
+    ctx.run_gc();
+    assert!(dropped.get());
+}
+
+#[test]
+fn test_callback_closure_dropped_with_function() {
+    use std::rc::Rc;
+
+    struct Guard(Rc<()>);
+    impl std::panic::RefUnwindSafe for Guard {}
+
+    let guard = Rc::new(());
+    let ctx = Context::new().unwrap();
+
+    let g = Guard(guard.clone());
+    ctx.add_callback("cb", move || {
+        let _ = &g;
+        1
+    })
+    .unwrap();
+    assert_eq!(Rc::strong_count(&guard), 2);
+    assert_eq!(ctx.eval_as::<i32>("cb()", "cb.js").unwrap(), 1);
+    assert_eq!(ctx.eval_as::<String>("cb.name", "cb.js").unwrap(), "cb");
+
+    // Replacing the global releases the previous closure.
+    let g = Guard(guard.clone());
+    ctx.add_callback("cb", move |a: i32, b: i32| {
+        let _ = &g;
+        a + b
+    })
+    .unwrap();
+    assert_eq!(Rc::strong_count(&guard), 2);
+    assert_eq!(ctx.eval_as::<i32>("cb.length", "cb.js").unwrap(), 2);
+
+    ctx.remove_callback("cb").unwrap();
+    assert_eq!(Rc::strong_count(&guard), 1);
+    assert!(ctx.eval("cb(1, 2)", "cb.js").is_err());
+
+    // Only globals that were added as callbacks are removed.
+    ctx.set_global("plain", 1).unwrap();
+    assert!(ctx.remove_callback("plain").is_err());
+    ctx.add_callback("replaced", || 1).unwrap();
+    ctx.eval("replaced = 2", "cb.js").unwrap();
+    assert!(ctx.remove_callback("replaced").is_err());
+    assert_eq!(ctx.eval_as::<i32>("plain + replaced", "cb.js").unwrap(), 3);
+}
+
+#[test]
+fn test_callback_with_call_context() {
+    let ctx = Context::new().unwrap();
+
+    ctx.add_callback("describe", |call: &CallContext| -> Result<String, ExecutionError> {
+        let name = call.this_property("name")?;
+        let doubled = call.context().call_function("double", vec![call.arg(0)?])?;
+        Ok(format!("{} {:?} {}", name.as_str().unwrap(), doubled, call.args_len()))
+    })
+    .unwrap();
+    ctx.eval("function double(x) { return x * 2; }", "cc.js").unwrap();
+
+    let res = ctx
+        .eval_as::<String>("({ name: 'obj', describe }).describe(21, 'extra')", "cc.js")
+        .unwrap();
+    assert_eq!(res, "obj Int(42) 2");
+
+    // Re-entrant globals and new.target.
+    ctx.add_constructor("ctor", |call: &CallContext| -> Result<JsValue, ExecutionError> {
+        call.context().set_global("constructed", call.new_target().is_some())?;
+        match call.arg(0)? {
+            JsValue::Undefined => Ok(JsValue::Object(JsObject::new())),
+            value => Ok(value),
+        }
+    })
+    .unwrap();
+    assert_eq!(ctx.eval_as::<bool>("typeof ctor() === 'object'", "cc.js").unwrap(), true);
+    assert_eq!(ctx.eval_as::<bool>("constructed", "cc.js").unwrap(), false);
+    assert_eq!(ctx.eval_as::<bool>("typeof new ctor() === 'object'", "cc.js").unwrap(), true);
+    assert_eq!(ctx.eval_as::<bool>("constructed", "cc.js").unwrap(), true);
+    // `new` must give an object.
+    assert_eq!(
+        ctx.eval_as::<bool>("try { new ctor(1); false } catch (e) { e instanceof TypeError }", "cc.js").unwrap(),
+        true
+    );
+
+    // Plain callbacks are not constructors.
+    assert_eq!(
+        ctx.eval_as::<bool>("try { new describe(); false } catch (e) { e instanceof TypeError }", "cc.js").unwrap(),
+        true
+    );
+}
+
+#[test]
+fn test_callback_optional_and_rest_args() {
+    let ctx = Context::new().unwrap();
+
+    ctx.add_callback("greet", |name: String, greeting: Option<String>| {
+        format!("{}, {}", greeting.unwrap_or_else(|| "Hello".into()), name)
+    })
+    .unwrap();
+    assert_eq!(ctx.eval_as::<String>("greet('a')", "args.js").unwrap(), "Hello, a");
+    assert_eq!(ctx.eval_as::<String>("greet('a', undefined)", "args.js").unwrap(), "Hello, a");
+    assert_eq!(ctx.eval_as::<String>("greet('a', 'Hi')", "args.js").unwrap(), "Hi, a");
+    assert_eq!(ctx.eval_as::<i32>("greet.length", "args.js").unwrap(), 1);
+    let err = ctx.eval("greet()", "args.js").unwrap_err().to_string();
+    assert!(err.contains("Invalid argument count: Expected 1 to 2, got 0"), "{}", err);
+
+    ctx.add_callback("sum", |first: i32, rest: Rest<i32>| {
+        first + rest.into_vec().into_iter().sum::<i32>()
+    })
+    .unwrap();
+    assert_eq!(ctx.eval_as::<i32>("sum(1)", "args.js").unwrap(), 1);
+    assert_eq!(ctx.eval_as::<i32>("sum(1, 2, 3, 4)", "args.js").unwrap(), 10);
+    assert_eq!(ctx.eval_as::<i32>("sum.length", "args.js").unwrap(), 1);
+
+    ctx.add_callback("any", |v: Option<String>| v.is_none()).unwrap();
+    assert_eq!(ctx.eval_as::<bool>("any()", "args.js").unwrap(), true);
+    assert_eq!(ctx.eval_as::<bool>("any('')", "args.js").unwrap(), false);
+
+    ctx.add_callback(
+        "many",
+        |a: i32, b: i32, c: i32, d: i32, e: i32, f: i32, g: i32, h: i32| {
+            a + b + c + d + e + f + g + h
+        },
+    )
+    .unwrap();
+    assert_eq!(ctx.eval_as::<i32>("many(1, 2, 3, 4, 5, 6, 7, 8)", "args.js").unwrap(), 36);
+    assert_eq!(ctx.eval_as::<i32>("many.length", "args.js").unwrap(), 8);
+
+    // Argument types can have their own error type.
+    struct Even(i32);
+    struct OddError;
+    impl From<OddError> for ValueError {
+        fn from(_: OddError) -> Self {
+            ValueError::Internal("odd number".into())
+        }
+    }
+    impl std::convert::TryFrom<JsValue> for Even {
+        type Error = OddError;
+
+        fn try_from(value: JsValue) -> Result<Self, OddError> {
+            match value {
+                JsValue::Int(n) if n % 2 == 0 => Ok(Even(n)),
+                _ => Err(OddError),
+            }
+        }
+    }
+    ctx.add_callback("half", |n: Even, rest: Rest<Even>| n.0 / 2 + rest.0.len() as i32).unwrap();
+    assert_eq!(ctx.eval_as::<i32>("half(4, 2)", "args.js").unwrap(), 3);
+    let err = ctx.eval("half(3)", "args.js").unwrap_err().to_string();
+    assert!(err.contains("odd number"), "{}", err);
+}
+
+#[test]
+fn test_callback_mut() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let ctx = Context::new().unwrap();
+
+    // Rc<RefCell<_>> is not RefUnwindSafe.
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let seen2 = seen.clone();
+    let mut calls = 0;
+    ctx.add_callback_mut("record", move |value: String| {
+        calls += 1;
+        seen2.borrow_mut().push(value);
+        calls
+    })
+    .unwrap();
+    assert_eq!(ctx.eval_as::<i32>("record('a'); record('b')", "mut.js").unwrap(), 2);
+    assert_eq!(*seen.borrow(), vec!["a".to_string(), "b".to_string()]);
+
+    ctx.add_callback_mut("reenter", |call: &CallContext| {
+        call.context().eval("reenter()", "mut.js").map(|_| true)
+    })
+    .unwrap();
+    let err = ctx.eval("reenter()", "mut.js").unwrap_err().to_string();
+    assert!(err.contains("can not be called re-entrantly"), "{}", err);
+}
+
+#[test]
+fn test_callback_panic_policy() {
+    PanicPolicy::record_locations();
+    let ctx = Context::new().unwrap();
+    ctx.add_callback("boom", || -> i32 { panic!("boom!") }).unwrap();
+
+    // By default the panic becomes a catchable JS error.
+    let msg = ctx
+        .eval_as::<String>("try { boom() } catch (e) { e.message }", "panic.js")
+        .unwrap();
+    assert!(msg.starts_with("Callback [boom] panicked at "), "{}", msg);
+    assert!(msg.contains(".rs:") && msg.ends_with(": boom!"), "{}", msg);
+
+    let ctx = Context::builder()
+        .panic_policy(PanicPolicy::Resume)
+        .build()
+        .unwrap();
+    ctx.add_callback("boom", || -> i32 { panic!("boom!") }).unwrap();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        ctx.eval("try { boom() } catch (e) { 'caught' } finally { }", "panic.js")
+    }));
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom!"));
+
+    // The context stays usable.
+    assert_eq!(ctx.eval_as::<i32>("1 + 1", "panic.js").unwrap(), 2);
+
+    // A panic whose error does not reach Rust is not resumed by later errors.
+    let other = Context::builder()
+        .panic_policy(PanicPolicy::Resume)
+        .build()
+        .unwrap();
+    let proxy = ctx.eval("new Proxy({}, { set() { boom() } })", "panic.js").unwrap();
+    let symbol = match ctx.create_symbol("key").unwrap() {
+        JsValue::Symbol(symbol) => symbol,
+        other => panic!("{:?}", other),
+    };
+    assert!(proxy.set_symbol_property(&symbol, JsValue::Int(1)).is_err());
+    assert!(matches!(other.eval("throw new Error('other')", "panic.js"), Err(ExecutionError::Exception(_))));
+    assert!(matches!(ctx.eval("throw new Error('later')", "panic.js"), Err(ExecutionError::Exception(_))));
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derive_into_and_from_js() {
+    use std::convert::TryFrom;
+
+    #[derive(IntoJs, FromJs, Debug, PartialEq, Clone)]
+    #[js(rename_all = "camelCase")]
+    struct Address {
+        street_name: String,
+        #[js(rename = "zip")]
+        zip_code: i32,
+    }
+
+    fn default_role() -> String {
+        "guest".into()
+    }
+
+    #[derive(IntoJs, FromJs, Debug, PartialEq, Clone)]
+    #[js(rename_all = "camelCase")]
+    struct User {
+        user_name: String,
+        email: Option<String>,
+        #[js(default)]
+        login_count: i32,
+        #[js(default = "default_role")]
+        role: String,
+        address: Address,
+        tags: Vec<String>,
+    }
+
+    #[derive(IntoJs, FromJs, Debug, PartialEq, Clone)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Rect { width: i32, height: i32 },
+    }
+
+    #[derive(IntoJs, FromJs, Debug, PartialEq, Clone)]
+    #[js(tag = "kind", rename_all = "lowercase")]
+    enum Event {
+        Click { x: i32, y: i32 },
+        Close,
+    }
+
+    #[derive(IntoJs, FromJs, Debug, PartialEq, Clone)]
+    #[js(untagged)]
+    enum NumberOrText {
+        Number(i32),
+        Text(String),
+    }
+
+    let ctx = Context::new().unwrap();
+
+    let user = ctx
+        .eval_as::<User>(
+            "({ userName: 'ann', address: { streetName: 'Main', zip: 123 }, tags: ['a'] })",
+            "derive.js",
+        )
+        .unwrap();
+    assert_eq!(
+        user,
+        User {
+            user_name: "ann".into(),
+            email: None,
+            login_count: 0,
+            role: "guest".into(),
+            address: Address { street_name: "Main".into(), zip_code: 123 },
+            tags: vec!["a".into()],
+        }
+    );
+
+    let err = ctx.eval_as::<User>("({ tags: [] })", "derive.js").unwrap_err();
+    assert!(err.to_string().contains("missing field `userName`"), "{}", err);
+
+    // Round trip through a callback.
+    ctx.add_callback("rename", |mut user: User, name: String| {
+        user.user_name = name;
+        user
+    })
+    .unwrap();
+    ctx.set_global("user", user.clone()).unwrap();
+    assert_eq!(
+        ctx.eval_as::<String>("rename(user, 'bob').userName + rename(user, 'x').address.streetName", "derive.js")
+            .unwrap(),
+        "bobMain"
+    );
+    assert_eq!(ctx.eval_as::<bool>("'email' in user", "derive.js").unwrap(), false);
+    assert_eq!(
+        ctx.eval_as::<String>("Object.keys(user).join()", "derive.js").unwrap(),
+        "userName,loginCount,role,address,tags"
+    );
+
+    for shape in vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { width: 2, height: 3 }] {
+        ctx.set_global("shape", shape.clone()).unwrap();
+        assert_eq!(ctx.eval_as::<Shape>("shape", "derive.js").unwrap(), shape);
+    }
+    assert_eq!(ctx.eval_as::<Shape>("'Empty'", "derive.js").unwrap(), Shape::Empty);
+    assert_eq!(
+        ctx.eval_as::<Shape>("({ Rect: { width: 1, height: 2 } })", "derive.js").unwrap(),
+        Shape::Rect { width: 1, height: 2 }
+    );
+
+    assert_eq!(
+        ctx.eval_as::<Event>("({ kind: 'click', x: 1, y: 2 })", "derive.js").unwrap(),
+        Event::Click { x: 1, y: 2 }
+    );
+    ctx.set_global("event", Event::Close).unwrap();
+    assert_eq!(ctx.eval_as::<String>("event.kind", "derive.js").unwrap(), "close");
+
+    assert_eq!(
+        NumberOrText::try_from(JsValue::String("a".into())).unwrap(),
+        NumberOrText::Text("a".into())
+    );
+    assert_eq!(ctx.eval_as::<NumberOrText>("7", "derive.js").unwrap(), NumberOrText::Number(7));
+    assert!(ctx.eval_as::<NumberOrText>("null", "derive.js").is_err());
+}
+
+#[cfg(feature = "derive")]
+#[js_module(name = "geometry")]
+mod geometry {
+    use super::*;
+
+    /// Add two numbers.
+    #[js_function]
+    pub fn add(a: i32, b: Option<i32>) -> i32 {
+        a + b.unwrap_or(0)
+    }
+
+    #[js_function(name = "checkedDiv")]
+    pub fn checked_div(a: i32, b: i32) -> Result<i32, String> {
+        a.checked_div(b).ok_or_else(|| "division by zero".to_string())
+    }
+
+    /// A point in 2D space.
+    #[js_class]
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Point {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    #[js_methods(rename_all = "camelCase")]
+    impl Point {
+        #[js(constructor)]
+        pub fn new(x: i32, y: i32) -> Self {
+            Point { x, y }
+        }
+
+        pub fn origin() -> Point {
+            Point { x: 0, y: 0 }
+        }
+
+        #[js(get)]
+        pub fn x(&self) -> i32 {
+            self.x
+        }
+
+        #[js(set)]
+        pub fn set_x(&mut self, x: i32) {
+            self.x = x;
+        }
+
+        pub fn move_by(&mut self, dx: i32, dy: i32) {
+            self.x += dx;
+            self.y += dy;
+        }
+
+        pub fn distance(&self, other: host::Instance<Point>) -> f64 {
+            let other = other.borrow();
+            f64::from((self.x - other.x).pow(2) + (self.y - other.y).pow(2)).sqrt()
+        }
+
+        #[js(skip)]
+        pub fn hidden(&self) {}
+    }
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_host_functions_and_classes() {
+    use std::convert::TryFrom;
+
+    let ctx = Context::new().unwrap();
+    geometry::register(&ctx).unwrap();
+
+    assert_eq!(ctx.eval_as::<i32>("add(1, 2) + add(3)", "host.js").unwrap(), 6);
+    assert_eq!(ctx.eval_as::<i32>("add.length", "host.js").unwrap(), 1);
+    assert_eq!(ctx.eval_as::<i32>("checkedDiv(7, 2)", "host.js").unwrap(), 3);
+    let err = ctx.eval("checkedDiv(1, 0)", "host.js").unwrap_err();
+    assert!(err.to_string().contains("division by zero"), "{}", err);
+    let err = ctx.eval("add('x')", "host.js").unwrap_err();
+    assert!(err.to_string().contains("Invalid argument `a` of add"), "{}", err);
+    let err = ctx.eval("add()", "host.js").unwrap_err();
+    assert!(err.to_string().contains("Invalid argument count"), "{}", err);
+    assert_eq!(<geometry::js_add as host::HostFunction>::DOC, "Add two numbers.");
+    assert_eq!(<geometry::Point as host::HostClass>::DOC, "A point in 2D space.");
+
+    let output = ctx
+        .eval_as::<String>(
+            r#"
+            const p = new Point(1, 2);
+            p.moveBy(2, 2);
+            p.x = p.x * 2;
+            [p instanceof Point, p.x, p.distance(Point.origin()), typeof p.hidden].join()
+            "#,
+            "host.js",
+        )
+        .unwrap();
+    assert_eq!(output, "true,6,7.211102550927978,undefined");
+
+    // Instances created in Rust get the class prototype.
+    ctx.set_global("q", geometry::Point::new(3, 4)).unwrap();
+    assert_eq!(ctx.eval_as::<f64>("q.distance(new Point(0, 0))", "host.js").unwrap(), 5.0);
+    let q = host::Instance::<geometry::Point>::try_from(ctx.eval("q", "host.js").unwrap()).unwrap();
+    assert_eq!(*q.borrow(), geometry::Point::new(3, 4));
+
+    let err = ctx.eval("Point(1, 2)", "host.js").unwrap_err();
+    assert!(err.to_string().contains("without 'new'"), "{}", err);
+    let err = ctx.eval("Point.prototype.moveBy.call({}, 1, 1)", "host.js").unwrap_err();
+    assert!(err.to_string().contains("Point.moveBy called on an incompatible receiver"), "{}", err);
+
+    // The same module as a native ES module.
+    let ctx = Context::new().unwrap();
+    geometry::register_module(&ctx).unwrap();
+    ctx.eval_module(
+        "import { add, Point } from 'geometry'; globalThis.result = add(1, new Point(2, 0).x);",
+        "main.js",
+    )
+    .unwrap();
+    assert_eq!(ctx.eval_as::<i32>("result", "host.js").unwrap(), 3);
+    assert!(geometry::register_module(&ctx).is_err());
+}
+
+#[test]
+fn test_typescript_declarations() {
+    use crate::typescript::TsFunction;
+
+    let ctx = Context::new().unwrap();
+    ctx.add_callback("add", |a: i32, b: Option<i32>| a + b.unwrap_or(0)).unwrap();
+    ctx.add_callback("sum", |values: Rest<i32>| values.0.iter().sum::<i32>()).unwrap();
+    ctx.add_callback("removed", || 1).unwrap();
+    ctx.remove_callback("removed").unwrap();
+    ctx.set_global("config", JsValue::Object(
+        vec![
+            ("name".to_string(), JsValue::from("app")),
+            ("ports".to_string(), JsValue::Array(vec![JsValue::Int(80)])),
+        ]
+        .into_iter()
+        .collect(),
+    ))
+    .unwrap();
+    ctx.add_callback("greet", |name: String| format!("Hello {}", name)).unwrap();
+    ctx.declare(
+        "greet",
+        TsFunction::new().param("name", "string").returns("string").doc("Greet someone."),
+    );
+
+    assert_eq!(
+        ctx.typescript_declarations(),
+        "declare function add(arg0: any, arg1?: any): any;\n\
+         declare function sum(...rest: any[]): any;\n\
+         declare var config: { name: string; ports: number[]; };\n\
+         /** Greet someone. */\n\
+         declare function greet(name: string): string;\n"
+    );
+
+    // Types follow the current value of the global.
+    ctx.eval("config = [1, 2]", "types.js").unwrap();
+    assert!(ctx.typescript_declarations().contains("declare var config: number[];\n"));
+
+    let ctx = ctx.reset().unwrap();
+    assert_eq!(ctx.typescript_declarations(), "");
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_typescript_declarations_of_host_modules() {
+    let ctx = Context::new().unwrap();
+    geometry::register_module(&ctx).unwrap();
+
+    assert_eq!(
+        ctx.typescript_declarations(),
+        r#"declare module "geometry" {
+    /** Add two numbers. */
+    export function add(a: number, b?: number): number;
+    export function checkedDiv(a: number, b: number): number;
+    /** A point in 2D space. */
+    export class Point {
+        constructor(x: number, y: number);
+        x: number;
+        static origin(): Point;
+        moveBy(dx: number, dy: number): void;
+        distance(other: Point): number;
+    }
+}
+"#
+    );
+}
+
+#[test]
+fn test_array_buffers_and_typed_arrays() {
+    use std::convert::TryFrom;
+
+    let ctx = Context::new().unwrap();
+
+    let value = ctx.eval("new Uint8Array([1, 2, 255]).buffer", "buffer.js").unwrap();
+    assert!(matches!(&value, JsValue::ArrayBuffer(bytes) if bytes == &[1, 2, 255]));
+
+    let value = ctx.eval("new Float32Array([0.5, -2])", "buffer.js").unwrap();
+    assert!(matches!(&value, JsValue::TypedArray(TypedArray::Float32(items)) if items == &[0.5, -2.0]));
+
+    // A view only copies its own part of the buffer.
+    let value = ctx.eval("new Int16Array(new Int16Array([1, 2, 3, 4]).buffer, 2, 2)", "buffer.js").unwrap();
+    assert!(matches!(&value, JsValue::TypedArray(TypedArray::Int16(items)) if items == &[2, 3]));
+
+    ctx.set_global("bytes", JsValue::ArrayBuffer(vec![7, 8, 9])).unwrap();
+    assert_eq!(
+        ctx.eval_as::<String>("bytes instanceof ArrayBuffer && new Uint8Array(bytes).join()", "buffer.js").unwrap(),
+        "7,8,9"
+    );
+    ctx.set_global("floats", TypedArray::Float64(vec![1.5, 2.5])).unwrap();
+    assert_eq!(
+        ctx.eval_as::<String>("floats.constructor.name + ':' + floats.join()", "buffer.js").unwrap(),
+        "Float64Array:1.5,2.5"
+    );
+    ctx.set_global("slice", &[1u8, 2][..]).unwrap();
+    assert_eq!(ctx.eval_as::<String>("slice.constructor.name", "buffer.js").unwrap(), "Uint8Array");
+
+    ctx.add_callback("checksum", |bytes: Vec<u8>| bytes.iter().map(|b| *b as i32).sum::<i32>())
+        .unwrap();
+    assert_eq!(ctx.eval_as::<i32>("checksum(new Uint8Array([1, 2, 3]))", "buffer.js").unwrap(), 6);
+    assert_eq!(ctx.eval_as::<i32>("checksum(new Uint8Array([4, 5]).buffer)", "buffer.js").unwrap(), 9);
+    assert_eq!(ctx.eval_as::<i32>("checksum([1, 1])", "buffer.js").unwrap(), 2);
+    assert!(ctx.eval("checksum([256])", "buffer.js").is_err());
+
+    // Owned bytes are returned as an Array unless wrapped in Bytes.
+    ctx.add_callback("encode", |text: String| Bytes(text.into_bytes())).unwrap();
+    ctx.add_callback("encodeList", |text: String| text.into_bytes()).unwrap();
+    assert_eq!(ctx.eval_as::<String>("encode('hi') instanceof Uint8Array && encode('hi').join()", "buffer.js").unwrap(), "104,105");
+    assert!(ctx.eval_as::<bool>("Array.isArray(encodeList('hi'))", "buffer.js").unwrap());
+
+    let values = Vec::<i32>::try_from(ctx.eval("new Int32Array([-1, 5])", "buffer.js").unwrap()).unwrap();
+    assert_eq!(values, vec![-1, 5]);
+
+    let err = ctx.eval("const b = new ArrayBuffer(4); b.transfer(); checksum(b)", "buffer.js").unwrap_err();
+    assert!(err.to_string().contains("detached"), "{}", err);
+}
+
+#[test]
+fn test_external_array_buffers_and_borrowed_bytes() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Frames(Vec<u8>, Rc<Cell<bool>>);
+
+    impl AsMut<[u8]> for Frames {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    impl Drop for Frames {
+        fn drop(&mut self) {
+            self.1.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let ctx = Context::new().unwrap();
+    let buffer = ctx.external_array_buffer(Frames(vec![1, 2, 3, 4], dropped.clone())).unwrap();
+    ctx.set_global("frames", buffer).unwrap();
+
+    ctx.add_callback("sum", |call: &CallContext| -> Result<i32, String> {
+        let bytes = unsafe { call.arg_bytes(0) }.map_err(|e| e.to_string())?;
+        Ok(bytes.iter().map(|b| *b as i32).sum())
+    })
+    .unwrap();
+    ctx.add_callback("fill", |call: &CallContext| -> Result<JsValue, String> {
+        let bytes = unsafe { call.arg_bytes_mut(0) }.map_err(|e| e.to_string())?;
+        bytes.iter_mut().for_each(|b| *b = 9);
+        Ok(JsValue::Undefined)
+    })
+    .unwrap();
+
+    assert_eq!(ctx.eval_as::<i32>("sum(frames)", "frames.js").unwrap(), 10);
+    assert_eq!(ctx.eval_as::<i32>("sum(new Uint8Array(frames, 1, 2))", "frames.js").unwrap(), 5);
+    assert_eq!(ctx.eval_as::<i32>("new Uint8Array(frames)[3] = 7; sum(frames)", "frames.js").unwrap(), 13);
+    assert_eq!(
+        ctx.eval_as::<String>("fill(new Uint8Array(frames, 2)); new Uint8Array(frames).join()", "frames.js").unwrap(),
+        "1,2,9,9"
+    );
+    assert!(ctx.eval("sum('not a buffer')", "frames.js").is_err());
+
+    assert!(!dropped.get());
+    ctx.eval("frames = undefined", "frames.js").unwrap();
+    drop(ctx);
+    assert!(dropped.get());
+}
+
+#[test]
+fn test_symbols() {
+    let ctx = Context::new().unwrap();
+
+    let symbol = ctx.eval_as::<JsSymbol>("Symbol('local')", "symbol.js").unwrap();
+    assert_eq!(symbol.kind(), SymbolKind::Unique);
+    assert_eq!(symbol.description(), Some("local"));
+    let symbol = ctx.eval_as::<JsSymbol>("Symbol()", "symbol.js").unwrap();
+    assert_eq!(symbol.description(), None);
+
+    let registered = ctx.eval_as::<JsSymbol>("Symbol.for('app.id')", "symbol.js").unwrap();
+    assert_eq!(registered.kind(), SymbolKind::Registered);
+    assert_eq!(registered.key(), Some("app.id"));
+    assert_eq!(registered, JsSymbol::registered("app.id"));
+
+    let iterator = ctx.eval_as::<JsSymbol>("Symbol.iterator", "symbol.js").unwrap();
+    assert_eq!(iterator.kind(), SymbolKind::WellKnown);
+    assert_eq!(iterator.well_known_name(), Some("iterator"));
+    assert_eq!(iterator, JsSymbol::well_known("iterator").unwrap());
+    let fake = ctx.eval_as::<JsSymbol>("Symbol('Symbol.iterator')", "symbol.js").unwrap();
+    assert_eq!(fake.kind(), SymbolKind::Unique);
+    assert!(JsSymbol::well_known("nope").is_none());
+
+    // Symbols created in Rust are the same symbols in Javascript.
+    ctx.set_global("registered", JsSymbol::registered("app.id")).unwrap();
+    ctx.set_global("iterator", JsSymbol::well_known("iterator").unwrap()).unwrap();
+    assert!(ctx.eval_as::<bool>("registered === Symbol.for('app.id')", "symbol.js").unwrap());
+    assert!(ctx.eval_as::<bool>("iterator === Symbol.iterator", "symbol.js").unwrap());
+
+    // A unique symbol keeps its identity.
+    let unique = ctx.create_symbol("unique").unwrap();
+    ctx.set_global("a", unique.clone()).unwrap();
+    ctx.set_global("b", unique).unwrap();
+    assert!(ctx.eval_as::<bool>("a === b && a.description === 'unique'", "symbol.js").unwrap());
+    let a = ctx.eval_as::<JsSymbol>("a", "symbol.js").unwrap();
+    let other = ctx.eval_as::<JsSymbol>("Symbol('unique')", "symbol.js").unwrap();
+    assert_eq!(a, ctx.eval_as::<JsSymbol>("b", "symbol.js").unwrap());
+    assert_ne!(a, other);
+
+    // Symbol-keyed properties.
+    let object = ctx
+        .eval("({ name: 'x', [Symbol.for('app.id')]: 7, [Symbol.toStringTag]: 'Thing' })", "symbol.js")
+        .unwrap();
+    let properties = object.get_properties().unwrap();
+    assert_eq!(properties.len(), 1);
+    let symbols = object.get_symbol_properties().unwrap();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0].0, JsSymbol::registered("app.id"));
+    assert!(matches!(symbols[0].1, JsValue::Int(7)));
+    assert_eq!(symbols[1].0, JsSymbol::well_known("toStringTag").unwrap());
+
+    object.set_symbol_property(&a, JsValue::from("secret")).unwrap();
+    ctx.set_global("object", object).unwrap();
+    assert_eq!(ctx.eval_as::<String>("object[a]", "symbol.js").unwrap(), "secret");
+    assert_eq!(ctx.eval_as::<String>("String(object)", "symbol.js").unwrap(), "[object Thing]");
+
+    // Conversion does not depend on the globals scripts can replace.
+    let ctx = Context::new().unwrap();
+    let symbol = ctx
+        .eval_as::<JsSymbol>(
+            "const s = Symbol.for('x'); \
+             Object.defineProperty(Symbol.prototype, 'description', { get() { throw 1; } }); \
+             Symbol.keyFor = () => { throw 2; }; globalThis.Symbol = undefined; s",
+            "symbol.js",
+        )
+        .unwrap();
+    assert_eq!(symbol.kind(), SymbolKind::Registered);
+    assert_eq!(symbol.description(), Some("x"));
+    let symbol = ctx.eval_as::<JsSymbol>("[...Object.getOwnPropertySymbols(Array.prototype)][0]", "symbol.js").unwrap();
+    assert_eq!(symbol, JsSymbol::well_known("iterator").unwrap());
+    ctx.set_global("iterator", symbol).unwrap();
+    assert!(ctx.eval_as::<bool>("typeof [][iterator] === 'function'", "symbol.js").unwrap());
+    ctx.set_global("empty", ctx.create_symbol("").unwrap()).unwrap();
+    assert!(matches!(ctx.eval("empty", "symbol.js").unwrap(), JsValue::Symbol(ref s) if s.description() == Some("")));
+    assert!(matches!(ctx.eval("1 + 1", "symbol.js").unwrap(), JsValue::Int(2)));
+}
+
+#[test]
+fn test_maps_and_sets() {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    let ctx = Context::new().unwrap();
+
+    let map = ctx.eval_as::<BTreeMap<i32, String>>("new Map([[2, 'b'], [1, 'a']])", "map.js").unwrap();
+    assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(1, "a".to_string()), (2, "b".to_string())]);
+    match ctx.eval("new Map([[true, 1], ['x', 2]])", "map.js").unwrap() {
+        JsValue::Map(entries) => {
+            assert_eq!(entries.len(), 2);
+            assert!(matches!(entries[0], (JsValue::Bool(true), JsValue::Int(1))));
+        }
+        other => panic!("expected a Map, got {:?}", other),
+    }
+    // String-keyed maps can still be read from plain objects.
+    let object = ctx.eval_as::<HashMap<String, i32>>("({ a: 1, b: 2 })", "map.js").unwrap();
+    assert_eq!(object["b"], 2);
+
+    let set = ctx.eval_as::<HashSet<i32>>("new Set([1, 2, 2, 3])", "set.js").unwrap();
+    assert_eq!(set, [1, 2, 3].iter().copied().collect());
+    let set = ctx.eval_as::<BTreeSet<String>>("['b', 'a']", "set.js").unwrap();
+    assert_eq!(set.into_iter().collect::<Vec<_>>(), vec!["a", "b"]);
+
+    let mut map = HashMap::new();
+    map.insert(7, vec![1, 2]);
+    ctx.set_global("map", map).unwrap();
+    assert!(ctx.eval_as::<bool>("map instanceof Map && map.get(7)[1] === 2", "map.js").unwrap());
+    let set: BTreeSet<_> = ["x", "y"].iter().map(|s| s.to_string()).collect();
+    ctx.set_global("set", set).unwrap();
+    assert_eq!(ctx.eval_as::<String>("[...set].join()", "set.js").unwrap(), "x,y");
+
+    // Subclasses are converted, WeakMaps are not.
+    let entries = ctx.eval("class Tags extends Set {}; new Tags(['a'])", "set.js").unwrap();
+    assert!(matches!(entries, JsValue::Set(ref values) if values.len() == 1));
+    assert!(matches!(ctx.eval("new WeakMap()", "map.js").unwrap(), JsValue::Raw(_)));
+
+    // Conversion does not use functions that scripts can replace.
+    ctx.eval(
+        "globalThis.tags = new Tags(['a', 'b']); globalThis.counts = new Map([['a', 1]]); \
+         Object.getPrototypeOf(tags.values()).next = () => ({ done: true }); \
+         Object.getPrototypeOf(counts.entries()).next = () => ({ done: true }); \
+         Set.prototype.values = Set.prototype.add = Map.prototype.set = () => { throw new Error('patched'); }; \
+         Array.from = () => [1, 2, 3]; globalThis.Set = globalThis.Map = undefined;",
+        "patch.js",
+    )
+    .unwrap();
+    assert!(matches!(ctx.eval("tags", "set.js").unwrap(), JsValue::Set(ref values) if values.len() == 2));
+    let counts = ctx.eval_as::<HashMap<String, i32>>("counts", "map.js").unwrap();
+    assert_eq!(counts["a"], 1);
+    ctx.set_global("copy", JsValue::Set(vec![JsValue::from("c")])).unwrap();
+    ctx.set_global("pairs", JsValue::Map(vec![(JsValue::Int(1), JsValue::from("one"))])).unwrap();
+    assert!(ctx.eval_as::<bool>("copy.has('c') && copy.size === 1 && pairs.get(1) === 'one'", "set.js").unwrap());
+    // Objects that only inherit from Set.prototype are not Sets.
+    let fake = ctx.eval("Object.create(Object.getPrototypeOf(tags))", "set.js").unwrap();
+    assert!(matches!(fake, JsValue::Raw(_)));
+}
+
+#[test]
+fn test_conversions_after_reset() {
+    let ctx = Context::new().unwrap();
+    let ctx = ctx.reset().unwrap();
+
+    let object = ctx.eval("({a: 1})", "reset.js").unwrap();
+    assert!(matches!(object.get_object().unwrap()["a"], JsValue::Int(1)));
+    let map = ctx.eval("new Map([[1, 2]])", "reset.js").unwrap();
+    assert!(matches!(map, JsValue::Map(ref entries) if matches!(entries[..], [(JsValue::Int(1), JsValue::Int(2))])));
+    let set = ctx.eval("new Set([1])", "reset.js").unwrap();
+    assert!(matches!(set, JsValue::Set(ref values) if matches!(values[..], [JsValue::Int(1)])));
+    let symbol = ctx.eval_as::<JsSymbol>("Symbol.for('reset')", "reset.js").unwrap();
+    assert_eq!(symbol, JsSymbol::registered("reset"));
+
+    ctx.set_global("object", object).unwrap();
+    ctx.set_global("map", map).unwrap();
+    ctx.set_global("set", set).unwrap();
+    assert!(ctx
+        .eval_as::<bool>("object.a === 1 && map instanceof Map && map.get(1) === 2 && set.has(1)", "reset.js")
+        .unwrap());
+}
+
+#[test]
+fn test_promise_rejection_with_cyclic_reason() {
+    use crate::exception::HostPromiseRejectionTracker;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Tracker(Rc<RefCell<Vec<JsValue>>>);
+
+    impl HostPromiseRejectionTracker for Tracker {
+        fn track_promise_rejection(&mut self, _promise: JsValue, reason: JsValue, _is_handled: bool) {
+            self.0.borrow_mut().push(reason);
+        }
+    }
+
+    let mut ctx = Context::new().unwrap();
+    let reasons = Rc::new(RefCell::new(Vec::new()));
+    ctx.set_promise_rejection_tracker(Tracker(reasons.clone()));
+    ctx.eval(
+        "const e = new Error('cyclic'); e.self = e; Promise.reject(e);
+         const a = [e]; a.push(a); Promise.reject(a); 0",
+        "reject.js",
+    )
+    .unwrap();
+
+    let mut reasons = reasons.borrow_mut();
+    assert_eq!(reasons.len(), 2);
+    ctx.set_global("array", reasons.pop().unwrap()).unwrap();
+    ctx.set_global("error", reasons.pop().unwrap()).unwrap();
+    assert!(ctx
+        .eval_as::<bool>("error === e && error.self === e && array === a && array[1] === a", "reject.js")
+        .unwrap());
+}
+
+#[test]
+fn test_value_limits() {
+    let ctx = Context::new().unwrap();
+
+    // Cycles are reported instead of overflowing the stack.
+    match ctx.eval("let a = [1]; a.push([a]); a", "cycle.js") {
+        Err(ExecutionError::Conversion(ValueError::Cycle { path })) => assert_eq!(path, "$[1][0]"),
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+    match ctx.eval("let m = new Map(); m.set('self', new Set([m])); m", "cycle.js") {
+        Err(ExecutionError::Conversion(ValueError::Cycle { path })) => assert_eq!(path, "$<value 0>[0]"),
+        other => panic!("expected a cycle, got {:?}", other),
+    }
+    // Shared values are not cycles.
+    let shared = ctx.eval_as::<Vec<Vec<i32>>>("let s = [1]; [s, s]", "shared.js").unwrap();
+    assert_eq!(shared, vec![vec![1], vec![1]]);
+    // Neither are plain objects, which convert lazily.
+    let object = ctx.eval("let o = {}; o.self = o; o", "cycle.js").unwrap();
+    assert!(object.get_properties().unwrap().contains_key("self"));
+
+    let limits = ValueLimits {
+        max_depth: 3,
+        max_elements: 10,
+    };
+    let ctx = Context::builder().value_limits(limits).build().unwrap();
+    ctx.eval("[[[1]]]", "depth.js").unwrap();
+    match ctx.eval("[[[[1]]]]", "depth.js") {
+        Err(ExecutionError::Conversion(ValueError::LimitExceeded { limit, path })) => {
+            assert_eq!(limit, ValueLimit::Depth(3));
+            assert_eq!(path, "$[0][0][0]");
+        }
+        other => panic!("expected the depth limit, got {:?}", other),
+    }
+    ctx.eval("[1, 2, 3, 4, 5, [6, 7, 8, 9]]", "count.js").unwrap();
+    let err = ctx.eval("[1, 2, 3, 4, 5, [6, 7, 8, 9, 10]]", "count.js").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value conversion failed - maximum of 10 elements exceeded at $[5][4]"
+    );
+    // Properties of plain objects count too, and errors are not hidden.
+    let object = ctx.eval("({ items: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11] })", "count.js").unwrap();
+    assert!(matches!(object.get_object(), Err(ValueError::LimitExceeded { .. })));
+    assert!(object.get_properties().is_none());
+    assert!(matches!(JsValue::Int(1).get_object(), Err(ValueError::UnexpectedType)));
+
+    // Sparse arrays are rejected by their length before reading them.
+    let ctx = Context::new().unwrap();
+    assert_eq!(ValueLimits::default().max_elements, 1_000_000);
+    let err = ctx.eval("let big = []; big.length = 3e8; big", "big.js").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value conversion failed - maximum of 1000000 elements exceeded at $[1000000]"
+    );
+}
+
+#[test]
+fn test_objects_keep_property_order() {
+    use std::convert::TryFrom;
+
+    let ctx = Context::new().unwrap();
+
+    let value = ctx.eval("({ zeta: 1, alpha: 2, 10: 'ten', mid: [3] })", "order.js").unwrap();
+    let object = value.get_object().unwrap();
+    // Integer keys come first, the others in creation order.
+    assert_eq!(object.keys().collect::<Vec<_>>(), vec!["10", "zeta", "alpha", "mid"]);
+    assert!(matches!(object["alpha"], JsValue::Int(2)));
+
+    let mut object = JsObject::try_from(value).unwrap();
+    assert!(matches!(object.insert("zeta".into(), JsValue::Int(0)), Some(JsValue::Int(1))));
+    object.insert("omega".into(), JsValue::Bool(true));
+    object.remove("10");
+    ctx.set_global("config", object).unwrap();
+    assert_eq!(
+        ctx.eval_as::<String>("Object.keys(config).join()", "order.js").unwrap(),
+        "zeta,alpha,mid,omega"
+    );
+    assert_eq!(ctx.eval_as::<i32>("config.zeta", "order.js").unwrap(), 0);
+
+    // HashMaps are still available.
+    let map = ctx.eval_as::<HashMap<String, JsValue>>("config", "order.js").unwrap();
+    assert_eq!(map.len(), 4);
+    let object = JsObject::from(map);
+    assert!(object.contains_key("omega"));
+
+    // Lookups do not scan the properties.
+    let mut object: JsObject = (0..200_000).map(|i| (format!("key{}", i), JsValue::Int(i))).collect();
+    object.extend(vec![("key5".to_string(), JsValue::Null), ("last".to_string(), JsValue::Bool(true))]);
+    assert_eq!(object.len(), 200_001);
+    assert!(matches!(object["key5"], JsValue::Null));
+    assert!(matches!(object.remove("key0"), Some(JsValue::Int(0))));
+    assert!(matches!(object["key199999"], JsValue::Int(199_999)));
+    assert_eq!(object.keys().next().map(String::as_str), Some("key1"));
+    assert_eq!(object.keys().last().map(String::as_str), Some("last"));
+}
+
+#[test]
+fn test_lazy_object_handles() {
+    use std::convert::TryFrom;
+
+    let ctx = Context::new().unwrap();
+
+    let result = ctx
+        .eval_ref(
+            "({ name: 'report', rows: Array.from({ length: 100000 }, (_, i) => ({ id: i })), nested: { deep: [1, 2] } })",
+            "handles.js",
+        )
+        .unwrap();
+    assert_eq!(result.keys().unwrap(), vec!["name", "rows", "nested"]);
+    assert!(matches!(result.get("name").unwrap(), JsValue::String(ref s) if s == "report"));
+    assert!(matches!(result.get("missing").unwrap(), JsValue::Undefined));
+    assert!(result.has("rows").unwrap());
+    assert!(result.has("toString").unwrap());
+
+    let rows = result.array("rows").unwrap();
+    assert_eq!(rows.len().unwrap(), 100000);
+    let row = rows.object(12345).unwrap();
+    assert!(matches!(row.get("id").unwrap(), JsValue::Int(12345)));
+    assert!(rows.index(100000).is_err());
+    assert!(result.array("name").is_err());
+    assert!(result.as_array().is_none());
+    assert!(rows.as_object().has("length").unwrap());
+
+    // Changes are visible to scripts.
+    row.set("seen", true).unwrap();
+    rows.set(0, "first").unwrap();
+    assert!(result.delete("name").unwrap());
+    ctx.eval_ref("globalThis.report = {}; report", "handles.js").unwrap().set("x", 1).unwrap();
+    assert_eq!(ctx.eval_as::<i32>("report.x", "handles.js").unwrap(), 1);
+
+    let deep = result.object("nested").unwrap().array("deep").unwrap();
+    assert_eq!(Vec::<i32>::try_from(deep.to_value().unwrap()).unwrap(), vec![1, 2]);
+    let nested = result.object("nested").unwrap().to_value().unwrap();
+    assert!(matches!(nested, JsValue::Object(ref nested) if matches!(nested["deep"], JsValue::Array(_))));
+    let report = ctx.eval_ref("({ items: [{ id: 1 }], meta: { tags: { a: 'x' } } })", "handles.js").unwrap();
+    match report.to_value().unwrap() {
+        JsValue::Object(object) => {
+            assert!(matches!(object["items"], JsValue::Array(ref items) if matches!(items[0], JsValue::Object(_))));
+            assert!(matches!(object["meta"], JsValue::Object(ref meta) if matches!(meta["tags"], JsValue::Object(_))));
+        }
+        other => panic!("expected an object, got {:?}", other),
+    }
+    let cycle = ctx.eval_ref("let cyclic = { list: [] }; cyclic.list.push(cyclic); cyclic", "handles.js").unwrap();
+    assert!(matches!(cycle.to_value(), Err(ExecutionError::Conversion(ValueError::Cycle { ref path })) if path == "$.list[0]"));
+
+    ctx.eval("function makeList(n) { return Array.from({ length: n }, (_, i) => i * 2); }", "handles.js")
+        .unwrap();
+    let list = ctx.call_ref("makeList", vec![5]).unwrap().as_array().unwrap();
+    assert_eq!(list.len().unwrap(), 5);
+    assert!(matches!(list.index(4).unwrap(), JsValue::Int(8)));
+
+    assert!(matches!(
+        ctx.eval_ref("42", "handles.js"),
+        Err(ExecutionError::Conversion(ValueError::UnexpectedType))
+    ));
+    let err = ctx.eval_ref("({ get bad() { throw new Error('nope') } })", "handles.js").unwrap().get("bad");
+    assert!(err.unwrap_err().to_string().contains("nope"));
+}
+
+#[test]
+fn test_persistent_handles() {
+    let ctx = Context::new().unwrap();
+
+    ctx.eval("var listeners = []; function register(f) { listeners.push(f); return f; }", "persist.js")
+        .unwrap();
+    let listener = ctx.eval("register(x => x * 2)", "persist.js").unwrap();
+    let listener: Persistent = ctx.persist(listener).unwrap();
+    let state = ctx.eval_ref("({ count: 1 })", "persist.js").unwrap().persist::<HashMap<String, i32>>();
+    assert!(listener.is_alive() && listener.belongs_to(&ctx));
+
+    // The handles survive across calls.
+    ctx.eval("listeners.length = 0", "persist.js").unwrap();
+    let result = ctx.call_js_function(listener.to_value(&ctx).unwrap(), vec![21]).unwrap();
+    assert!(matches!(result, JsValue::Int(42)));
+    state.get_ref(&ctx).unwrap().set("count", 2).unwrap();
+    assert_eq!(state.get(&ctx).unwrap()["count"], 2);
+    let copy = state.clone().cast::<JsValue>();
+    assert!(matches!(copy.get_ref(&ctx).unwrap().get("count").unwrap(), JsValue::Int(2)));
+
+    // They can not be used with another context.
+    let other = Context::new().unwrap();
+    assert!(!state.belongs_to(&other));
+    assert!(matches!(state.get(&other), Err(ExecutionError::InvalidHandle)));
+
+    // Nor after a reset, and dropping them later is safe.
+    let ctx = ctx.reset().unwrap();
+    assert!(!listener.is_alive() && !copy.is_alive());
+    assert!(matches!(listener.to_value(&ctx), Err(ExecutionError::InvalidHandle)));
+    let err = state.get_ref(&ctx).unwrap_err();
+    assert!(err.to_string().starts_with("Invalid handle"));
+    drop(listener);
+
+    let kept = ctx.persist::<Vec<i32>>(vec![1, 2]).unwrap();
+    assert_eq!(kept.get(&ctx).unwrap(), vec![1, 2]);
+
+    // Many live handles, some of them dropped along the way.
+    let handles = (0..50_000)
+        .filter_map(|i| {
+            let handle = ctx.persist::<i32>(i).unwrap();
+            if i % 3 == 0 { None } else { Some(handle) }
+        })
+        .collect::<Vec<_>>();
+    assert!(handles.iter().all(|handle| handle.is_alive()));
+    assert_eq!(handles[1].get(&ctx).unwrap(), 2);
+    drop(ctx);
+    assert!(!kept.is_alive());
+    drop((kept, state, copy));
+}
+
+#[test]
+fn test_calls_with_this() {
+    let ctx = Context::new().unwrap();
+    ctx.eval(
+        r#"
+        var app = {
+            name: 'app',
+            services: {
+                prefix: '> ',
+                format(text) { return this.prefix + text; },
+                fail() { throw new TypeError('broken ' + this.prefix.trim()); },
+            },
+        };
+        function whoami() { return this === globalThis ? 'global' : this.name; }
+        "#,
+        "this.js",
+    )
+    .unwrap();
+
+    let services = ctx.eval("app.services", "this.js").unwrap();
+    let formatted = ctx.call_method(services.clone(), "format", vec!["hi"]).unwrap();
+    assert!(matches!(formatted, JsValue::String(ref s) if s == "> hi"));
+
+    let whoami = ctx.eval("whoami", "this.js").unwrap();
+    let app = ctx.eval("app", "this.js").unwrap();
+    let name = ctx.call_with_this(whoami.clone(), app, Vec::<JsValue>::new()).unwrap();
+    assert!(matches!(name, JsValue::String(ref s) if s == "app"));
+
+    let formatted = ctx.call_path("app.services.format", vec!["path"]).unwrap();
+    assert!(matches!(formatted, JsValue::String(ref s) if s == "> path"));
+    let name = ctx.call_path("whoami", Vec::<JsValue>::new()).unwrap();
+    assert!(matches!(name, JsValue::String(ref s) if s == "global"));
+
+    // Exceptions thrown by methods are reported.
+    let err = ctx.call_path("app.services.fail", Vec::<JsValue>::new()).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s == "TypeError: broken >"), "{}", err);
+    let err = ctx.call_path("app.missing.format", Vec::<JsValue>::new()).unwrap_err();
+    assert_eq!(err.to_string(), "Internal error: Property 'app.missing' not found");
+    assert!(ctx.call_method(services, "prefix", Vec::<JsValue>::new()).is_err());
+
+    let handle = ctx.eval_ref("app.services", "this.js").unwrap();
+    let formatted = handle.call_method("format", vec!["ref"]).unwrap();
+    assert!(matches!(formatted, JsValue::String(ref s) if s == "> ref"));
+}
+
+#[test]
+fn test_construct() {
+    let ctx = Context::new().unwrap();
+    ctx.eval(
+        r#"
+        class Point {
+            constructor(x, y) { if (y === undefined) throw new RangeError('y is required'); this.x = x; this.y = y; }
+            sum() { return this.x + this.y; }
+        }
+        globalThis.Point = Point;
+        "#,
+        "construct.js",
+    )
+    .unwrap();
+
+    let point = ctx.construct_global("Point", vec![1, 2]).unwrap();
+    assert!(matches!(ctx.call_method(point, "sum", Vec::<JsValue>::new()).unwrap(), JsValue::Int(3)));
+
+    let constructor = ctx.eval("Point", "construct.js").unwrap();
+    let point = ctx.construct(constructor, vec![3, 4]).unwrap();
+    assert!(matches!(point.get_properties().unwrap()["y"], JsValue::Int(4)));
+
+    let set = ctx.construct_global("Set", vec![JsValue::Array(vec![JsValue::Int(1), JsValue::Int(1)])]).unwrap();
+    assert!(matches!(set, JsValue::Set(ref values) if values.len() == 1));
+    #[cfg(feature = "chrono")]
+    assert!(matches!(ctx.construct_global("Date", vec![0]).unwrap(), JsValue::Date(_)));
+
+    let err = ctx.construct_global("Point", vec![1]).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s == "RangeError: y is required"), "{}", err);
+    let err = ctx.construct_global("parseInt", vec!["1"]).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+    assert!(ctx.construct_global("Missing", Vec::<JsValue>::new()).is_err());
+}
+
+#[test]
+fn test_define_property() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let ctx = Context::new().unwrap();
+    let global = ctx.global_ref().unwrap();
+    global.define_property("VERSION", Property::value("1.2.3").read_only()).unwrap();
+    let version = ctx.eval("VERSION = 'x'; delete globalThis.VERSION; VERSION", "define.js").unwrap();
+    assert_eq!(version.as_str(), Some("1.2.3"));
+    let err = ctx.eval("'use strict'; VERSION = 'x'", "define.js").unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+
+    let level = Rc::new(Cell::new(1));
+    let config = ctx.eval_ref("({ name: 'app' })", "define.js").unwrap();
+    let get_level = level.clone();
+    let set_level = level.clone();
+    config
+        .define_property(
+            "level",
+            Property::accessor()
+                .getter(move || get_level.get())
+                .setter(move |value: i32| {
+                    set_level.set(value);
+                    JsValue::Undefined
+                })
+                .enumerable(false),
+        )
+        .unwrap();
+    config.define_property("hidden", Property::value(true).enumerable(false)).unwrap();
+    global.set("config", config.as_value_ref().to_value().unwrap()).unwrap();
+    assert!(matches!(ctx.eval("config.level = 5; config.level", "define.js").unwrap(), JsValue::Int(5)));
+    assert_eq!(level.get(), 5);
+    assert_eq!(config.keys().unwrap(), vec!["name".to_string()]);
+    let getter_name = ctx.eval("Object.getOwnPropertyDescriptor(config, 'level').get.name", "define.js").unwrap();
+    assert_eq!(getter_name.as_str(), Some("get level"));
+
+    assert!(config.is_extensible().unwrap());
+    config.prevent_extensions().unwrap();
+    assert!(!config.is_extensible().unwrap());
+    let added = ctx.eval("config.added = 1; config.added", "define.js").unwrap();
+    assert!(matches!(added, JsValue::Undefined));
+
+    let sealed = ctx.eval_ref("globalThis.sealed = { a: 1 }", "define.js").unwrap();
+    sealed.seal().unwrap();
+    assert!(!sealed.delete("a").unwrap());
+    let frozen = ctx.eval_ref("globalThis.frozen = { a: 1 }", "define.js").unwrap();
+    frozen.freeze().unwrap();
+    let result = ctx.eval("sealed.a = 2; frozen.a = 2; [sealed.a, frozen.a, Object.isFrozen(frozen)]", "define.js");
+    assert!(matches!(result.unwrap(), JsValue::Array(ref values) if matches!(values[..], [JsValue::Int(2), JsValue::Int(1), JsValue::Bool(true)])));
+
+    let err = frozen.define_property("b", Property::value(1)).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+}
+
+#[test]
+fn test_iterators() {
+    use std::convert::TryFrom;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context as TaskContext, Poll, Wake, Waker};
+
+    let mut ctx = Context::new().unwrap();
+    let mut gate = JsPromise::new(&mut ctx);
+    ctx.set_global("gate", gate.js_value()).unwrap();
+    let mut later = JsPromise::new(&mut ctx);
+    ctx.set_global("later", later.js_value()).unwrap();
+    ctx.eval(
+        r#"
+        globalThis.closed = false;
+        globalThis.numbers = function* (n) {
+            try { for (let i = 0; i < n; i++) yield i; } finally { closed = true; }
+        };
+        globalThis.failing = function* () { yield 1; throw new Error('broken'); };
+        globalThis.pages = async function* () { yield 'a'; await null; yield 'b'; };
+        globalThis.gated = async function* () { yield await gate; };
+        "#,
+        "iter.js",
+    )
+    .unwrap();
+
+    let values = ctx.iterate(JsValue::Array(vec![JsValue::Int(1), JsValue::Int(2)])).unwrap();
+    assert!(matches!(values.collect::<Result<Vec<_>, _>>().unwrap()[..], [JsValue::Int(1), JsValue::Int(2)]));
+    let entries = ctx.eval_ref("new Map([['k', 1]])", "iter.js").unwrap().iter().unwrap().count();
+    assert_eq!(entries, 1);
+    assert!(ctx.iterate(1).is_err());
+    assert!(ctx.iterate(JsObject::new()).is_err());
+
+    let numbers = ctx.eval_ref("numbers(10)", "iter.js").unwrap();
+    let sum: i32 = numbers.iter().unwrap().map(|v| i32::try_from(v.unwrap()).unwrap()).take(3).sum();
+    assert_eq!(sum, 3);
+    assert!(matches!(ctx.eval("closed", "iter.js").unwrap(), JsValue::Bool(true)));
+
+    let mut failing = ctx.eval_ref("failing()", "iter.js").unwrap().iter().unwrap();
+    assert!(matches!(failing.next(), Some(Ok(JsValue::Int(1)))));
+    let err = failing.next().unwrap().unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s == "Error: broken"), "{}", err);
+    assert!(failing.next().is_none());
+
+    let pages = ctx.eval_ref("pages()", "iter.js").unwrap().iter_async().unwrap();
+    let pages = pages.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(pages.iter().map(|p| p.as_str().unwrap()).collect::<Vec<_>>(), vec!["a", "b"]);
+    let values = ctx.eval_ref("[Promise.resolve(1), 2]", "iter.js").unwrap().iter_async().unwrap();
+    assert!(matches!(values.collect::<Result<Vec<_>, _>>().unwrap()[..], [JsValue::Int(1), JsValue::Int(2)]));
+    let mut rejected = ctx.eval_ref("[Promise.reject(new Error('no'))]", "iter.js").unwrap().iter_async().unwrap();
+    assert!(matches!(rejected.next(), Some(Err(ExecutionError::Exception(JsValue::String(ref s)))) if s == "Error: no"));
+
+    let mut gated = ctx.eval_ref("gated()", "iter.js").unwrap().iter_async().unwrap();
+    assert!(gated.poll_next().is_pending());
+    gate.resolve(JsValue::Int(7));
+    assert!(matches!(gated.poll_next(), Poll::Ready(Some(Ok(JsValue::Int(7))))));
+
+    struct CountingWaker(AtomicUsize);
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+    let mut cx = TaskContext::from_waker(&waker);
+    let mut future = gated.next_async();
+    let future = std::pin::Pin::new(&mut future);
+    assert!(matches!(std::future::Future::poll(future, &mut cx), Poll::Ready(None)));
+
+    // A waiting task is woken once the host settles a promise, not before.
+    let mut gated = ctx.eval_ref("(async function* () { yield await later; })()", "iter.js").unwrap().iter_async().unwrap();
+    let mut future = gated.next_async();
+    assert!(std::pin::Pin::new(&mut future).poll(&mut cx).is_pending());
+    assert!(std::pin::Pin::new(&mut future).poll(&mut cx).is_pending());
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+    later.resolve(JsValue::Int(8));
+    assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+    assert!(matches!(std::pin::Pin::new(&mut future).poll(&mut cx), Poll::Ready(Some(Ok(JsValue::Int(8))))));
+
+    // Items that can not be converted close the iterator.
+    let limits = ValueLimits { max_depth: 1, ..ValueLimits::default() };
+    let ctx = Context::builder().value_limits(limits).build().unwrap();
+    ctx.eval(
+        "globalThis.closed = false; \
+         globalThis.nested = function* () { try { yield [[1]]; yield 2; } finally { closed = true; } };",
+        "iter.js",
+    )
+    .unwrap();
+    let mut nested = ctx.eval_ref("nested()", "iter.js").unwrap().iter().unwrap();
+    assert!(matches!(nested.next(), Some(Err(ExecutionError::Conversion(ValueError::LimitExceeded { .. })))));
+    assert!(nested.next().is_none());
+    assert!(matches!(ctx.eval("closed", "iter.js").unwrap(), JsValue::Bool(true)));
+}
+
+#[test]
+fn test_host_iterators() {
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context as TaskContext, Poll, Waker};
+
+    struct Counter {
+        next: i32,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl Iterator for Counter {
+        type Item = i32;
+
+        fn next(&mut self) -> Option<i32> {
+            self.next += 1;
+            Some(self.next)
+        }
+    }
+
+    impl Drop for Counter {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Channel {
+        items: Rc<RefCell<VecDeque<i32>>>,
+        closed: Rc<Cell<bool>>,
+        waker: Rc<RefCell<Option<Waker>>>,
+    }
+
+    impl Channel {
+        fn send(&self, item: i32) {
+            self.items.borrow_mut().push_back(item);
+            if let Some(waker) = self.waker.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+
+        fn close(&self) {
+            self.closed.set(true);
+            if let Some(waker) = self.waker.borrow_mut().take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Stream for Channel {
+        type Item = i32;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<i32>> {
+            if let Some(item) = self.items.borrow_mut().pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if self.closed.get() {
+                return Poll::Ready(None);
+            }
+            *self.waker.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    let ctx = Context::new().unwrap();
+    ctx.add_callback("range", |n: i32| JsValue::iterator(0..n)).unwrap();
+    let dropped = Rc::new(Cell::new(false));
+    let counter_dropped = dropped.clone();
+    ctx.add_callback("counter", move || {
+        JsValue::iterator(Counter {
+            next: 0,
+            dropped: counter_dropped.clone(),
+        })
+    })
+    .unwrap();
+
+    let spread = ctx.eval("[...range(3)].join()", "host_iter.js").unwrap();
+    assert_eq!(spread.as_str(), Some("0,1,2"));
+    let helpers = ctx.eval("range(4).filter(i => i % 2).map(i => i * 10).toArray().join()", "host_iter.js").unwrap();
+    assert_eq!(helpers.as_str(), Some("10,30"));
+    let first = ctx.eval("let first; for (const i of counter()) { first = i; break; } first", "host_iter.js").unwrap();
+    assert!(matches!(first, JsValue::Int(1)));
+    assert!(dropped.get());
+
+    let iterator = JsValue::iterator(vec!["a", "b"]);
+    ctx.set_global("letters", iterator.clone()).unwrap();
+    assert!(ctx.set_global("again", iterator).is_err());
+    let letters = ctx.iterate(ctx.eval("letters", "host_iter.js").unwrap()).unwrap();
+    assert_eq!(letters.map(|l| l.unwrap().as_str().unwrap().to_string()).collect::<Vec<_>>(), vec!["a", "b"]);
+
+    let channel = Channel::default();
+    ctx.set_global("channel", JsValue::stream(channel.clone())).unwrap();
+    ctx.eval(
+        r#"
+        globalThis.received = [];
+        (async () => {
+            for await (const value of channel) received.push(value);
+            received.push('end');
+        })();
+        "#,
+        "host_iter.js",
+    )
+    .unwrap();
+    let run_jobs = || while ctx.execute_pending_job().unwrap() {};
+    let received = || ctx.eval("received.join()", "host_iter.js").unwrap().as_str().unwrap().to_string();
+    run_jobs();
+    assert_eq!(received(), "");
+    channel.send(1);
+    channel.send(2);
+    run_jobs();
+    assert_eq!(received(), "1,2");
+    channel.close();
+    run_jobs();
+    assert_eq!(received(), "1,2,end");
+
+    let channel = Channel::default();
+    channel.send(5);
+    channel.close();
+    let values = ctx.iterate_async(JsValue::stream(channel)).unwrap();
+    assert!(matches!(values.collect::<Result<Vec<_>, _>>().unwrap()[..], [JsValue::Int(5)]));
+
+    let pending = Channel::default();
+    let mut values = ctx.iterate_async(JsValue::stream(pending.clone())).unwrap();
+    assert!(values.poll_next().is_pending());
+    pending.send(6);
+    assert!(matches!(values.poll_next(), Poll::Ready(Some(Ok(JsValue::Int(6))))));
+    drop(values);
+
+    // Pending requests are released with the context.
+    let pending = Channel::default();
+    ctx.set_global("pending", JsValue::stream(pending.clone())).unwrap();
+    ctx.eval("(async () => { for await (const value of pending); })()", "host_iter.js").unwrap();
+    drop(ctx);
+    pending.send(7);
+}
+
+#[test]
+fn test_value_operations() {
+    let ctx = Context::new().unwrap();
+    let value = |code: &str| ctx.value_ref(ctx.eval(code, "ops.js").unwrap()).unwrap();
+
+    assert_eq!(value("' 12.5 '").to_number().unwrap(), 12.5);
+    assert!(value("'abc'").to_number().unwrap().is_nan());
+    assert_eq!(value("({ valueOf() { return 7; } })").to_number().unwrap(), 7.0);
+    let err = value("Symbol('s')").to_number().unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+    let err = value("({ valueOf() { throw new Error('nope'); } })").to_number().unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s == "Error: nope"), "{}", err);
+    assert_eq!(value("2 ** 32 + 5.9").to_int32().unwrap(), 5);
+    assert_eq!(value("'-3'").to_int32().unwrap(), -3);
+
+    assert!(!value("''").to_boolean().unwrap());
+    assert!(!value("NaN").to_boolean().unwrap());
+    assert!(value("[]").to_boolean().unwrap());
+    assert_eq!(value("[1, [2, 3]]").to_string().unwrap(), "1,2,3");
+    assert!(value("Symbol('s')").to_string().is_err());
+    assert!(ctx.eval("1", "ops.js").is_ok());
+
+    let types = ["undefined", "null", "true", "1", "1.5", "'s'", "Symbol()", "({})", "[]", "(() => 1)", "(class {})"]
+        .iter()
+        .map(|code| value(code).type_of())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec!["undefined", "object", "boolean", "number", "number", "string", "symbol", "object", "object", "function", "function"]
+    );
+
+    let date = value("new Date(0)");
+    assert!(date.instance_of(&value("Date")).unwrap());
+    assert!(date.instance_of(&value("Object")).unwrap());
+    assert!(!date.instance_of(&value("Array")).unwrap());
+    assert!(!value("1").instance_of(&value("Number")).unwrap());
+    let err = date.instance_of(&value("({})")).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+
+    let object = ctx.eval_ref("globalThis.shared = { nested: {} }", "ops.js").unwrap();
+    assert!(object.as_value_ref().strict_equals(&value("shared")).unwrap());
+    assert!(object.property_ref("nested").unwrap().strict_equals(&value("shared.nested")).unwrap());
+    assert!(object.property_ref("missing").unwrap().strict_equals(&value("undefined")).unwrap());
+    assert!(!value("({})").strict_equals(&value("({})")).unwrap());
+    assert!(!value("NaN").strict_equals(&value("NaN")).unwrap());
+    assert!(value("NaN").same_value(&value("NaN")).unwrap());
+    assert!(value("0").strict_equals(&value("-0")).unwrap());
+    assert!(!value("0").same_value(&value("-0")).unwrap());
+    assert!(value("'1'").loose_equals(&ctx.value_ref(1).unwrap()).unwrap());
+    assert!(value("null").loose_equals(&value("undefined")).unwrap());
+    assert!(!value("null").loose_equals(&value("0")).unwrap());
+    let err = value("({ valueOf() { throw new Error('cmp'); } })").loose_equals(&value("1")).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s == "Error: cmp"), "{}", err);
+
+    let other = Context::new().unwrap();
+    let foreign = other.value_ref(1).unwrap();
+    assert!(matches!(value("1").strict_equals(&foreign), Err(ExecutionError::InvalidHandle)));
+    assert!(value("({})").as_object().is_some());
+    assert!(value("1").as_object().is_none());
+}
+
+#[test]
+fn test_json() {
+    let ctx = Context::new().unwrap();
+
+    let document = ctx.parse_json(r#"{"name": "app", "tags": ["a", "b"], "nested": {"n": 1.5}}"#).unwrap();
+    assert_eq!(document.type_of(), "object");
+    let object = document.as_object().unwrap();
+    assert_eq!(object.keys().unwrap(), vec!["name", "tags", "nested"]);
+    ctx.set_global("doc", document.to_value().unwrap()).unwrap();
+    let tags = ctx.eval("doc.tags.join('+') + doc.nested.n", "json.js").unwrap();
+    assert_eq!(tags.as_str(), Some("a+b1.5"));
+    assert_eq!(ctx.parse_json("42").unwrap().to_number().unwrap(), 42.0);
+
+    let err = ctx.parse_json("{\"unterminated\": ").unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("SyntaxError")), "{}", err);
+    assert!(ctx.parse_json("{} trailing").is_err());
+    assert!(ctx.eval("1", "json.js").is_ok());
+
+    assert_eq!(document.to_json(0).unwrap(), r#"{"name":"app","tags":["a","b"],"nested":{"n":1.5}}"#);
+    assert_eq!(object.to_json(2).unwrap().lines().nth(1), Some(r#"  "name": "app","#));
+    assert_eq!(ctx.stringify(JsValue::Array(vec![JsValue::Int(1), JsValue::Null]), 0).unwrap(), "[1,null]");
+    assert_eq!(ctx.stringify("quote\"", 0).unwrap(), r#""quote\"""#);
+    assert_eq!(ctx.eval_json("({ a: [1, 2], b: undefined, toJSON() { return { a: this.a }; } })", "json.js").unwrap(), r#"{"a":[1,2]}"#);
+    assert_eq!(ctx.eval_json("new Date(0)", "json.js").unwrap(), r#""1970-01-01T00:00:00.000Z""#);
+
+    assert!(ctx.stringify(JsValue::Undefined, 0).is_err());
+    assert!(ctx.eval_json("(function () {})", "json.js").is_err());
+    let err = ctx.eval_json("const cyclic = {}; cyclic.self = cyclic; cyclic", "json.js").unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+}
+
+#[test]
+fn test_copy_between_contexts() {
+    let source = Context::new().unwrap();
+    let target = Context::new().unwrap();
+
+    let graph = source
+        .eval(
+            r#"
+            const shared = { n: 1 };
+            const graph = {
+                a: shared,
+                b: shared,
+                bytes: new Uint8Array([1, 2, 3]),
+                when: new Date(86400000),
+                map: new Map([['k', shared]]),
+                set: new Set([1, 2]),
+            };
+            graph.self = graph;
+            graph
+            "#,
+            "copy.js",
+        )
+        .unwrap();
+
+    let err = target.set_global("graph", graph.clone()).unwrap_err();
+    assert!(matches!(err, ExecutionError::Conversion(ValueError::ForeignValue)), "{}", err);
+    assert!(target.call_function("String", vec![graph.clone()]).is_err());
+
+    let copy = source.copy_to(graph, &target).unwrap();
+    target.set_global("graph", copy).unwrap();
+    let checks = target
+        .eval(
+            r#"[
+                graph.a === graph.b,
+                graph.self === graph,
+                graph.map.get('k') === graph.a,
+                graph.bytes instanceof Uint8Array && graph.bytes.join() === '1,2,3',
+                graph.when instanceof Date && graph.when.getTime() === 86400000,
+                graph.set instanceof Set && graph.set.has(2),
+            ].every(Boolean)"#,
+            "copy.js",
+        )
+        .unwrap();
+    assert!(matches!(checks, JsValue::Bool(true)));
+    target.eval("graph.a.n = 2", "copy.js").unwrap();
+    assert!(matches!(source.eval("shared.n", "copy.js").unwrap(), JsValue::Int(1)));
+
+    let list = source.value_ref(JsValue::Array(vec![JsValue::Int(1), "two".into()])).unwrap().copy_to(&target).unwrap();
+    assert_eq!(list.to_json(0).unwrap(), r#"[1,"two"]"#);
+
+    let err = source.copy_to(source.eval("(() => 1)", "copy.js").unwrap(), &target).unwrap_err();
+    assert!(matches!(err, ExecutionError::Exception(JsValue::String(ref s)) if s.starts_with("TypeError")), "{}", err);
+    assert!(source.eval("1", "copy.js").is_ok());
+
+    let registered = source.eval("Symbol.for('app')", "copy.js").unwrap();
+    target.set_global("registered", registered).unwrap();
+    assert!(matches!(target.eval("registered === Symbol.for('app')", "copy.js").unwrap(), JsValue::Bool(true)));
+    let unique = source.eval("Symbol('local')", "copy.js").unwrap();
+    assert!(matches!(
+        target.set_global("unique", unique),
+        Err(ExecutionError::Conversion(ValueError::ForeignValue))
+    ));
+
+    let stale = source.eval("({})", "copy.js").unwrap();
+    let source = source.reset().unwrap();
+    assert!(matches!(
+        source.set_global("stale", stale),
+        Err(ExecutionError::Conversion(ValueError::ForeignValue))
+    ));
+}
@@ -0,0 +1,543 @@
+//! TypeScript declarations of the host API.
+//!
+//! Every [Context] records a declaration for the globals added with
+//! `add_callback`, `set_global` and [HostModule](crate::host::HostModule),
+//! and for native modules. Callback and value types are inferred where
+//! possible, [Context::declare] replaces them with an exact signature.
+//! [Context::typescript_declarations] renders everything as a `.d.ts` file.
+//!
+//! ```rust
+//! use deft_quick_js::typescript::TsFunction;
+//! use deft_quick_js::Context;
+//!
+//! let context = Context::new().unwrap();
+//! context.add_callback("add", |a: i32, b: i32| a + b).unwrap();
+//! context.declare("add", TsFunction::new().param("a", "number").param("b", "number").returns("number"));
+//! context.set_global("version", "1.0").unwrap();
+//!
+//! let dts = context.typescript_declarations();
+//! assert!(dts.contains("declare function add(a: number, b: number): number;"));
+//! assert!(dts.contains("declare var version: string;"));
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::host::{HostClass, Instance};
+use crate::{JsSymbol, JsValue, TypedArray};
+
+/// A Rust type with a known TypeScript type.
+pub trait TsType {
+    /// The TypeScript type of the Javascript value.
+    fn ts_type() -> String;
+}
+
+macro_rules! impl_ts_type {
+    ( $( $ty:ty => $ts:expr, )* ) => {
+        $(
+            impl TsType for $ty {
+                fn ts_type() -> String {
+                    $ts.to_string()
+                }
+            }
+        )*
+    };
+}
+
+impl_ts_type! {
+    bool => "boolean",
+    i8 => "number",
+    i16 => "number",
+    i32 => "number",
+    u8 => "number",
+    u16 => "number",
+    u32 => "number",
+    f64 => "number",
+    String => "string",
+    JsSymbol => "symbol",
+    JsValue => "any",
+    () => "void",
+}
+
+#[cfg(feature = "bigint")]
+impl_ts_type! {
+    i64 => "bigint",
+    u64 => "bigint",
+    i128 => "bigint",
+    u128 => "bigint",
+    crate::BigInt => "bigint",
+}
+
+#[cfg(feature = "chrono")]
+impl_ts_type! {
+    chrono::DateTime<chrono::Utc> => "Date",
+}
+
+impl TsType for TypedArray {
+    fn ts_type() -> String {
+        "ArrayBufferView".into()
+    }
+}
+
+impl<T: TsType> TsType for Option<T> {
+    fn ts_type() -> String {
+        format!("{} | null", T::ts_type())
+    }
+}
+
+impl<T: TsType> TsType for Vec<T> {
+    fn ts_type() -> String {
+        array_of(&T::ts_type())
+    }
+}
+
+impl<K: TsType, V: TsType> TsType for HashMap<K, V> {
+    fn ts_type() -> String {
+        format!("Map<{}, {}>", K::ts_type(), V::ts_type())
+    }
+}
+
+impl<K: TsType, V: TsType> TsType for BTreeMap<K, V> {
+    fn ts_type() -> String {
+        format!("Map<{}, {}>", K::ts_type(), V::ts_type())
+    }
+}
+
+impl<T: TsType> TsType for HashSet<T> {
+    fn ts_type() -> String {
+        format!("Set<{}>", T::ts_type())
+    }
+}
+
+impl<T: TsType> TsType for BTreeSet<T> {
+    fn ts_type() -> String {
+        format!("Set<{}>", T::ts_type())
+    }
+}
+
+impl<T: HostClass> TsType for Instance<T> {
+    fn ts_type() -> String {
+        T::NAME.to_string()
+    }
+}
+
+fn array_of(ty: &str) -> String {
+    if ty.contains(' ') {
+        format!("({})[]", ty)
+    } else {
+        format!("{}[]", ty)
+    }
+}
+
+/// The type shared by all values, `None` if there are none or they differ.
+fn common_type<'a>(values: impl Iterator<Item = &'a JsValue>) -> Option<String> {
+    let mut types = values.map(value_type).collect::<Vec<_>>();
+    types.dedup();
+    match types.len() {
+        1 => types.pop(),
+        _ => None,
+    }
+}
+
+/// Infer the TypeScript type of a value.
+pub fn value_type(value: &JsValue) -> String {
+    match value {
+        JsValue::Undefined => "undefined".into(),
+        JsValue::Null => "null".into(),
+        JsValue::Bool(_) => "boolean".into(),
+        JsValue::Int(_) | JsValue::Float(_) => "number".into(),
+        JsValue::String(_) => "string".into(),
+        JsValue::ArrayBuffer(_) => "ArrayBuffer".into(),
+        JsValue::TypedArray(array) => array.name().into(),
+        JsValue::Symbol(_) => "symbol".into(),
+        JsValue::Array(items) => match common_type(items.iter()) {
+            Some(ty) => array_of(&ty),
+            None => "any[]".into(),
+        },
+        JsValue::Map(entries) => format!(
+            "Map<{}, {}>",
+            common_type(entries.iter().map(|(k, _)| k)).unwrap_or_else(|| "any".into()),
+            common_type(entries.iter().map(|(_, v)| v)).unwrap_or_else(|| "any".into()),
+        ),
+        JsValue::Set(values) => format!("Set<{}>", common_type(values.iter()).unwrap_or_else(|| "any".into())),
+        JsValue::Iterator(iterator) if iterator.is_stream() => "AsyncIterableIterator<any>".into(),
+        JsValue::Iterator(_) => "IterableIterator<any>".into(),
+        JsValue::Object(map) => {
+            let props = map
+                .iter()
+                .map(|(key, value)| format!("{}: {};", property_name(key), value_type(value)))
+                .collect::<Vec<_>>();
+            if props.is_empty() {
+                "{}".into()
+            } else {
+                format!("{{ {} }}", props.join(" "))
+            }
+        }
+        #[cfg(feature = "chrono")]
+        JsValue::Date(_) => "Date".into(),
+        #[cfg(feature = "bigint")]
+        JsValue::BigInt(_) => "bigint".into(),
+        _ => "any".into(),
+    }
+}
+
+/// Quote a property name that is not an identifier.
+fn property_name(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c == '_' || c == '$' || c.is_alphabetic() || (i > 0 && c.is_numeric()));
+    if is_identifier && !name.is_empty() {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+/// A parameter of a [TsFunction].
+#[derive(Clone, Debug)]
+pub struct TsParam {
+    pub name: String,
+    pub ty: String,
+    pub optional: bool,
+    pub rest: bool,
+}
+
+/// The signature of a function.
+#[derive(Clone, Debug)]
+pub struct TsFunction {
+    pub params: Vec<TsParam>,
+    pub returns: String,
+    pub doc: String,
+}
+
+impl Default for TsFunction {
+    fn default() -> Self {
+        Self {
+            params: Vec::new(),
+            returns: "any".into(),
+            doc: String::new(),
+        }
+    }
+}
+
+impl TsFunction {
+    /// A function without parameters that returns `any`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(mut self, name: &str, ty: &str, optional: bool, rest: bool) -> Self {
+        self.params.push(TsParam {
+            name: name.into(),
+            ty: ty.into(),
+            optional,
+            rest,
+        });
+        self
+    }
+
+    /// Add a required parameter.
+    pub fn param(self, name: &str, ty: &str) -> Self {
+        self.push(name, ty, false, false)
+    }
+
+    /// Add an optional parameter.
+    pub fn optional(self, name: &str, ty: &str) -> Self {
+        self.push(name, ty, true, false)
+    }
+
+    /// Add a rest parameter, `ty` is the type of each argument.
+    pub fn rest(self, name: &str, ty: &str) -> Self {
+        self.push(name, ty, false, true)
+    }
+
+    /// Set the return type.
+    pub fn returns(mut self, ty: &str) -> Self {
+        self.returns = ty.into();
+        self
+    }
+
+    /// Set the documentation.
+    pub fn doc(mut self, doc: &str) -> Self {
+        self.doc = doc.into();
+        self
+    }
+
+    /// The signature of a callback with the given parameters, see
+    /// [CallbackArg](crate::CallbackArg).
+    pub(crate) fn from_params(params: &[(bool, bool)]) -> Self {
+        params
+            .iter()
+            .enumerate()
+            .fold(Self::new(), |function, (index, (required, variadic))| {
+                if *variadic {
+                    function.rest("rest", "any")
+                } else {
+                    let name = format!("arg{}", index);
+                    function.push(&name, "any", !required, false)
+                }
+            })
+    }
+
+    fn render_params(&self) -> String {
+        self.params
+            .iter()
+            .map(|p| {
+                if p.rest {
+                    format!("...{}: {}", p.name, array_of(&p.ty))
+                } else if p.optional {
+                    format!("{}?: {}", p.name, p.ty)
+                } else {
+                    format!("{}: {}", p.name, p.ty)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A property of a [TsClass], defined by a getter and an optional setter.
+#[derive(Clone, Debug)]
+pub struct TsProperty {
+    pub name: String,
+    pub ty: String,
+    pub readonly: bool,
+    pub doc: String,
+}
+
+/// A method of a [TsClass].
+#[derive(Clone, Debug)]
+pub struct TsMethod {
+    pub name: String,
+    pub function: TsFunction,
+    pub is_static: bool,
+}
+
+/// The declaration of a class.
+#[derive(Clone, Debug, Default)]
+pub struct TsClass {
+    /// `None` if the class can not be constructed from Javascript.
+    pub constructor: Option<TsFunction>,
+    pub properties: Vec<TsProperty>,
+    pub methods: Vec<TsMethod>,
+    pub doc: String,
+}
+
+impl TsClass {
+    /// A class without members.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the documentation.
+    pub fn doc(mut self, doc: &str) -> Self {
+        self.doc = doc.into();
+        self
+    }
+
+    /// Add a method, or a static method if `is_static` is set.
+    pub fn method(&mut self, name: &str, function: TsFunction, is_static: bool) {
+        self.methods.push(TsMethod {
+            name: name.into(),
+            function,
+            is_static,
+        });
+    }
+
+    /// Add a read-only property.
+    pub fn getter(&mut self, name: &str, ty: &str, doc: &str) {
+        self.properties.push(TsProperty {
+            name: name.into(),
+            ty: ty.into(),
+            readonly: true,
+            doc: doc.into(),
+        });
+    }
+
+    /// Make a property writable, adding it if there is no getter.
+    pub fn setter(&mut self, name: &str, ty: &str, doc: &str) {
+        match self.properties.iter_mut().find(|p| p.name == name) {
+            Some(property) => property.readonly = false,
+            None => self.properties.push(TsProperty {
+                name: name.into(),
+                ty: ty.into(),
+                readonly: false,
+                doc: doc.into(),
+            }),
+        }
+    }
+}
+
+/// The declaration of a global or a module export.
+#[derive(Clone, Debug)]
+pub enum TsDeclaration {
+    Function(TsFunction),
+    Class(TsClass),
+    /// A variable of the given type.
+    Variable(String),
+}
+
+impl From<TsFunction> for TsDeclaration {
+    fn from(function: TsFunction) -> Self {
+        TsDeclaration::Function(function)
+    }
+}
+
+impl From<TsClass> for TsDeclaration {
+    fn from(class: TsClass) -> Self {
+        TsDeclaration::Class(class)
+    }
+}
+
+/// The declaration of a global, `None` for a variable whose type is
+/// inferred from its value when the declarations are rendered.
+type GlobalDeclaration = Option<TsDeclaration>;
+
+/// The declarations of a context, in the order they were added.
+#[derive(Default)]
+pub(crate) struct Declarations {
+    globals: Vec<(String, GlobalDeclaration)>,
+    modules: Vec<(String, Vec<(String, TsDeclaration)>)>,
+}
+
+impl Declarations {
+    /// Add or replace the declaration of a global.
+    pub(crate) fn set_global(&mut self, name: &str, declaration: TsDeclaration) {
+        self.put_global(name, Some(declaration));
+    }
+
+    /// Declare a variable whose type is inferred when rendering.
+    pub(crate) fn infer_global(&mut self, name: &str) {
+        self.put_global(name, None);
+    }
+
+    fn put_global(&mut self, name: &str, declaration: GlobalDeclaration) {
+        match self.globals.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = declaration,
+            None => self.globals.push((name.into(), declaration)),
+        }
+    }
+
+    /// The globals whose type is inferred from their value.
+    pub(crate) fn inferred_globals(&self) -> Vec<String> {
+        self.globals
+            .iter()
+            .filter(|(_, declaration)| declaration.is_none())
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub(crate) fn remove_global(&mut self, name: &str) {
+        self.globals.retain(|(n, _)| n != name);
+    }
+
+    pub(crate) fn add_module(&mut self, name: &str, exports: Vec<(String, TsDeclaration)>) {
+        self.modules.push((name.into(), exports));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.globals.clear();
+        self.modules.clear();
+    }
+
+    /// Render as the contents of a `.d.ts` file, with the types of the
+    /// [inferred globals](Declarations::inferred_globals).
+    pub(crate) fn render(&self, inferred: &HashMap<String, String>) -> String {
+        let mut out = String::new();
+        for (name, declaration) in &self.globals {
+            match declaration {
+                Some(declaration) => render_declaration(&mut out, "", "declare ", name, declaration),
+                None => {
+                    let ty = inferred.get(name).map_or("any", String::as_str);
+                    render_declaration(&mut out, "", "declare ", name, &TsDeclaration::Variable(ty.into()));
+                }
+            }
+        }
+        for (module, exports) in &self.modules {
+            writeln!(out, "declare module {:?} {{", module).unwrap();
+            for (name, declaration) in exports {
+                render_declaration(&mut out, "    ", "export ", name, declaration);
+            }
+            writeln!(out, "}}").unwrap();
+        }
+        out
+    }
+}
+
+fn render_doc(out: &mut String, indent: &str, doc: &str) {
+    if doc.is_empty() {
+        return;
+    }
+    let lines = doc.lines().collect::<Vec<_>>();
+    if let [line] = lines.as_slice() {
+        writeln!(out, "{}/** {} */", indent, line).unwrap();
+        return;
+    }
+    writeln!(out, "{}/**", indent).unwrap();
+    for line in lines {
+        writeln!(out, "{} * {}", indent, line).unwrap();
+    }
+    writeln!(out, "{} */", indent).unwrap();
+}
+
+fn render_declaration(out: &mut String, indent: &str, keyword: &str, name: &str, declaration: &TsDeclaration) {
+    match declaration {
+        TsDeclaration::Function(function) => {
+            render_doc(out, indent, &function.doc);
+            writeln!(
+                out,
+                "{}{}function {}({}): {};",
+                indent,
+                keyword,
+                name,
+                function.render_params(),
+                function.returns
+            )
+            .unwrap();
+        }
+        TsDeclaration::Variable(ty) => {
+            writeln!(out, "{}{}var {}: {};", indent, keyword, name, ty).unwrap();
+        }
+        TsDeclaration::Class(class) => {
+            render_doc(out, indent, &class.doc);
+            writeln!(out, "{}{}class {} {{", indent, keyword, name).unwrap();
+            let member_indent = format!("{}    ", indent);
+            match &class.constructor {
+                Some(constructor) => {
+                    render_doc(out, &member_indent, &constructor.doc);
+                    writeln!(out, "{}constructor({});", member_indent, constructor.render_params()).unwrap();
+                }
+                None => writeln!(out, "{}private constructor();", member_indent).unwrap(),
+            }
+            for property in &class.properties {
+                render_doc(out, &member_indent, &property.doc);
+                let readonly = if property.readonly { "readonly " } else { "" };
+                writeln!(
+                    out,
+                    "{}{}{}: {};",
+                    member_indent,
+                    readonly,
+                    property_name(&property.name),
+                    property.ty
+                )
+                .unwrap();
+            }
+            for method in &class.methods {
+                render_doc(out, &member_indent, &method.function.doc);
+                let modifier = if method.is_static { "static " } else { "" };
+                writeln!(
+                    out,
+                    "{}{}{}({}): {};",
+                    member_indent,
+                    modifier,
+                    property_name(&method.name),
+                    method.function.render_params(),
+                    method.function.returns
+                )
+                .unwrap();
+            }
+            writeln!(out, "{}}}", indent).unwrap();
+        }
+    }
+}
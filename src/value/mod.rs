@@ -1,19 +1,31 @@
 #[cfg(feature = "bigint")]
 pub(crate) mod bigint;
+mod object;
+mod typed_array;
+pub(crate) mod symbol;
 
 use std::convert::{TryFrom, TryInto};
-use std::{collections::HashMap, error, fmt};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+use std::{error, fmt};
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::mem::ManuallyDrop;
+use std::os::raw::c_void;
 use std::rc::Rc;
 use libquickjs_sys as q;
 
 #[cfg(feature = "bigint")]
 pub use bigint::BigInt;
-use libquickjs_sys::{JS_Call, JS_FreeValue, JS_NewPromiseCapability, JSContext, JSValue};
-use crate::{Context, ExecutionError};
-use crate::bindings::convert::{deserialize_object, deserialize_value, serialize_value};
-use crate::bindings::{make_cstring, TAG_EXCEPTION};
+pub use object::JsObject;
+pub use typed_array::{Bytes, TypedArray};
+pub use symbol::{JsSymbol, SymbolKind};
+use libquickjs_sys::{JS_Call, JS_FreeValue, JS_NewPromiseCapability, JSContext, JSRuntime, JSValue};
+use crate::{Context, ExecutionError, HostIterator};
+use crate::bindings::convert::{
+    deserialize_object, deserialize_symbol_properties, deserialize_value, serialize_value, set_symbol_property,
+};
+use crate::bindings::{discard_exception, make_cstring, wake_for_jobs, ContextWrapper, TAG_EXCEPTION};
 use crate::bindings::value::JsTag;
 use crate::ValueError::UnexpectedType;
 
@@ -22,6 +34,8 @@ use crate::ValueError::UnexpectedType;
 pub struct RawJSValue {
     /// the js context
     ctx: *mut JSContext,
+    /// the js runtime, which outlives the context on `Context::reset`
+    rt: *mut JSRuntime,
     /// The raw js value
     js_value: *mut JSValue,
 }
@@ -33,13 +47,25 @@ impl RawJSValue {
         unsafe {
             libquickjs_sys::JS_DupValue(ctx, *value);
         }
-        let ptr = Box::into_raw(Box::new(*value));
+        // Takes over the reference that was just acquired.
+        Self::from_owned(ctx, *value)
+    }
+
+    pub(crate) fn from_owned(ctx: *mut JSContext, value: JSValue) -> Self {
+        let rt = unsafe { q::JS_GetRuntime(ctx) };
+        let ptr = Box::into_raw(Box::new(value));
         Self {
             ctx,
+            rt,
             js_value: ptr,
         }
     }
 
+    /// Whether the value belongs to the given context.
+    pub(crate) fn belongs_to(&self, ctx: *mut JSContext) -> bool {
+        self.ctx == ctx
+    }
+
     /// Create JSValue
     pub fn create_js_value(&self) -> JSValue {
         unsafe {
@@ -63,21 +89,137 @@ impl Drop for RawJSValue {
     fn drop(&mut self) {
         unsafe {
             let v = unsafe { Box::from_raw(self.js_value) };
-            libquickjs_sys::JS_FreeValue(self.ctx, *v.as_ref());
-            //TODO free js_value?
-            // Box::from_raw(self.js_value);
+            // Free through the runtime: the value may be released from a
+            // resource finalizer after its context is gone.
+            libquickjs_sys::JS_FreeValueRT(self.rt, *v.as_ref());
+        }
+    }
+}
+
+/// Reports the Javascript values held by a resource to the garbage collector.
+///
+/// A resource that stores JS values (e.g. a registered listener function)
+/// must report every one of them from [Trace::trace], otherwise a reference
+/// cycle between the resource and its JS values can never be collected.
+///
+/// Traced resources are created with [JsValue::new_traced_resource].
+pub trait Trace {
+    /// Mark all JS values owned by `self` with the given tracer.
+    fn trace(&self, tracer: &Tracer);
+}
+
+/// Marks the JS values reported by a [Trace] implementation.
+pub struct Tracer {
+    runtime: *mut JSRuntime,
+    mark_func: q::JS_MarkFunc,
+}
+
+impl Tracer {
+    pub(crate) fn new(runtime: *mut JSRuntime, mark_func: q::JS_MarkFunc) -> Self {
+        Self { runtime, mark_func }
+    }
+
+    /// Mark a value, including the values nested inside arrays and objects.
+    pub fn mark(&self, value: &JsValue) {
+        match value {
+            JsValue::Raw(raw) | JsValue::Exception(raw) => unsafe {
+                q::JS_MarkValue(self.runtime, *raw.js_value, self.mark_func);
+            },
+            JsValue::Array(values) => values.iter().for_each(|v| self.mark(v)),
+            JsValue::Object(map) => map.values().for_each(|v| self.mark(v)),
+            JsValue::Map(entries) => entries.iter().for_each(|(k, v)| {
+                self.mark(k);
+                self.mark(v);
+            }),
+            JsValue::Set(values) => values.iter().for_each(|v| self.mark(v)),
+            _ => {}
+        }
+    }
+}
+
+impl Trace for JsValue {
+    fn trace(&self, tracer: &Tracer) {
+        tracer.mark(self);
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &Tracer) {
+        if let Some(v) = self {
+            v.trace(tracer);
         }
     }
 }
 
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, tracer: &Tracer) {
+        self.iter().for_each(|v| v.trace(tracer));
+    }
+}
+
+type TraceFn = fn(&dyn Any, &Tracer);
+
+fn trace_resource<T: Any + Trace>(resource: &dyn Any, tracer: &Tracer) {
+    if let Some(r) = resource.downcast_ref::<T>() {
+        r.trace(tracer);
+    }
+}
+
+/// The Javascript object of a resource. It is not owned by the resource,
+/// its finalizer clears it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ResourceWrapper {
+    pub(crate) runtime: *mut JSRuntime,
+    pub(crate) object: *mut c_void,
+}
+
 #[derive(Debug, Clone)]
 pub struct ResourceValue {
-    pub resource: Rc<RefCell<dyn Any>>
+    pub resource: Rc<RefCell<dyn Any>>,
+    pub(crate) trace: Option<TraceFn>,
+    /// The Javascript object of this resource while it is alive, so every
+    /// conversion of the resource gives the same object.
+    pub(crate) wrapper: Rc<Cell<Option<ResourceWrapper>>>,
 }
 
 
 impl ResourceValue {
 
+    /// Create a resource that holds no JS values.
+    pub fn new<T: Any>(value: T) -> Self {
+        Self {
+            resource: Rc::new(RefCell::new(value)),
+            trace: None,
+            wrapper: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Create a resource whose JS values are reported to the garbage collector.
+    pub fn new_traced<T: Any + Trace>(value: T) -> Self {
+        Self {
+            resource: Rc::new(RefCell::new(value)),
+            trace: Some(trace_resource::<T>),
+            wrapper: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Report the JS values of this resource, if it is traced.
+    ///
+    /// Only a resource that is owned by its Javascript object alone is
+    /// traced. A resource that is also held from Rust keeps its values
+    /// alive, like resources that are currently borrowed mutably do for
+    /// this collection.
+    pub(crate) fn trace(&self, tracer: &Tracer) {
+        if let Some(trace) = self.trace {
+            if Rc::strong_count(&self.resource) > 1 {
+                return;
+            }
+            if let Ok(resource) = self.resource.try_borrow() {
+                trace(&*resource, tracer);
+            }
+        }
+    }
+
     pub fn with<T: Any,R, F: FnOnce(&mut T) -> R>(&self, callback: F) -> Option<R> {
         let mut b = self.resource.borrow_mut();
         if let Some(e) = b.downcast_mut::<T>() {
@@ -101,8 +243,20 @@ pub enum JsValue {
     Float(f64),
     String(String),
     Array(Vec<JsValue>),
-    Object(HashMap<String, JsValue>),
+    /// The properties of an object, in property order.
+    Object(JsObject),
     Resource(ResourceValue),
+    /// The contents of an ArrayBuffer.
+    ArrayBuffer(Vec<u8>),
+    /// The contents of a typed array such as `Uint8Array`.
+    TypedArray(TypedArray),
+    Symbol(JsSymbol),
+    /// The entries of a `Map`, in insertion order.
+    Map(Vec<(JsValue, JsValue)>),
+    /// The values of a `Set`, in insertion order.
+    Set(Vec<JsValue>),
+    /// A Rust iterator or stream, converted to a Javascript iterator.
+    Iterator(HostIterator),
     Raw(RawJSValue),
     Exception(RawJSValue),
     /// chrono::Datetime<Utc> / JS Date integration.
@@ -118,13 +272,13 @@ pub enum JsValue {
 }
 
 impl JsValue {
-    pub fn create_object(context: *mut JSContext, map: HashMap<String, JsValue>) -> Result<Self, ValueError> {
+    pub fn create_object(context: *mut JSContext, map: impl Into<JsObject>) -> Result<Self, ValueError> {
         let obj = unsafe { q::JS_NewObject(context) };
         if q::JS_IsException(obj) {
             return Err(ValueError::Internal("Could not create object".into()));
         }
 
-        for (key, value) in map {
+        for (key, value) in map.into() {
             let ckey = make_cstring(key)?;
 
             let qvalue = serialize_value(context, value).map_err(|e| {
@@ -158,10 +312,7 @@ impl JsValue {
             }
         }
 
-        Ok(JsValue::Raw(RawJSValue {
-            ctx: context,
-            js_value: Box::into_raw(Box::new(obj)),
-        }))
+        Ok(JsValue::Raw(RawJSValue::from_owned(context, obj)))
     }
 
     pub fn value_type(&self) -> &'static str {
@@ -175,6 +326,12 @@ impl JsValue {
             JsValue::Array(_) => "array",
             JsValue::Object(_) => "object",
             JsValue::Resource(_) => "resource",
+            JsValue::ArrayBuffer(_) => "arraybuffer",
+            JsValue::TypedArray(_) => "typedarray",
+            JsValue::Symbol(_) => "symbol",
+            JsValue::Map(_) => "map",
+            JsValue::Set(_) => "set",
+            JsValue::Iterator(_) => "iterator",
             JsValue::Raw(_) => "raw",
             JsValue::Exception(_) => "exception",
             #[cfg(feature = "chrono")]
@@ -204,9 +361,12 @@ impl JsValue {
     }
 
     pub fn new_resource<T: Any>(value: T) -> Self {
-        Self::Resource(ResourceValue {
-            resource: Rc::new(RefCell::new(value)),
-        })
+        Self::Resource(ResourceValue::new(value))
+    }
+
+    /// Create a resource that may hold JS values, see [Trace].
+    pub fn new_traced_resource<T: Any + Trace>(value: T) -> Self {
+        Self::Resource(ResourceValue::new_traced(value))
     }
 
     pub fn as_resource<T: Any,R, F: FnOnce(&mut T) -> R>(&self, callback: F) -> Option<R> {
@@ -219,15 +379,45 @@ impl JsValue {
         }
     }
 
+    /// The properties of an object, `None` if it is not an object or its
+    /// properties could not be converted. See [JsValue::get_object].
     pub fn get_properties(&self) -> Option<HashMap<String, JsValue>> {
-        if let JsValue::Raw(raw) = self {
-            if let Ok(r) = deserialize_object(raw.ctx, unsafe {&*raw.js_value}) {
-                Some(r)
-            } else {
-                None
+        self.get_object().ok().map(Into::into)
+    }
+
+    /// Get the enumerable string-keyed properties of an object, in
+    /// property order.
+    ///
+    /// Fails with [ValueError::UnexpectedType] if the value is not an object,
+    /// or with the error of converting one of the properties, e.g. a
+    /// [ValueError::LimitExceeded].
+    pub fn get_object(&self) -> Result<JsObject, ValueError> {
+        match self {
+            JsValue::Object(object) => Ok(object.clone()),
+            JsValue::Raw(raw) if q::JS_IsObject(unsafe { *raw.js_value }) => {
+                deserialize_object(raw.ctx, unsafe { &*raw.js_value })
             }
-        } else {
-            None
+            _ => Err(UnexpectedType),
+        }
+    }
+
+    /// Get the enumerable symbol-keyed properties of an object.
+    pub fn get_symbol_properties(&self) -> Option<Vec<(JsSymbol, JsValue)>> {
+        match self {
+            JsValue::Raw(raw) if q::JS_IsObject(unsafe { *raw.js_value }) => {
+                deserialize_symbol_properties(raw.ctx, unsafe { &*raw.js_value }).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Set a symbol-keyed property of an object.
+    pub fn set_symbol_property(&self, symbol: &JsSymbol, value: JsValue) -> Result<(), ExecutionError> {
+        match self {
+            JsValue::Raw(raw) if q::JS_IsObject(unsafe { *raw.js_value }) => {
+                Ok(set_symbol_property(raw.ctx, unsafe { &*raw.js_value }, symbol, value)?)
+            }
+            _ => Err(ExecutionError::Conversion(UnexpectedType)),
         }
     }
 
@@ -250,11 +440,20 @@ impl JsValue {
                     qargs.as_mut_ptr(),
                 )
             };
-            let r = deserialize_value(raw.ctx, &qres_raw);
             unsafe {
                 for q in qargs {
                     JS_FreeValue(raw.ctx, q);
                 }
+            }
+            if q::JS_IsException(qres_raw) {
+                // The context is owned elsewhere, so the wrapper must never be dropped.
+                let wrapper = ManuallyDrop::new(unsafe { ContextWrapper::from_raw(raw.ctx) });
+                return Err(wrapper
+                    .get_exception()
+                    .unwrap_or_else(|| ExecutionError::Exception("Unknown exception".into())));
+            }
+            let r = deserialize_value(raw.ctx, &qres_raw);
+            unsafe {
                 JS_FreeValue(raw.ctx, qres_raw);
             }
             Ok(r?)
@@ -347,9 +546,14 @@ impl JsPromise {
             let mut val = crate::bindings::convert::serialize_value(self.context, value).unwrap();
             let res = JS_Call(self.context, self.func[0], undef, 1, &mut val as *mut JSValue);
             JS_FreeValue(self.context, val);
-            JS_FreeValue(self.context, res);
+            if q::JS_IsException(res) {
+                discard_exception(self.context);
+            } else {
+                JS_FreeValue(self.context, res);
+            }
             JS_FreeValue(self.context, self.func[0]);
             JS_FreeValue(self.context, self.func[1]);
+            wake_for_jobs(self.context);
         }
     }
 
@@ -363,9 +567,14 @@ impl JsPromise {
             let mut val = crate::bindings::convert::serialize_value(self.context, value).unwrap();
             let res = JS_Call(self.context, self.func[1], undef, 1, &mut val as *mut JSValue);
             JS_FreeValue(self.context, val);
-            JS_FreeValue(self.context, res);
+            if q::JS_IsException(res) {
+                discard_exception(self.context);
+            } else {
+                JS_FreeValue(self.context, res);
+            }
             JS_FreeValue(self.context, self.func[0]);
             JS_FreeValue(self.context, self.func[1]);
+            wake_for_jobs(self.context);
         }
     }
 
@@ -499,11 +708,135 @@ where
                 .into_iter()
                 .map(|item| item.try_into().map_err(|_| ValueError::UnexpectedType))
                 .collect(),
+            JsValue::TypedArray(array) => array
+                .into_values()
+                .into_iter()
+                .map(|item| item.try_into().map_err(|_| ValueError::UnexpectedType))
+                .collect(),
             _ => Err(ValueError::UnexpectedType),
         }
     }
 }
 
+/// Takes the bytes of an ArrayBuffer or typed array, or an array of bytes.
+impl TryFrom<JsValue> for Vec<u8> {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        match value {
+            JsValue::ArrayBuffer(bytes) => Ok(bytes),
+            JsValue::TypedArray(TypedArray::Uint8(bytes)) => Ok(bytes),
+            JsValue::TypedArray(TypedArray::Uint8Clamped(bytes)) => Ok(bytes),
+            JsValue::TypedArray(array) => Ok(array.as_bytes().to_vec()),
+            JsValue::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    JsValue::Int(x) => u8::try_from(x).map_err(|_| ValueError::UnexpectedType),
+                    _ => Err(ValueError::UnexpectedType),
+                })
+                .collect(),
+            _ => Err(ValueError::UnexpectedType),
+        }
+    }
+}
+
+/// Converts to a `Uint8Array`, unlike `Vec<u8>`, which converts to an
+/// `Array`. See [Bytes] for owned bytes.
+impl<'a> From<&'a [u8]> for JsValue {
+    fn from(bytes: &'a [u8]) -> Self {
+        JsValue::TypedArray(TypedArray::Uint8(bytes.to_vec()))
+    }
+}
+
+macro_rules! value_impl_map {
+    ( $( $map:ident <K: $($bound:path),*> ),* ) => {
+        $(
+            /// Converts to a `Map`.
+            impl<K, V> From<$map<K, V>> for JsValue
+            where
+                K: Into<JsValue>,
+                V: Into<JsValue>,
+            {
+                fn from(map: $map<K, V>) -> Self {
+                    JsValue::Map(map.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+                }
+            }
+
+            /// Takes the entries of a `Map`, or the properties of an object.
+            impl<K, V> TryFrom<JsValue> for $map<K, V>
+            where
+                K: TryFrom<JsValue> $( + $bound )*,
+                V: TryFrom<JsValue>,
+            {
+                type Error = ValueError;
+
+                fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+                    let entries = match value {
+                        JsValue::Map(entries) => entries,
+                        JsValue::Object(map) => map.into_iter().map(|(k, v)| (JsValue::String(k), v)).collect(),
+                        value @ JsValue::Raw(_) => {
+                            value.get_object()?.into_iter().map(|(k, v)| (JsValue::String(k), v)).collect()
+                        }
+                        _ => return Err(ValueError::UnexpectedType),
+                    };
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| {
+                            let k = K::try_from(k).map_err(|_| ValueError::UnexpectedType)?;
+                            let v = V::try_from(v).map_err(|_| ValueError::UnexpectedType)?;
+                            Ok((k, v))
+                        })
+                        .collect()
+                }
+            }
+        )*
+    };
+}
+
+value_impl_map! {
+    HashMap<K: Eq, Hash>,
+    BTreeMap<K: Ord>
+}
+
+macro_rules! value_impl_set {
+    ( $( $set:ident <T: $($bound:path),*> ),* ) => {
+        $(
+            /// Converts to a `Set`.
+            impl<T> From<$set<T>> for JsValue
+            where
+                T: Into<JsValue>,
+            {
+                fn from(set: $set<T>) -> Self {
+                    JsValue::Set(set.into_iter().map(Into::into).collect())
+                }
+            }
+
+            /// Takes the values of a `Set` or an array.
+            impl<T> TryFrom<JsValue> for $set<T>
+            where
+                T: TryFrom<JsValue> $( + $bound )*,
+            {
+                type Error = ValueError;
+
+                fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+                    match value {
+                        JsValue::Set(values) | JsValue::Array(values) => values
+                            .into_iter()
+                            .map(|v| T::try_from(v).map_err(|_| ValueError::UnexpectedType))
+                            .collect(),
+                        _ => Err(ValueError::UnexpectedType),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+value_impl_set! {
+    HashSet<T: Eq, Hash>,
+    BTreeSet<T: Ord>
+}
+
 impl<'a> From<&'a str> for JsValue {
     fn from(val: &'a str) -> Self {
         JsValue::String(val.into())
@@ -534,6 +867,13 @@ pub enum ValueError {
     Internal(String),
     /// Received an unexpected type that could not be converted.
     UnexpectedType,
+    /// A limit of [ValueLimits] was exceeded at `path`, e.g. `$[0].items`.
+    LimitExceeded { limit: ValueLimit, path: String },
+    /// The value contains itself at `path`.
+    Cycle { path: String },
+    /// A `JsValue::Raw` value was used in a context it does not belong to,
+    /// see [Context::copy_to].
+    ForeignValue,
     #[doc(hidden)]
     __NonExhaustive,
 }
@@ -558,6 +898,9 @@ impl fmt::Display for ValueError {
             StringWithZeroBytes(_) => write!(f, "String contains \\0 bytes",),
             Internal(e) => write!(f, "Value conversion failed - internal error: {}", e),
             UnexpectedType => write!(f, "Could not convert - received unexpected type"),
+            LimitExceeded { limit, path } => write!(f, "Value conversion failed - {} exceeded at {}", limit, path),
+            Cycle { path } => write!(f, "Value conversion failed - cyclic reference at {}", path),
+            ForeignValue => write!(f, "Value conversion failed - the value belongs to another context"),
             __NonExhaustive => unreachable!(),
         }
     }
@@ -565,6 +908,47 @@ impl fmt::Display for ValueError {
 
 impl error::Error for ValueError {}
 
+/// Limits on converting a nested Javascript value to a [JsValue].
+///
+/// Arrays, `Map`s and `Set`s are converted with their contents, so a huge
+/// or deeply nested value from an untrusted script could exhaust memory or
+/// the stack. Set with [ContextBuilder::value_limits](crate::ContextBuilder::value_limits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueLimits {
+    /// How deeply containers may be nested.
+    pub max_depth: usize,
+    /// How many values a container may hold, counting all nested values.
+    pub max_elements: usize,
+}
+
+impl Default for ValueLimits {
+    /// A depth of 256 and at most 1,000,000 elements.
+    fn default() -> Self {
+        ValueLimits {
+            max_depth: 256,
+            max_elements: 1_000_000,
+        }
+    }
+}
+
+/// The limit reported by [ValueError::LimitExceeded].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueLimit {
+    /// [ValueLimits::max_depth].
+    Depth(usize),
+    /// [ValueLimits::max_elements].
+    Elements(usize),
+}
+
+impl fmt::Display for ValueLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueLimit::Depth(depth) => write!(f, "maximum depth of {}", depth),
+            ValueLimit::Elements(count) => write!(f, "maximum of {} elements", count),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+
+use super::{JsValue, ValueError};
+
+/// The properties of a Javascript object, in property order.
+///
+/// Objects keep the order of their properties when they are converted
+/// from and to Javascript. Converting from a `HashMap` takes its order.
+#[derive(Clone, Default)]
+pub struct JsObject {
+    properties: Vec<(String, JsValue)>,
+    /// The position of each property in `properties`.
+    index: HashMap<String, usize>,
+}
+
+impl JsObject {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        JsObject {
+            properties: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.index.get(key).copied()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsValue> {
+        self.position(key).map(|index| &self.properties[index].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut JsValue> {
+        self.position(key).map(move |index| &mut self.properties[index].1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Set a property, returning the previous value.
+    ///
+    /// A new property is added last, an existing one keeps its position.
+    pub fn insert(&mut self, key: String, value: JsValue) -> Option<JsValue> {
+        match self.position(&key) {
+            Some(index) => Some(std::mem::replace(&mut self.properties[index].1, value)),
+            None => {
+                self.push(key, value);
+                None
+            }
+        }
+    }
+
+    /// Add a property that is known to be missing.
+    pub(crate) fn push(&mut self, key: String, value: JsValue) {
+        debug_assert!(!self.contains_key(&key));
+        self.index.insert(key.clone(), self.properties.len());
+        self.properties.push((key, value));
+    }
+
+    /// Remove a property, keeping the order of the others.
+    pub fn remove(&mut self, key: &str) -> Option<JsValue> {
+        let index = self.index.remove(key)?;
+        for position in self.index.values_mut() {
+            if *position > index {
+                *position -= 1;
+            }
+        }
+        Some(self.properties.remove(index).1)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.properties.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &JsValue> {
+        self.properties.iter().map(|(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &JsValue)> {
+        self.properties.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut JsValue)> {
+        self.properties.iter_mut().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl fmt::Debug for JsObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Index<&str> for JsObject {
+    type Output = JsValue;
+
+    /// Panics if the property is missing.
+    fn index(&self, key: &str) -> &JsValue {
+        self.get(key).unwrap_or_else(|| panic!("missing property `{}`", key))
+    }
+}
+
+impl IntoIterator for JsObject {
+    type Item = (String, JsValue);
+    type IntoIter = std::vec::IntoIter<(String, JsValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.properties.into_iter()
+    }
+}
+
+impl Extend<(String, JsValue)> for JsObject {
+    fn extend<I: IntoIterator<Item = (String, JsValue)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl FromIterator<(String, JsValue)> for JsObject {
+    fn from_iter<I: IntoIterator<Item = (String, JsValue)>>(iter: I) -> Self {
+        let mut object = JsObject::new();
+        object.extend(iter);
+        object
+    }
+}
+
+impl From<HashMap<String, JsValue>> for JsObject {
+    fn from(map: HashMap<String, JsValue>) -> Self {
+        let mut object = JsObject::with_capacity(map.len());
+        for (key, value) in map {
+            object.push(key, value);
+        }
+        object
+    }
+}
+
+impl From<JsObject> for HashMap<String, JsValue> {
+    fn from(object: JsObject) -> Self {
+        object.properties.into_iter().collect()
+    }
+}
+
+impl From<JsObject> for JsValue {
+    fn from(object: JsObject) -> Self {
+        JsValue::Object(object)
+    }
+}
+
+/// Takes an object, or reads the properties of a `JsValue::Raw` object.
+impl TryFrom<JsValue> for JsObject {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        match value {
+            JsValue::Object(object) => Ok(object),
+            value @ JsValue::Raw(_) => value.get_object(),
+            _ => Err(ValueError::UnexpectedType),
+        }
+    }
+}
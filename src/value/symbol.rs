@@ -0,0 +1,138 @@
+use std::convert::TryFrom;
+
+use super::{JsValue, RawJSValue, ValueError};
+
+/// The names of the well-known symbols, e.g. `iterator` for
+/// `Symbol.iterator`.
+pub(crate) const WELL_KNOWN_SYMBOLS: &[&str] = &[
+    "asyncIterator",
+    "hasInstance",
+    "isConcatSpreadable",
+    "iterator",
+    "match",
+    "matchAll",
+    "replace",
+    "search",
+    "species",
+    "split",
+    "toPrimitive",
+    "toStringTag",
+    "unscopables",
+];
+
+/// How a [JsSymbol] was created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Created with `Symbol()`, equal only to itself.
+    Unique,
+    /// In the global registry, created with `Symbol.for(key)`.
+    Registered,
+    /// A well-known symbol such as `Symbol.iterator`.
+    WellKnown,
+}
+
+/// A Javascript symbol.
+///
+/// Registered and well-known symbols can be created from Rust without a
+/// context, unique symbols with [Context::create_symbol](crate::Context::create_symbol).
+///
+/// Two symbols are equal if they are the same Javascript symbol.
+#[derive(Clone, Debug)]
+pub struct JsSymbol {
+    kind: SymbolKind,
+    description: Option<String>,
+    /// The Javascript symbol, `None` if it is looked up by key or name when
+    /// it is converted.
+    raw: Option<RawJSValue>,
+}
+
+impl JsSymbol {
+    pub(crate) fn from_raw(kind: SymbolKind, description: Option<String>, raw: RawJSValue) -> Self {
+        JsSymbol {
+            kind,
+            description,
+            raw: Some(raw),
+        }
+    }
+
+    /// The symbol of `Symbol.for(key)`.
+    pub fn registered(key: impl Into<String>) -> Self {
+        JsSymbol {
+            kind: SymbolKind::Registered,
+            description: Some(key.into()),
+            raw: None,
+        }
+    }
+
+    /// A well-known symbol by name, e.g. `iterator` for `Symbol.iterator`.
+    ///
+    /// Returns `None` if there is no such symbol.
+    pub fn well_known(name: &str) -> Option<Self> {
+        if !WELL_KNOWN_SYMBOLS.contains(&name) {
+            return None;
+        }
+        Some(JsSymbol {
+            kind: SymbolKind::WellKnown,
+            description: Some(format!("Symbol.{}", name)),
+            raw: None,
+        })
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    /// The description, `None` for `Symbol()`.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The registry key of a registered symbol.
+    pub fn key(&self) -> Option<&str> {
+        match self.kind {
+            SymbolKind::Registered => self.description(),
+            _ => None,
+        }
+    }
+
+    /// The name of a well-known symbol, e.g. `iterator`.
+    pub fn well_known_name(&self) -> Option<&str> {
+        match self.kind {
+            SymbolKind::WellKnown => self.description()?.strip_prefix("Symbol."),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn raw(&self) -> Option<&RawJSValue> {
+        self.raw.as_ref()
+    }
+}
+
+impl PartialEq for JsSymbol {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.kind, other.kind) {
+            (SymbolKind::Unique, SymbolKind::Unique) => match (&self.raw, &other.raw) {
+                (Some(a), Some(b)) => unsafe { (*a.js_value).u.ptr == (*b.js_value).u.ptr },
+                _ => false,
+            },
+            (a, b) => a == b && self.description == other.description,
+        }
+    }
+}
+
+impl From<JsSymbol> for JsValue {
+    fn from(symbol: JsSymbol) -> Self {
+        JsValue::Symbol(symbol)
+    }
+}
+
+impl TryFrom<JsValue> for JsSymbol {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        match value {
+            JsValue::Symbol(symbol) => Ok(symbol),
+            _ => Err(ValueError::UnexpectedType),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::convert::TryFrom;
+
+use libquickjs_sys as q;
+
+use super::{JsValue, ValueError};
+
+/// The contents of a Javascript typed array, by element type.
+///
+/// Converted with a single copy of the underlying bytes. `Float16Array`s
+/// and `DataView`s are passed as `JsValue::Raw`.
+///
+/// A `Vec<u8>` converts to an `Array` of numbers like any other `Vec`,
+/// while a `&[u8]` converts to a `Uint8Array`. Return [Bytes],
+/// `TypedArray::Uint8` or `JsValue::ArrayBuffer` to pass owned bytes as a
+/// buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypedArray {
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Uint8Clamped(Vec<u8>),
+    Int16(Vec<i16>),
+    Uint16(Vec<u16>),
+    Int32(Vec<i32>),
+    Uint32(Vec<u32>),
+    BigInt64(Vec<i64>),
+    BigUint64(Vec<u64>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+}
+
+/// Copy native-endian bytes into a vector of `T`.
+///
+/// Safety: every bit pattern must be a valid `T`.
+unsafe fn from_bytes<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    let len = bytes.len() / std::mem::size_of::<T>();
+    let mut items = Vec::<T>::with_capacity(len);
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), items.as_mut_ptr() as *mut u8, len * std::mem::size_of::<T>());
+    items.set_len(len);
+    items
+}
+
+fn as_bytes<T: Copy>(items: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(items.as_ptr() as *const u8, std::mem::size_of_val(items)) }
+}
+
+macro_rules! typed_array_impl {
+    ( $( $variant:ident, $array_type:ident, $name:expr; )* ) => {
+        impl TypedArray {
+            /// The name of the Javascript constructor, e.g. `Uint8Array`.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $( TypedArray::$variant(_) => $name, )*
+                }
+            }
+
+            /// The number of elements.
+            pub fn len(&self) -> usize {
+                match self {
+                    $( TypedArray::$variant(items) => items.len(), )*
+                }
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// The elements as native-endian bytes.
+            pub fn as_bytes(&self) -> &[u8] {
+                match self {
+                    $( TypedArray::$variant(items) => as_bytes(items), )*
+                }
+            }
+
+            pub(crate) fn array_type(&self) -> q::JSTypedArrayEnum {
+                match self {
+                    $( TypedArray::$variant(_) => q::$array_type, )*
+                }
+            }
+
+            /// Copy the bytes of a typed array of the given type, see
+            /// `JS_GetTypedArrayType`.
+            pub(crate) fn from_bytes(array_type: q::JSTypedArrayEnum, bytes: &[u8]) -> Option<Self> {
+                match array_type {
+                    $( q::$array_type => Some(TypedArray::$variant(unsafe { from_bytes(bytes) })), )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+typed_array_impl! {
+    Int8, JSTypedArrayEnum_JS_TYPED_ARRAY_INT8, "Int8Array";
+    Uint8, JSTypedArrayEnum_JS_TYPED_ARRAY_UINT8, "Uint8Array";
+    Uint8Clamped, JSTypedArrayEnum_JS_TYPED_ARRAY_UINT8C, "Uint8ClampedArray";
+    Int16, JSTypedArrayEnum_JS_TYPED_ARRAY_INT16, "Int16Array";
+    Uint16, JSTypedArrayEnum_JS_TYPED_ARRAY_UINT16, "Uint16Array";
+    Int32, JSTypedArrayEnum_JS_TYPED_ARRAY_INT32, "Int32Array";
+    Uint32, JSTypedArrayEnum_JS_TYPED_ARRAY_UINT32, "Uint32Array";
+    BigInt64, JSTypedArrayEnum_JS_TYPED_ARRAY_BIG_INT64, "BigInt64Array";
+    BigUint64, JSTypedArrayEnum_JS_TYPED_ARRAY_BIG_UINT64, "BigUint64Array";
+    Float32, JSTypedArrayEnum_JS_TYPED_ARRAY_FLOAT32, "Float32Array";
+    Float64, JSTypedArrayEnum_JS_TYPED_ARRAY_FLOAT64, "Float64Array";
+}
+
+impl TypedArray {
+    /// Convert the elements to separate values.
+    pub(crate) fn into_values(self) -> Vec<JsValue> {
+        fn values<T: Into<JsValue>>(items: Vec<T>) -> Vec<JsValue> {
+            items.into_iter().map(Into::into).collect()
+        }
+        match self {
+            TypedArray::Int8(items) => values(items),
+            TypedArray::Uint8(items) | TypedArray::Uint8Clamped(items) => values(items),
+            TypedArray::Int16(items) => values(items),
+            TypedArray::Uint16(items) => values(items),
+            TypedArray::Int32(items) => values(items),
+            TypedArray::Uint32(items) => values(items),
+            #[cfg(feature = "bigint")]
+            TypedArray::BigInt64(items) => values(items),
+            #[cfg(feature = "bigint")]
+            TypedArray::BigUint64(items) => values(items),
+            #[cfg(not(feature = "bigint"))]
+            TypedArray::BigInt64(items) => items.into_iter().map(|x| JsValue::Float(x as f64)).collect(),
+            #[cfg(not(feature = "bigint"))]
+            TypedArray::BigUint64(items) => items.into_iter().map(|x| JsValue::Float(x as f64)).collect(),
+            TypedArray::Float32(items) => items.into_iter().map(|x| JsValue::Float(x.into())).collect(),
+            TypedArray::Float64(items) => values(items),
+        }
+    }
+}
+
+impl From<TypedArray> for JsValue {
+    fn from(array: TypedArray) -> Self {
+        JsValue::TypedArray(array)
+    }
+}
+
+impl TryFrom<JsValue> for TypedArray {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        match value {
+            JsValue::TypedArray(array) => Ok(array),
+            _ => Err(ValueError::UnexpectedType),
+        }
+    }
+}
+
+/// Owned bytes passed to Javascript as a `Uint8Array`.
+///
+/// Taken from an ArrayBuffer, a typed array or an array of bytes, like
+/// `Vec<u8>`.
+///
+/// ```rust
+/// use deft_quick_js::{Bytes, Context};
+/// let context = Context::new().unwrap();
+///
+/// context.add_callback("reversed", |bytes: Bytes| {
+///     Bytes(bytes.0.into_iter().rev().collect())
+/// }).unwrap();
+/// let value = context.eval_as::<String>("reversed(new Uint8Array([1, 2, 3])).join()", "main.js").unwrap();
+/// assert_eq!(value, "3,2,1");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for JsValue {
+    fn from(bytes: Bytes) -> Self {
+        JsValue::TypedArray(TypedArray::Uint8(bytes.0))
+    }
+}
+
+impl TryFrom<JsValue> for Bytes {
+    type Error = ValueError;
+
+    fn try_from(value: JsValue) -> Result<Self, Self::Error> {
+        Vec::<u8>::try_from(value).map(Bytes)
+    }
+}